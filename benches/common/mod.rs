@@ -0,0 +1,29 @@
+// Helpers shared by the benches, which hand the Lox programs in this directory
+// to the `bench` command of the interpreter to be timed.
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+pub const RUNS: u32 = 5;
+
+pub fn script(workload: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("benches")
+        .join(workload)
+        .with_extension("lox")
+}
+
+// Runs `interpreter [args] bench` on `workloads`, which prints the mean and
+// standard deviation of each of them.
+pub fn bench(interpreter: &Path, args: &[&str], workloads: &[&str]) {
+    let status = Command::new(interpreter)
+        .args(args)
+        .arg("bench")
+        .arg("-n")
+        .arg(RUNS.to_string())
+        .args(workloads.iter().map(|workload| script(workload)))
+        .status()
+        .expect("failed to run interpreter");
+    assert!(status.success(), "a workload failed");
+}
@@ -0,0 +1,13 @@
+// Times the dispatch loop of the VM on a call heavy, a loop heavy and a string
+// heavy program. Only one dispatch loop is built in, so this prints absolute
+// times: to see what a change to the loop gains, run it before and after.
+mod common;
+
+use std::path::Path;
+
+const WORKLOADS: [&str; 3] = ["fib", "loop", "string"];
+
+fn main() {
+    let interpreter = Path::new(env!("CARGO_BIN_EXE_rlox-bytecode"));
+    common::bench(interpreter, &[], &WORKLOADS);
+}
@@ -0,0 +1,16 @@
+// Times Lox programs compiled with `-O0` against `-O1`, which fuses common
+// instruction sequences with the peephole pass.
+mod common;
+
+use std::path::Path;
+
+const WORKLOADS: [&str; 2] = ["loop", "comparison"];
+
+fn main() {
+    let interpreter = Path::new(env!("CARGO_BIN_EXE_rlox-bytecode"));
+    for opt_flag in ["-O0", "-O1"] {
+        println!("{}", opt_flag);
+        common::bench(interpreter, &[opt_flag], &WORKLOADS);
+        println!();
+    }
+}
@@ -0,0 +1,39 @@
+// Times Lox programs on interpreters built with the tagged union and the
+// NaN-boxed representation of values (the `nan_boxing` feature).
+mod common;
+
+use std::{path::PathBuf, process::Command};
+
+const WORKLOADS: [&str; 2] = ["loop", "comparison"];
+
+fn build_interpreter(features: &[&str]) -> PathBuf {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = manifest_dir
+        .join("target")
+        .join(format!("bench-value-repr-{}", features.join("-")));
+    let status = Command::new(env!("CARGO"))
+        .current_dir(&manifest_dir)
+        .args([
+            "build",
+            "--release",
+            "--bin",
+            "rlox-bytecode",
+            "--target-dir",
+        ])
+        .arg(&target_dir)
+        .arg(format!("--features={}", features.join(",")))
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "building the interpreter failed");
+    target_dir.join("release").join("rlox-bytecode")
+}
+
+fn main() {
+    let tagged_union = build_interpreter(&[]);
+    let nan_boxed = build_interpreter(&["nan_boxing"]);
+    for (name, interpreter) in [("tagged union", tagged_union), ("nan boxing", nan_boxed)] {
+        println!("{}", name);
+        common::bench(&interpreter, &[], &WORKLOADS);
+        println!();
+    }
+}
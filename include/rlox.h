@@ -0,0 +1,79 @@
+/*
+ * C API for embedding the rlox-bytecode interpreter. Link against the cdylib
+ * built by `cargo build` (librlox_bytecode.so, .dylib or .dll).
+ *
+ * Value handles (LoxValue *) are owned by whoever receives them and have to be
+ * freed with lox_value_free, except for the ones passed to and returned from a
+ * native callback. Strings are copied into their handles; handles to other
+ * objects (functions, instances) may dangle once Lox code can't reach the
+ * object anymore.
+ */
+#ifndef RLOX_H
+#define RLOX_H
+
+#include <stdbool.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct LoxVM LoxVM;
+typedef struct LoxValue LoxValue;
+
+typedef enum {
+    LOX_OK,
+    LOX_COMPILE_ERROR,
+    LOX_RUNTIME_ERROR,
+} LoxResult;
+
+typedef enum {
+    LOX_TYPE_NIL,
+    LOX_TYPE_BOOL,
+    LOX_TYPE_NUMBER,
+    LOX_TYPE_STRING,
+    LOX_TYPE_OBJECT,
+    LOX_TYPE_ERROR,
+} LoxType;
+
+/*
+ * Called with the user_data it was registered with and the arguments, which
+ * are freed once it returns. Returns a new handle that the VM takes ownership
+ * of, NULL for nil, or one made by lox_error to raise a runtime error.
+ */
+typedef LoxValue *(*LoxNativeFn)(void *user_data, int argc, const LoxValue *const *args);
+
+LoxVM *lox_vm_new(void);
+void lox_vm_free(LoxVM *vm);
+
+/* Runs source as top level code. Globals stay defined for later calls. */
+LoxResult lox_interpret(LoxVM *vm, const char *source);
+/* Message of the error from the last lox_interpret, NULL if it succeeded. */
+const char *lox_last_error(const LoxVM *vm);
+
+/* NULL if there is no global called name. */
+LoxValue *lox_get_global(const LoxVM *vm, const char *name);
+/* Returns false if value is an error, which can't be stored. */
+bool lox_set_global(LoxVM *vm, const char *name, const LoxValue *value);
+void lox_define_native(LoxVM *vm, const char *name, int arity, LoxNativeFn callback,
+                       void *user_data);
+
+LoxValue *lox_nil(void);
+LoxValue *lox_bool(bool boolean);
+LoxValue *lox_number(double number);
+LoxValue *lox_string(const char *string);
+LoxValue *lox_error(const char *message);
+
+LoxType lox_value_type(const LoxValue *value);
+/* false for anything but booleans */
+bool lox_value_as_bool(const LoxValue *value);
+/* 0 for anything but numbers */
+double lox_value_as_number(const LoxValue *value);
+/* NULL for anything but strings, owned by the handle */
+const char *lox_value_as_string(const LoxValue *value);
+void lox_value_free(LoxValue *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,109 @@
+// Runs Lox programs a number of times in fresh interpreter processes and
+// reports how long they took, for `rlox-bytecode bench`.
+use std::{
+    env,
+    path::Path,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use rlox_bytecode::OptLevel;
+
+const DEFAULT_RUNS: usize = 10;
+
+pub fn usage(prog_name: &str) -> String {
+    format!("Usage: {} [-O0|-O1] bench [-n runs] script...", prog_name)
+}
+
+// returns whether every script ran successfully every time
+pub fn run(prog_name: &str, args: &[String], opt_level: OptLevel) -> bool {
+    let (runs, scripts) = match args {
+        [flag, runs, scripts @ ..] if flag == "-n" => match runs.parse() {
+            Ok(runs) if runs > 0 => (runs, scripts),
+            _ => {
+                println!("{}", usage(prog_name));
+                return false;
+            }
+        },
+        scripts => (DEFAULT_RUNS, scripts),
+    };
+    if scripts.is_empty() {
+        println!("{}", usage(prog_name));
+        return false;
+    }
+    let interpreter = env::current_exe().expect("can't find the interpreter");
+    let opt_flag = match opt_level {
+        OptLevel::O0 => "-O0",
+        OptLevel::O1 => "-O1",
+    };
+
+    println!(
+        "{:20} {:>6} {:>12} {:>12}",
+        "workload", "runs", "mean", "stddev"
+    );
+    let mut all_passed = true;
+    for script in scripts {
+        let workload = Path::new(script)
+            .file_stem()
+            .map_or(script.as_str(), |stem| stem.to_str().unwrap_or(script));
+        match time_runs(&interpreter, opt_flag, script, runs) {
+            Ok(times) => {
+                let (mean, stddev) = mean_and_stddev(&times);
+                println!(
+                    "{:20} {:>6} {:>12.2?} {:>12.2?}",
+                    workload, runs, mean, stddev
+                );
+            }
+            Err(reason) => {
+                all_passed = false;
+                println!("{:20} {:>6} {:>12}   {}", workload, "-", "failed", reason);
+            }
+        }
+    }
+    all_passed
+}
+
+fn time_runs(
+    interpreter: &Path,
+    opt_flag: &str,
+    script: &str,
+    runs: usize,
+) -> Result<Vec<Duration>, String> {
+    (0..runs)
+        .map(|_| {
+            let start = Instant::now();
+            let output = Command::new(interpreter)
+                .arg(opt_flag)
+                .arg(script)
+                .stdin(Stdio::null())
+                .output()
+                .map_err(|error| error.to_string())?;
+            let elapsed = start.elapsed();
+            if output.status.success() {
+                Ok(elapsed)
+            } else {
+                // skip the line saying where a panic happened to get to its message
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let message = stderr
+                    .lines()
+                    .find(|line| !line.is_empty() && !line.starts_with("thread "))
+                    .unwrap_or("");
+                Err(format!("{}: {}", output.status, message))
+            }
+        })
+        .collect()
+}
+
+fn mean_and_stddev(times: &[Duration]) -> (Duration, Duration) {
+    let secs = times.iter().map(Duration::as_secs_f64).collect::<Vec<_>>();
+    let mean = secs.iter().sum::<f64>() / secs.len() as f64;
+    // sample standard deviation, there is no spread with a single run
+    let variance = match secs.len() {
+        1 => 0.0,
+        n => secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1) as f64,
+    };
+    (
+        Duration::from_secs_f64(mean),
+        Duration::from_secs_f64(variance.sqrt()),
+    )
+}
@@ -0,0 +1,358 @@
+// Methods of the types built into Lox, like `push` on lists. They are looked
+// up by name when called, so the objects don't carry any method tables.
+use std::borrow::Cow;
+
+use crate::{
+    byte_string::ByteSlice,
+    error::RuntimeError,
+    host::NativeContext,
+    object::{Map, MapKey, Obj, ObjPtr},
+    value::Value,
+    vm::VM,
+};
+
+// Called with the object the method was looked up on and the arguments, whose
+// number the VM already checked against the arity.
+pub type BuiltinMethod = fn(&mut NativeContext, ObjPtr, &[Value]) -> Result<Value, RuntimeError>;
+
+// Called with the object the method was looked up on and a function to call
+// back, which takes the whole VM to run.
+pub type CallbackMethod = fn(&mut VM, ObjPtr, Value) -> Result<Value, RuntimeError>;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Method {
+    Native(BuiltinMethod),
+    Callback(CallbackMethod),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Builtin {
+    pub arity: usize,
+    pub method: Method,
+}
+
+pub fn find_method(receiver: ObjPtr, name: &ByteSlice) -> Result<Builtin, RuntimeError> {
+    // methods calling back take the function as their only argument
+    let callback: Option<CallbackMethod> = match (receiver.as_obj(), name) {
+        (Obj::List(_), b"map") => Some(VM::list_map),
+        (Obj::List(_), b"filter") => Some(VM::list_filter),
+        (Obj::List(_), b"sort") => Some(VM::list_sort),
+        _ => None,
+    };
+    if let Some(callback) = callback {
+        return Ok(Builtin {
+            arity: 1,
+            method: Method::Callback(callback),
+        });
+    }
+    let (arity, method): (usize, BuiltinMethod) = match (receiver.as_obj(), name) {
+        (Obj::List(_), b"push") => (1, list_push),
+        (Obj::List(_), b"pop") => (0, list_pop),
+        (Obj::List(_), b"len") => (0, list_len),
+        (Obj::List(_), b"insert") => (2, list_insert),
+        (Obj::List(_), b"remove") => (1, list_remove),
+        (Obj::Map(_), b"keys") => (0, map_keys),
+        (Obj::Map(_), b"values") => (0, map_values),
+        (Obj::Map(_), b"has") => (1, map_has),
+        (Obj::Map(_), b"remove") => (1, map_remove),
+        (Obj::Map(_), b"len") => (0, map_len),
+        (Obj::String(_), b"len") => (0, string_len),
+        (Obj::String(_), b"chars") => (0, string_chars),
+        (Obj::String(_), b"bytes") => (0, string_bytes),
+        (Obj::String(_), b"substring") => (2, string_substring),
+        (Obj::String(_), b"indexOf") => (1, string_index_of),
+        (Obj::String(_), b"split") => (1, string_split),
+        (Obj::String(_), b"upper") => (0, string_upper),
+        (Obj::String(_), b"lower") => (0, string_lower),
+        (Obj::String(_), b"trim") => (0, string_trim),
+        (Obj::List(_) | Obj::Map(_) | Obj::String(_), _) => {
+            return Err(RuntimeError::UndefinedProperty(
+                String::from_utf8_lossy(name).into(),
+            ))
+        }
+        _ => return Err(RuntimeError::OnlyInstancesHaveProperties),
+    };
+    Ok(Builtin {
+        arity,
+        method: Method::Native(method),
+    })
+}
+
+// Steps through the elements of a list, a map, a string or a range for a
+// `for-in` loop. `iterator` is nil before the first element and whatever the
+// previous call returned after that. Returns the next iterator along with the
+// element, or `None` once there are no more elements. Maps go through their
+// keys and strings through their characters.
+pub fn iterate(
+    ctx: &mut NativeContext,
+    sequence: ObjPtr,
+    iterator: Value,
+) -> Result<Option<(Value, Value)>, RuntimeError> {
+    let position = iterator.as_number();
+    let next = match sequence.as_obj() {
+        Obj::List(elements) => {
+            let idx = position.map_or(0, |idx| idx as usize + 1);
+            (elements.get(idx)).map(|element| (Value::Number(idx as f64), *element))
+        }
+        Obj::Map(map) => {
+            let idx = position.map_or(0, |idx| idx as usize + 1);
+            (map.entries().get(idx)).map(|(key, _)| (Value::Number(idx as f64), *key))
+        }
+        Obj::Range(range) => {
+            let n = position.map_or(range.start, |n| n + 1f64);
+            (n < range.end).then_some((Value::Number(n), Value::Number(n)))
+        }
+        Obj::String(string) => {
+            // the iterator is the byte offset of the current character
+            let offset = position.map_or(0, |offset| {
+                let offset = offset as usize;
+                offset + char_width(string[offset])
+            });
+            if offset < string.len() {
+                let end = (offset + char_width(string[offset])).min(string.len());
+                let character = String::from_utf8_lossy(&string[offset..end]);
+                Some((Value::Number(offset as f64), ctx.string(&character)))
+            } else {
+                None
+            }
+        }
+        _ => return Err(RuntimeError::NotIterable),
+    };
+    Ok(next)
+}
+
+// number of bytes in the UTF-8 encoded character starting with `byte`
+fn char_width(byte: u8) -> usize {
+    match byte {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    }
+}
+
+// Position of the element at `index` in a list of `len` elements. Indices
+// have to be whole numbers.
+pub fn list_index(index: Value, len: usize) -> Result<usize, RuntimeError> {
+    let index = match index.as_number() {
+        Some(index) if index.fract() == 0f64 => index,
+        _ => return Err(RuntimeError::IndexMustBeInteger),
+    };
+    if index < 0f64 || index >= len as f64 {
+        return Err(RuntimeError::IndexOutOfBounds { index, len });
+    }
+    Ok(index as usize)
+}
+
+pub fn list<'a>(receiver: ObjPtr) -> &'a mut Vec<Value> {
+    match receiver.as_obj_mut() {
+        Obj::List(elements) => elements,
+        _ => unreachable!("list methods are only looked up on lists"),
+    }
+}
+
+fn list_push(
+    _: &mut NativeContext,
+    receiver: ObjPtr,
+    args: &[Value],
+) -> Result<Value, RuntimeError> {
+    list(receiver).push(args[0]);
+    Ok(Value::Nil)
+}
+
+fn list_pop(_: &mut NativeContext, receiver: ObjPtr, _: &[Value]) -> Result<Value, RuntimeError> {
+    list(receiver).pop().ok_or(RuntimeError::PopFromEmptyList)
+}
+
+fn list_len(_: &mut NativeContext, receiver: ObjPtr, _: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Number(list(receiver).len() as f64))
+}
+
+fn list_insert(
+    _: &mut NativeContext,
+    receiver: ObjPtr,
+    args: &[Value],
+) -> Result<Value, RuntimeError> {
+    let elements = list(receiver);
+    // inserting right after the last element appends
+    let idx = match args[0].as_number() {
+        Some(index) if index == elements.len() as f64 => elements.len(),
+        _ => list_index(args[0], elements.len())?,
+    };
+    elements.insert(idx, args[1]);
+    Ok(Value::Nil)
+}
+
+fn list_remove(
+    _: &mut NativeContext,
+    receiver: ObjPtr,
+    args: &[Value],
+) -> Result<Value, RuntimeError> {
+    let elements = list(receiver);
+    let idx = list_index(args[0], elements.len())?;
+    Ok(elements.remove(idx))
+}
+
+pub fn map_key(key: Value) -> Result<MapKey, RuntimeError> {
+    MapKey::new(key).ok_or(RuntimeError::InvalidKey)
+}
+
+pub fn undefined_key(key: Value) -> RuntimeError {
+    RuntimeError::UndefinedKey(key.to_string())
+}
+
+fn map<'a>(receiver: ObjPtr) -> &'a mut Map {
+    match receiver.as_obj_mut() {
+        Obj::Map(map) => map,
+        _ => unreachable!("map methods are only looked up on maps"),
+    }
+}
+
+fn map_keys(ctx: &mut NativeContext, receiver: ObjPtr, _: &[Value]) -> Result<Value, RuntimeError> {
+    let keys = map(receiver).entries().iter().map(|(key, _)| *key);
+    Ok(ctx.list(keys.collect()))
+}
+
+fn map_values(
+    ctx: &mut NativeContext,
+    receiver: ObjPtr,
+    _: &[Value],
+) -> Result<Value, RuntimeError> {
+    let values = map(receiver).entries().iter().map(|(_, value)| *value);
+    Ok(ctx.list(values.collect()))
+}
+
+fn map_has(_: &mut NativeContext, receiver: ObjPtr, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = map_key(args[0])?;
+    Ok(Value::Boolean(map(receiver).get(key).is_some()))
+}
+
+fn map_remove(
+    _: &mut NativeContext,
+    receiver: ObjPtr,
+    args: &[Value],
+) -> Result<Value, RuntimeError> {
+    let key = map_key(args[0])?;
+    map(receiver)
+        .remove(key)
+        .ok_or_else(|| undefined_key(args[0]))
+}
+
+fn map_len(_: &mut NativeContext, receiver: ObjPtr, _: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Number(map(receiver).entries().len() as f64))
+}
+
+// String methods count in characters rather than bytes. Strings made by Lox
+// are always valid UTF-8, only the host could create other ones.
+fn string<'a>(value: ObjPtr) -> Cow<'a, str> {
+    match value.as_obj() {
+        Obj::String(string) => String::from_utf8_lossy(string),
+        _ => unreachable!("string methods are only looked up on strings"),
+    }
+}
+
+fn string_argument<'a>(value: Value) -> Result<Cow<'a, str>, RuntimeError> {
+    match value.as_obj_ptr() {
+        Some(ptr) if ptr.is_string() => Ok(string(ptr)),
+        _ => Err(RuntimeError::ArgumentMustBeString),
+    }
+}
+
+fn string_len(_: &mut NativeContext, receiver: ObjPtr, _: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Number(string(receiver).chars().count() as f64))
+}
+
+fn string_chars(
+    ctx: &mut NativeContext,
+    receiver: ObjPtr,
+    _: &[Value],
+) -> Result<Value, RuntimeError> {
+    let chars = (string(receiver).chars())
+        .map(|c| ctx.string(c.encode_utf8(&mut [0; 4])))
+        .collect();
+    Ok(ctx.list(chars))
+}
+
+fn string_bytes(
+    ctx: &mut NativeContext,
+    receiver: ObjPtr,
+    _: &[Value],
+) -> Result<Value, RuntimeError> {
+    let bytes = receiver.as_string().iter();
+    Ok(ctx.list(bytes.map(|&byte| Value::Number(byte as f64)).collect()))
+}
+
+// the characters from `start` up to but not including `end`
+fn string_substring(
+    ctx: &mut NativeContext,
+    receiver: ObjPtr,
+    args: &[Value],
+) -> Result<Value, RuntimeError> {
+    let (start, end) = match (args[0].as_number(), args[1].as_number()) {
+        (Some(start), Some(end)) if start.fract() == 0f64 && end.fract() == 0f64 => (start, end),
+        _ => return Err(RuntimeError::IndexMustBeInteger),
+    };
+    let string = string(receiver);
+    let len = string.chars().count();
+    if start < 0f64 || start > end || end > len as f64 {
+        return Err(RuntimeError::SubstringOutOfBounds { start, end, len });
+    }
+    let substring: String = (string.chars())
+        .skip(start as usize)
+        .take((end - start) as usize)
+        .collect();
+    Ok(ctx.string(&substring))
+}
+
+// the position in characters of the first occurrence of the argument, or -1
+fn string_index_of(
+    _: &mut NativeContext,
+    receiver: ObjPtr,
+    args: &[Value],
+) -> Result<Value, RuntimeError> {
+    let needle = string_argument(args[0])?;
+    let string = string(receiver);
+    let idx =
+        (string.find(&*needle)).map_or(-1f64, |offset| string[..offset].chars().count() as f64);
+    Ok(Value::Number(idx))
+}
+
+// an empty separator splits the string into its characters
+fn string_split(
+    ctx: &mut NativeContext,
+    receiver: ObjPtr,
+    args: &[Value],
+) -> Result<Value, RuntimeError> {
+    let separator = string_argument(args[0])?;
+    if separator.is_empty() {
+        return string_chars(ctx, receiver, args);
+    }
+    let parts = (string(receiver).split(&*separator))
+        .map(|part| ctx.string(part))
+        .collect();
+    Ok(ctx.list(parts))
+}
+
+fn string_upper(
+    ctx: &mut NativeContext,
+    receiver: ObjPtr,
+    _: &[Value],
+) -> Result<Value, RuntimeError> {
+    Ok(ctx.string(&string(receiver).to_uppercase()))
+}
+
+fn string_lower(
+    ctx: &mut NativeContext,
+    receiver: ObjPtr,
+    _: &[Value],
+) -> Result<Value, RuntimeError> {
+    Ok(ctx.string(&string(receiver).to_lowercase()))
+}
+
+fn string_trim(
+    ctx: &mut NativeContext,
+    receiver: ObjPtr,
+    _: &[Value],
+) -> Result<Value, RuntimeError> {
+    Ok(ctx.string(string(receiver).trim()))
+}
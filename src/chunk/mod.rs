@@ -1,14 +1,18 @@
+mod peephole;
 mod test;
+mod verify;
 
-use std::u8;
+use std::io::{self, Write};
 
 use crate::opcode::OpCode;
 use crate::value::Value;
 
+pub use self::verify::VerifyError;
+
 type LineCount = usize;
 type LineNumber = usize;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Chunk {
     pub lines: Vec<(LineCount, LineNumber)>,
     pub code: Vec<u8>,
@@ -28,6 +32,11 @@ impl Chunk {
         self.lines.push((1, line_number))
     }
 
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
     pub fn write_constant(&mut self, value: Value, line_number: usize) {
         if self.constants.len() < u8::MAX as usize + 1 {
             self.write(OpCode::Constant as u8, line_number);
@@ -43,7 +52,42 @@ impl Chunk {
         }
     }
 
-    fn get_line(&self, offset: usize) -> usize {
+    // drops everything from `len` onwards along with its line information
+    pub fn truncate(&mut self, len: usize) {
+        self.code.truncate(len);
+        let mut remaining = len;
+        let mut runs = 0;
+        for (line_count, _) in self.lines.iter_mut() {
+            if remaining == 0 {
+                break;
+            }
+            *line_count = (*line_count).min(remaining);
+            remaining -= *line_count;
+            runs += 1;
+        }
+        self.lines.truncate(runs);
+    }
+
+    // offset of the instruction that the jump at `offset` lands on
+    fn jump_target(&self, offset: usize) -> Option<usize> {
+        let next = offset + OpCode::from(self.code[offset]).size();
+        let jump = || self.jump_operand(offset);
+        match self.code[offset].into() {
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::ForIter | OpCode::SetupTry => {
+                Some(next + jump())
+            }
+            OpCode::Loop => Some(next - jump()),
+            _ => None,
+        }
+    }
+
+    // the distance a jump at `offset` goes, which is always its last operand
+    fn jump_operand(&self, offset: usize) -> usize {
+        let next = offset + OpCode::from(self.code[offset]).size();
+        u16::from_be_bytes([self.code[next - 2], self.code[next - 1]]) as usize
+    }
+
+    pub fn get_line(&self, offset: usize) -> usize {
         let mut cumulative_position = 0;
         for (line_count, line_number) in self.lines.iter() {
             cumulative_position += line_count;
@@ -54,30 +98,36 @@ impl Chunk {
         0
     }
 
-    pub fn disassemble(&self, name: &str) {
-        println!("== {name} ==");
+    pub fn disassemble(&self, name: &str, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "== {name} ==")?;
         let mut offset = 0;
         while offset < self.code.len() {
-            offset = self.disassemble_instruction(offset).unwrap();
+            offset = self.disassemble_instruction(offset, out)?.unwrap();
         }
+        Ok(())
     }
 
-    pub fn disassemble_instruction(&self, offset: usize) -> Option<usize> {
-        print!("{:04} ", offset);
+    pub fn disassemble_instruction(
+        &self,
+        offset: usize,
+        out: &mut dyn Write,
+    ) -> io::Result<Option<usize>> {
+        write!(out, "{:04} ", offset)?;
         if offset > 0 && self.get_line(offset) == self.get_line(offset - 1) {
-            print!("   | ");
+            write!(out, "   | ")?;
         } else {
-            print!("{:4} ", self.get_line(offset));
+            write!(out, "{:4} ", self.get_line(offset))?;
         }
-        if let Some(instruction) = self.code.get(offset) {
+        Ok(if let Some(instruction) = self.code.get(offset) {
             let instruction: OpCode = (*instruction).into();
             match instruction {
-                OpCode::Constant => {
+                OpCode::Constant | OpCode::Closure => {
                     let constant_index = self.code[offset + 1];
-                    println!(
+                    writeln!(
+                        out,
                         "{:?} {constant_index} {:?}",
                         instruction, self.constants[constant_index as usize]
-                    );
+                    )?;
                     Some(offset + 2)
                 }
                 OpCode::ConstantLong => {
@@ -85,19 +135,74 @@ impl Chunk {
                     let m = self.code[offset + 2];
                     let l = self.code[offset + 3];
                     let constant_index = u32::from_be_bytes([0, h, m, l]);
-                    println!(
+                    writeln!(
+                        out,
                         "{:?} {constant_index} {:?}",
                         instruction, self.constants[constant_index as usize]
-                    );
+                    )?;
+                    Some(offset + 4)
+                }
+                OpCode::DefineGlobal
+                | OpCode::GetGlobal
+                | OpCode::SetGlobal
+                | OpCode::AddConstant
+                | OpCode::GetProperty
+                | OpCode::SetProperty => {
+                    let constant_index = self.code[offset + 1];
+                    writeln!(
+                        out,
+                        "{:?} {constant_index} {}",
+                        instruction, self.constants[constant_index as usize]
+                    )?;
+                    Some(offset + 2)
+                }
+                OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                    let slot = self.code[offset + 1];
+                    writeln!(out, "{:?} {slot}", instruction)?;
+                    Some(offset + 2)
+                }
+                OpCode::Call
+                | OpCode::BuildList
+                | OpCode::BuildMap
+                | OpCode::Dup
+                | OpCode::Bury => {
+                    let count = self.code[offset + 1];
+                    writeln!(out, "{:?} {count}", instruction)?;
+                    Some(offset + 2)
+                }
+                OpCode::Invoke => {
+                    let (constant_index, arg_count) =
+                        (self.code[offset + 1], self.code[offset + 2]);
+                    writeln!(
+                        out,
+                        "{:?} {constant_index} {} {arg_count}",
+                        instruction, self.constants[constant_index as usize]
+                    )?;
+                    Some(offset + 3)
+                }
+                OpCode::GetLocalGetLocal => {
+                    let (slot1, slot2) = (self.code[offset + 1], self.code[offset + 2]);
+                    writeln!(out, "{:?} {slot1} {slot2}", instruction)?;
+                    Some(offset + 3)
+                }
+                OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop | OpCode::SetupTry => {
+                    let target = self.jump_target(offset).unwrap();
+                    writeln!(out, "{:?} {offset} -> {target}", instruction)?;
+                    Some(offset + 3)
+                }
+                OpCode::ForIter => {
+                    let slot = self.code[offset + 1];
+                    let target = self.jump_target(offset).unwrap();
+                    writeln!(out, "{:?} {slot} {offset} -> {target}", instruction)?;
                     Some(offset + 4)
                 }
                 simple_instruction => {
-                    println!("{:?}", simple_instruction);
+                    writeln!(out, "{:?}", simple_instruction)?;
                     Some(offset + 1)
                 }
             }
         } else {
             None
-        }
+        })
     }
 }
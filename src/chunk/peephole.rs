@@ -0,0 +1,101 @@
+use std::collections::{HashMap, HashSet};
+use std::iter;
+
+use crate::chunk::Chunk;
+use crate::opcode::OpCode;
+
+impl Chunk {
+    // bytes of the instruction replacing the two instructions at `first` and
+    // `second`, if there is one
+    fn fuse(&self, first: usize, second: usize) -> Option<Vec<u8>> {
+        let fused = match (self.code[first].into(), self.code[second].into()) {
+            (OpCode::Less, OpCode::Not) => vec![OpCode::GreaterEqual as u8],
+            (OpCode::Greater, OpCode::Not) => vec![OpCode::LessEqual as u8],
+            (OpCode::Equal, OpCode::Not) => vec![OpCode::NotEqual as u8],
+            (OpCode::Constant, OpCode::Add) => {
+                vec![OpCode::AddConstant as u8, self.code[first + 1]]
+            }
+            (OpCode::GetLocal, OpCode::GetLocal) => vec![
+                OpCode::GetLocalGetLocal as u8,
+                self.code[first + 1],
+                self.code[second + 1],
+            ],
+            _ => return None,
+        };
+        Some(fused)
+    }
+
+    // rewrites common sequences of instructions in `code[start..]` into fused
+    // instructions while keeping jump offsets and line information consistent
+    pub fn peephole(&mut self, start: usize) {
+        let mut instructions = Vec::new();
+        let mut jump_targets = HashSet::new();
+        let mut offset = start;
+        while offset < self.code.len() {
+            instructions.push(offset);
+            jump_targets.extend(self.jump_target(offset));
+            offset += OpCode::from(self.code[offset]).size();
+        }
+
+        let lines = self
+            .lines
+            .iter()
+            .flat_map(|(line_count, line_number)| iter::repeat_n(*line_number, *line_count))
+            .skip(start)
+            .collect::<Vec<_>>();
+        let mut code = Vec::new();
+        let mut new_offsets = HashMap::new();
+        let mut jumps = Vec::new();
+        let mut idx = 0;
+        while idx < instructions.len() {
+            let offset = instructions[idx];
+            new_offsets.insert(offset, start + code.len());
+            // an instruction that is jumped to has to stay where it is
+            let fused = instructions
+                .get(idx + 1)
+                .filter(|second| !jump_targets.contains(second))
+                .and_then(|second| Some((*second, self.fuse(offset, *second)?)));
+            match fused {
+                Some((second, fused)) => {
+                    // the second instruction is the one that can fail, like
+                    // the `Add` of a constant, which has no line of its own
+                    let line = lines[second - start];
+                    code.extend(fused.into_iter().map(|byte| (byte, line)));
+                    idx += 2;
+                }
+                None => {
+                    if let Some(target) = self.jump_target(offset) {
+                        jumps.push((code.len(), target));
+                    }
+                    let size = OpCode::from(self.code[offset]).size();
+                    for byte_offset in offset..offset + size {
+                        code.push((self.code[byte_offset], lines[byte_offset - start]));
+                    }
+                    idx += 1;
+                }
+            }
+        }
+        new_offsets.insert(self.code.len(), start + code.len());
+
+        // instructions only ever shrink, so the new jumps still fit in a u16
+        for (jump_idx, old_target) in jumps {
+            let instruction = OpCode::from(code[jump_idx].0);
+            // the jump is the last operand and counts from the next instruction
+            let size = instruction.size();
+            let next = start + jump_idx + size;
+            let target = new_offsets[&old_target];
+            let jump = match instruction {
+                OpCode::Loop => next - target,
+                _ => target - next,
+            };
+            let [h, l] = (jump as u16).to_be_bytes();
+            code[jump_idx + size - 2].0 = h;
+            code[jump_idx + size - 1].0 = l;
+        }
+
+        self.truncate(start);
+        for (byte, line) in code {
+            self.write(byte, line);
+        }
+    }
+}
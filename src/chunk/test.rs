@@ -1,6 +1,9 @@
 #[cfg(test)]
 mod tests {
-    use crate::{chunk::Chunk, opcode::OpCode};
+    use crate::{
+        chunk::{Chunk, VerifyError},
+        opcode::OpCode,
+    };
 
     #[test]
     fn write_op_constant() {
@@ -57,6 +60,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::identity_op)]
     fn many_constants() {
         let mut chunk = Chunk::default();
         for i in 0..(255 * 255) {
@@ -82,4 +86,396 @@ mod tests {
         let l = chunk.code[last_idx - 0];
         assert_eq!(255 * 255 - 1, u32::from_be_bytes([0, h, m, l]));
     }
+
+    #[test]
+    fn peephole_fuses_instructions() {
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::GetLocal as u8, 1);
+        chunk.write(0, 1);
+        chunk.write(OpCode::GetLocal as u8, 1);
+        chunk.write(1, 1);
+        chunk.write(OpCode::Less as u8, 2);
+        chunk.write(OpCode::Not as u8, 2);
+        chunk.write_constant(1.2.into(), 3);
+        chunk.write(OpCode::Add as u8, 3);
+        chunk.peephole(0);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::GetLocalGetLocal as u8,
+                0,
+                1,
+                OpCode::GreaterEqual as u8,
+                OpCode::AddConstant as u8,
+                0,
+            ]
+        );
+        assert_eq!(chunk.lines, vec![(3, 1), (1, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn peephole_gives_fused_instructions_the_second_line() {
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::GetLocal as u8, 1);
+        chunk.write(0, 1);
+        // constants are emitted without a line
+        chunk.write_constant(1.0.into(), 0);
+        chunk.write(OpCode::Add as u8, 2);
+        chunk.write(OpCode::Return as u8, 2);
+        chunk.peephole(0);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::GetLocal as u8,
+                0,
+                OpCode::AddConstant as u8,
+                0,
+                OpCode::Return as u8
+            ]
+        );
+        assert_eq!(chunk.lines, vec![(2, 1), (3, 2)]);
+    }
+
+    #[test]
+    fn peephole_keeps_jumps_consistent() {
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Nil as u8, 1);
+        chunk.write(OpCode::Less as u8, 1);
+        chunk.write(OpCode::Not as u8, 1);
+        chunk.write(OpCode::JumpIfFalse as u8, 2);
+        chunk.write(0, 2);
+        chunk.write(2, 2);
+        chunk.write(OpCode::Nil as u8, 3);
+        chunk.write(OpCode::Pop as u8, 3);
+        chunk.write(OpCode::Equal as u8, 4);
+        chunk.write(OpCode::Not as u8, 4);
+        chunk.write(OpCode::Loop as u8, 5);
+        chunk.write(0, 5);
+        chunk.write(13, 5);
+        chunk.peephole(0);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::Nil as u8,
+                OpCode::GreaterEqual as u8,
+                OpCode::JumpIfFalse as u8,
+                0,
+                2,
+                OpCode::Nil as u8,
+                OpCode::Pop as u8,
+                OpCode::NotEqual as u8,
+                OpCode::Loop as u8,
+                0,
+                11,
+            ]
+        );
+        assert_eq!(chunk.jump_target(2), Some(7));
+        assert_eq!(chunk.jump_target(8), Some(0));
+    }
+
+    #[test]
+    fn peephole_keeps_for_iter_jumps_consistent() {
+        let mut chunk = Chunk::default();
+        for byte in [
+            OpCode::Nil as u8,
+            OpCode::Nil as u8,
+            OpCode::ForIter as u8,
+            0,
+            0,
+            10,
+        ] {
+            chunk.write(byte, 1);
+        }
+        for byte in [OpCode::GetLocal as u8, 2, OpCode::GetLocal as u8, 2] {
+            chunk.write(byte, 2);
+        }
+        for byte in [OpCode::Add, OpCode::Pop, OpCode::Pop] {
+            chunk.write(byte as u8, 2);
+        }
+        for byte in [OpCode::Loop as u8, 0, 14, OpCode::Pop as u8] {
+            chunk.write(byte, 3);
+        }
+        chunk.peephole(0);
+        assert_eq!(chunk.jump_target(2), Some(15));
+        assert_eq!(chunk.jump_target(12), Some(2));
+        assert!(chunk.verify(0, 0, 0).unwrap().falls_through);
+    }
+
+    #[test]
+    fn peephole_does_not_fuse_into_jump_target() {
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Jump as u8, 1);
+        chunk.write(0, 1);
+        chunk.write(2, 1);
+        chunk.write(OpCode::GetLocal as u8, 1);
+        chunk.write(0, 1);
+        chunk.write(OpCode::GetLocal as u8, 1);
+        chunk.write(1, 1);
+        chunk.write(OpCode::GetLocal as u8, 1);
+        chunk.write(2, 1);
+        chunk.peephole(0);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::Jump as u8,
+                0,
+                2,
+                OpCode::GetLocal as u8,
+                0,
+                OpCode::GetLocalGetLocal as u8,
+                1,
+                2,
+            ]
+        );
+    }
+
+    #[test]
+    fn peephole_leaves_code_before_start_alone() {
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Less as u8, 1);
+        chunk.write(OpCode::Not as u8, 1);
+        chunk.write(OpCode::Less as u8, 2);
+        chunk.write(OpCode::Not as u8, 2);
+        chunk.peephole(2);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::Less as u8,
+                OpCode::Not as u8,
+                OpCode::GreaterEqual as u8
+            ]
+        );
+        assert_eq!(chunk.lines, vec![(2, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn verify_computes_max_stack() {
+        let mut chunk = Chunk::default();
+        chunk.write_constant(1f64.into(), 1);
+        chunk.write_constant(2f64.into(), 1);
+        chunk.write_constant(3f64.into(), 1);
+        chunk.write(OpCode::Add as u8, 1);
+        chunk.write(OpCode::Add as u8, 1);
+        chunk.write(OpCode::Return as u8, 1);
+        let verified = chunk.verify(0, 0, 0).unwrap();
+        assert_eq!(verified.max_stack, 3);
+        assert!(!verified.falls_through);
+    }
+
+    #[test]
+    fn verify_pushes_the_exception_for_handlers() {
+        let mut chunk = Chunk::default();
+        for byte in [
+            OpCode::SetupTry as u8,
+            0,
+            2,
+            OpCode::Nil as u8,
+            OpCode::Throw as u8,
+        ] {
+            chunk.write(byte, 1);
+        }
+        // the handler returns the exception
+        chunk.write(OpCode::Return as u8, 1);
+        let verified = chunk.verify(0, 0, 0).unwrap();
+        assert_eq!(verified.max_stack, 1);
+        assert!(!verified.falls_through);
+
+        // the handler starts with a single value, too few to add
+        chunk.code[5] = OpCode::Add as u8;
+        assert!(chunk.verify(0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn verify_reports_falling_through() {
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Nil as u8, 1);
+        chunk.write(OpCode::Pop as u8, 1);
+        assert!(chunk.verify(0, 0, 0).unwrap().falls_through);
+        assert!(chunk.verify(2, 0, 0).unwrap().falls_through);
+    }
+
+    #[test]
+    fn verify_rejects_stack_underflow() {
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Nil as u8, 1);
+        chunk.write(OpCode::Add as u8, 1);
+        assert_eq!(
+            chunk.verify(0, 0, 0),
+            Err(VerifyError {
+                offset: 1,
+                reason: "stack underflow"
+            })
+        );
+        assert!(chunk.verify(0, 1, 0).is_ok());
+    }
+
+    #[test]
+    fn verify_rejects_bad_operands() {
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Constant as u8, 1);
+        chunk.write(0, 1);
+        assert_eq!(
+            chunk.verify(0, 0, 0).unwrap_err().reason,
+            "constant out of range"
+        );
+
+        let mut chunk = Chunk::default();
+        chunk.write_constant(1f64.into(), 1);
+        chunk.write(OpCode::GetGlobal as u8, 1);
+        chunk.write(0, 1);
+        assert_eq!(
+            chunk.verify(0, 0, 0).unwrap_err().reason,
+            "name is not a string"
+        );
+
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::GetLocal as u8, 1);
+        chunk.write(1, 1);
+        assert_eq!(
+            chunk.verify(0, 1, 0).unwrap_err().reason,
+            "local slot out of range"
+        );
+
+        // the second read can see the value pushed by the first, but no further
+        let mut chunk = Chunk::default();
+        for byte in [OpCode::GetLocalGetLocal as u8, 0, 1, OpCode::Pop as u8] {
+            chunk.write(byte, 1);
+        }
+        chunk.write(OpCode::Pop as u8, 1);
+        assert!(chunk.verify(0, 1, 0).is_ok());
+        chunk.code[2] = 2;
+        assert_eq!(
+            chunk.verify(0, 1, 0).unwrap_err().reason,
+            "local slot out of range"
+        );
+
+        // the iterator lives in the slot after the sequence
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::ForIter as u8, 1);
+        chunk.write(0, 1);
+        chunk.write(0, 1);
+        chunk.write(0, 1);
+        assert_eq!(
+            chunk.verify(0, 1, 0).unwrap_err().reason,
+            "local slot out of range"
+        );
+
+        // the running closure captures a single upvalue
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::GetUpvalue as u8, 1);
+        chunk.write(1, 1);
+        assert!(chunk.verify(0, 0, 2).is_ok());
+        assert_eq!(
+            chunk.verify(0, 0, 1).unwrap_err().reason,
+            "upvalue out of range"
+        );
+
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::COUNT, 1);
+        assert_eq!(chunk.verify(0, 0, 0).unwrap_err().reason, "unknown opcode");
+
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Jump as u8, 1);
+        chunk.write(0, 1);
+        assert_eq!(
+            chunk.verify(0, 0, 0).unwrap_err().reason,
+            "truncated instruction"
+        );
+    }
+
+    #[test]
+    fn verify_rejects_bad_jumps() {
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Jump as u8, 1);
+        chunk.write(0, 1);
+        chunk.write(1, 1);
+        chunk.write(OpCode::GetLocal as u8, 1);
+        chunk.write(0, 1);
+        assert_eq!(
+            chunk.verify(0, 1, 0).unwrap_err().reason,
+            "jump to the middle of an instruction"
+        );
+
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Loop as u8, 1);
+        chunk.write(0, 1);
+        chunk.write(4, 1);
+        assert_eq!(
+            chunk.verify(0, 0, 0).unwrap_err().reason,
+            "jump to the middle of an instruction"
+        );
+    }
+
+    #[test]
+    fn verify_rejects_inconsistent_stack_height() {
+        // pushes a value on every iteration of the loop
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Nil as u8, 1);
+        chunk.write(OpCode::Loop as u8, 1);
+        chunk.write(0, 1);
+        chunk.write(4, 1);
+        assert_eq!(
+            chunk.verify(0, 0, 0).unwrap_err().reason,
+            "inconsistent stack height"
+        );
+    }
+
+    #[test]
+    fn verify_rejects_unpaired_handlers() {
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::PopTry as u8, 1);
+        chunk.write(OpCode::Halt as u8, 1);
+        assert_eq!(
+            chunk.verify(0, 0, 0),
+            Err(VerifyError {
+                offset: 0,
+                reason: "no handler to pop"
+            })
+        );
+
+        // the `try` block returns without popping its handler
+        let mut chunk = Chunk::default();
+        let code = [
+            OpCode::SetupTry as u8,
+            0,
+            3,
+            OpCode::Nil as u8,
+            OpCode::Return as u8,
+            OpCode::PopTry as u8,
+            OpCode::Return as u8,
+        ];
+        for byte in code {
+            chunk.write(byte, 1);
+        }
+        assert_eq!(
+            chunk.verify(0, 0, 0),
+            Err(VerifyError {
+                offset: 4,
+                reason: "handler left set up"
+            })
+        );
+
+        // only one of the paths to the `Pop` sets up a handler
+        let mut chunk = Chunk::default();
+        let code = [
+            OpCode::JumpIfFalse as u8,
+            0,
+            3,
+            OpCode::SetupTry as u8,
+            0,
+            2,
+            OpCode::Pop as u8,
+            OpCode::Halt as u8,
+            OpCode::Pop as u8,
+            OpCode::Pop as u8,
+            OpCode::Halt as u8,
+        ];
+        for byte in code {
+            chunk.write(byte, 1);
+        }
+        assert_eq!(
+            chunk.verify(0, 1, 0).unwrap_err().reason,
+            "inconsistent handlers"
+        );
+    }
 }
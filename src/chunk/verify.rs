@@ -0,0 +1,316 @@
+use std::{error::Error, fmt::Display};
+
+use crate::chunk::Chunk;
+use crate::object::{Capture, Obj, ObjPtr};
+use crate::opcode::OpCode;
+
+#[derive(Debug, PartialEq)]
+pub struct VerifyError {
+    pub offset: usize,
+    pub reason: &'static str,
+}
+
+impl Error for VerifyError {}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid bytecode at offset {}: {}",
+            self.offset, self.reason
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Verified {
+    // most values on the stack at any point, relative to the start of the frame
+    pub max_stack: usize,
+    // whether execution can run past the last instruction of the chunk
+    pub falls_through: bool,
+}
+
+impl Chunk {
+    fn invalid(offset: usize, reason: &'static str) -> VerifyError {
+        VerifyError { offset, reason }
+    }
+
+    fn check_constant(&self, offset: usize, idx: usize) -> Result<(), VerifyError> {
+        if idx < self.constants.len() {
+            Ok(())
+        } else {
+            Err(Self::invalid(offset, "constant out of range"))
+        }
+    }
+
+    fn check_name(&self, offset: usize) -> Result<(), VerifyError> {
+        let idx = self.code[offset + 1] as usize;
+        self.check_constant(offset, idx)?;
+        match self.constants[idx].as_obj_ptr() {
+            Some(ptr) if ptr.is_string() => Ok(()),
+            _ => Err(Self::invalid(offset, "name is not a string")),
+        }
+    }
+
+    // The function of a `Closure` instruction can only capture locals that
+    // are on the stack, or the slot the closure goes into for functions
+    // calling themselves.
+    fn check_closure(&self, offset: usize, height: usize) -> Result<(), VerifyError> {
+        let idx = self.code[offset + 1] as usize;
+        self.check_constant(offset, idx)?;
+        let function = match self.constants[idx].as_obj_ptr().map(ObjPtr::as_obj) {
+            Some(Obj::Function(function)) => function,
+            _ => {
+                return Err(Self::invalid(
+                    offset,
+                    "closure of something other than a function",
+                ))
+            }
+        };
+        for capture in &function.captures {
+            match capture {
+                Capture::Local(slot) if *slot as usize > height => {
+                    return Err(Self::invalid(offset, "local slot out of range"));
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    // Checks that the code reachable from `start` only contains valid
+    // instructions whose operands are in range, that jumps land on
+    // instructions and that the height of the stack at every instruction is
+    // the same on all paths to it and never drops below what the instruction
+    // pops, starting with `height` values on the stack. Upvalue indices are
+    // checked against the `upvalues` the running closure has, and every
+    // `PopTry` must pop a handler set up by the same chunk, which must all be
+    // gone by the time it returns. The VM relies on this to run the chunk
+    // without any further checks.
+    pub fn verify(
+        &self,
+        start: usize,
+        height: usize,
+        upvalues: usize,
+    ) -> Result<Verified, VerifyError> {
+        let len = self.code.len();
+        if start > len {
+            return Err(Self::invalid(start, "start is past the end of the chunk"));
+        }
+        let mut is_instruction = vec![false; len + 1];
+        let mut offset = start;
+        while offset < len {
+            if self.code[offset] >= OpCode::COUNT {
+                return Err(Self::invalid(offset, "unknown opcode"));
+            }
+            is_instruction[offset] = true;
+            offset += OpCode::from(self.code[offset]).size();
+        }
+        if offset > len {
+            return Err(Self::invalid(len, "truncated instruction"));
+        }
+        is_instruction[len] = true;
+
+        // the stack height and the number of handlers set up at every
+        // instruction
+        let mut states = vec![None; len + 1];
+        let mut worklist = vec![start];
+        states[start] = Some((height, 0));
+        let mut verified = Verified {
+            max_stack: height,
+            falls_through: false,
+        };
+        while let Some(offset) = worklist.pop() {
+            let (height, tries) = states[offset].unwrap();
+            if offset == len {
+                verified.falls_through = true;
+                continue;
+            }
+            let instruction = OpCode::from(self.code[offset]);
+            let next = offset + instruction.size();
+            let operand = |n: usize| self.code[offset + n] as usize;
+            let (pops, pushes) = match instruction {
+                OpCode::Return => (1, 0),
+                OpCode::Halt => (0, 0),
+                OpCode::Constant => {
+                    self.check_constant(offset, operand(1))?;
+                    (0, 1)
+                }
+                OpCode::ConstantLong => {
+                    let idx = u32::from_be_bytes([
+                        0,
+                        self.code[offset + 1],
+                        self.code[offset + 2],
+                        self.code[offset + 3],
+                    ]);
+                    self.check_constant(offset, idx as usize)?;
+                    (0, 1)
+                }
+                OpCode::AddConstant => {
+                    self.check_constant(offset, operand(1))?;
+                    (1, 1)
+                }
+                OpCode::Nil | OpCode::True | OpCode::False => (0, 1),
+                OpCode::Negate | OpCode::Not | OpCode::BitNot => (1, 1),
+                OpCode::Add
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide
+                | OpCode::Modulo
+                | OpCode::Power
+                | OpCode::BitAnd
+                | OpCode::BitOr
+                | OpCode::BitXor
+                | OpCode::ShiftLeft
+                | OpCode::ShiftRight
+                | OpCode::Equal
+                | OpCode::Greater
+                | OpCode::Less
+                | OpCode::GreaterEqual
+                | OpCode::LessEqual
+                | OpCode::NotEqual => (2, 1),
+                OpCode::Pop | OpCode::Print => (1, 0),
+                OpCode::DefineGlobal => {
+                    self.check_name(offset)?;
+                    (1, 0)
+                }
+                OpCode::GetGlobal => {
+                    self.check_name(offset)?;
+                    (0, 1)
+                }
+                OpCode::SetGlobal => {
+                    self.check_name(offset)?;
+                    (1, 1)
+                }
+                OpCode::GetLocal | OpCode::SetLocal | OpCode::GetLocalGetLocal => {
+                    // the second slot of `GetLocalGetLocal` is read after the
+                    // first value is pushed, so it can be that value
+                    let in_range = if instruction == OpCode::GetLocalGetLocal {
+                        operand(1) < height && operand(2) <= height
+                    } else {
+                        operand(1) < height
+                    };
+                    if !in_range {
+                        return Err(Self::invalid(offset, "local slot out of range"));
+                    }
+                    match instruction {
+                        OpCode::GetLocal => (0, 1),
+                        OpCode::SetLocal => (1, 1),
+                        _ => (0, 2),
+                    }
+                }
+                OpCode::Jump | OpCode::Loop | OpCode::SetupTry | OpCode::PopTry => (0, 0),
+                OpCode::JumpIfFalse => (1, 1),
+                OpCode::Call => (operand(1) + 1, 1),
+                OpCode::GetProperty => {
+                    self.check_name(offset)?;
+                    (1, 1)
+                }
+                OpCode::SetProperty => {
+                    self.check_name(offset)?;
+                    (2, 1)
+                }
+                OpCode::Invoke => {
+                    self.check_name(offset)?;
+                    (operand(2) + 1, 1)
+                }
+                OpCode::BuildList => (operand(1), 1),
+                OpCode::BuildMap => (operand(1) * 2, 1),
+                OpCode::Range => (2, 1),
+                OpCode::Stringify => (1, 1),
+                OpCode::MatchValue => (2, 1),
+                OpCode::MatchRange => (3, 1),
+                OpCode::ForIter => {
+                    // the sequence and the iterator
+                    if operand(1) + 1 >= height {
+                        return Err(Self::invalid(offset, "local slot out of range"));
+                    }
+                    (0, 1)
+                }
+                OpCode::GetIndex => (2, 1),
+                OpCode::SetIndex => (3, 1),
+                OpCode::Closure => {
+                    self.check_closure(offset, height)?;
+                    (0, 1)
+                }
+                OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                    if operand(1) >= upvalues {
+                        return Err(Self::invalid(offset, "upvalue out of range"));
+                    }
+                    match instruction {
+                        OpCode::GetUpvalue => (0, 1),
+                        _ => (1, 1),
+                    }
+                }
+                OpCode::CloseUpvalue => (1, 0),
+                OpCode::Dup => (operand(1), operand(1) * 2),
+                OpCode::Bury => (operand(1) + 1, operand(1) + 1),
+                OpCode::Throw => (1, 0),
+            };
+            if height < pops {
+                return Err(Self::invalid(offset, "stack underflow"));
+            }
+            let height = height - pops + pushes;
+            verified.max_stack = verified.max_stack.max(height);
+            match instruction {
+                OpCode::PopTry if tries == 0 => {
+                    return Err(Self::invalid(offset, "no handler to pop"));
+                }
+                OpCode::Return | OpCode::Halt if tries > 0 => {
+                    return Err(Self::invalid(offset, "handler left set up"));
+                }
+                _ => (),
+            }
+
+            let jump = || self.jump_operand(offset);
+            let successors = match instruction {
+                OpCode::Return | OpCode::Halt | OpCode::Throw => vec![],
+                OpCode::Jump => vec![(next.checked_add(jump()), (height, tries))],
+                OpCode::Loop => vec![(next.checked_sub(jump()), (height, tries))],
+                OpCode::JumpIfFalse | OpCode::ForIter => vec![
+                    (Some(next), (height, tries)),
+                    (next.checked_add(jump()), (height, tries)),
+                ],
+                // the handler starts with the exception on top, after it has
+                // been popped
+                OpCode::SetupTry => {
+                    verified.max_stack = verified.max_stack.max(height + 1);
+                    vec![
+                        (Some(next), (height, tries + 1)),
+                        (next.checked_add(jump()), (height + 1, tries)),
+                    ]
+                }
+                OpCode::PopTry => vec![(Some(next), (height, tries - 1))],
+                _ => vec![(Some(next), (height, tries))],
+            };
+            for (successor, state) in successors {
+                match successor {
+                    Some(successor)
+                        if successor >= start && is_instruction.get(successor) == Some(&true) =>
+                    {
+                        match states[successor] {
+                            None => {
+                                states[successor] = Some(state);
+                                worklist.push(successor);
+                            }
+                            Some((expected, _)) if expected != state.0 => {
+                                return Err(Self::invalid(offset, "inconsistent stack height"));
+                            }
+                            Some((_, expected)) if expected != state.1 => {
+                                return Err(Self::invalid(offset, "inconsistent handlers"));
+                            }
+                            Some(_) => (),
+                        }
+                    }
+                    _ => {
+                        return Err(Self::invalid(
+                            offset,
+                            "jump to the middle of an instruction",
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(verified)
+    }
+}
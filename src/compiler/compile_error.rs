@@ -0,0 +1,87 @@
+use core::fmt;
+use std::str;
+use std::{error::Error, fmt::Display};
+
+use crate::token::Token;
+
+#[derive(Debug, PartialEq)]
+pub enum CompileError<'a> {
+    TooManyLocals { name: &'a Token<'a> },
+    TooManyConstants { name: &'a Token<'a> },
+    LocalAlreadyDeclared { name: &'a Token<'a> },
+    ReadInOwnInitializer { name: &'a Token<'a> },
+    JumpTooLarge,
+    TooManyParameters { name: &'a Token<'a> },
+    TooManyArguments { paren: &'a Token<'a> },
+    TooManyUpvalues { name: &'a Token<'a> },
+    TooManyElements { bracket: &'a Token<'a> },
+    TooManyEntries { brace: &'a Token<'a> },
+    OutsideLoop { keyword: &'a Token<'a> },
+}
+
+impl<'a> Error for CompileError<'a> {}
+
+impl<'a> Display for CompileError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::TooManyLocals { name } => write!(
+                f,
+                "Too many local variables in scope when declaring {} on line {}",
+                str::from_utf8(name.lexeme).unwrap(),
+                name.line
+            ),
+            CompileError::TooManyConstants { name } => write!(
+                f,
+                "Too many constants in chunk when referring to {} on line {}",
+                str::from_utf8(name.lexeme).unwrap(),
+                name.line
+            ),
+            CompileError::LocalAlreadyDeclared { name } => write!(
+                f,
+                "Variable {} is already declared in this scope on line {}",
+                str::from_utf8(name.lexeme).unwrap(),
+                name.line
+            ),
+            CompileError::ReadInOwnInitializer { name } => write!(
+                f,
+                "Can't read local variable {} in its own initializer on line {}",
+                str::from_utf8(name.lexeme).unwrap(),
+                name.line
+            ),
+            CompileError::JumpTooLarge => write!(f, "Too much code to jump over"),
+            CompileError::TooManyParameters { name } => write!(
+                f,
+                "Function {} can't have more than 255 parameters on line {}",
+                str::from_utf8(name.lexeme).unwrap(),
+                name.line
+            ),
+            CompileError::TooManyArguments { paren } => write!(
+                f,
+                "Can't pass more than 255 arguments on line {}",
+                paren.line
+            ),
+            CompileError::TooManyUpvalues { name } => write!(
+                f,
+                "Too many variables of enclosing functions used when referring to {} on line {}",
+                str::from_utf8(name.lexeme).unwrap(),
+                name.line
+            ),
+            CompileError::TooManyElements { bracket } => write!(
+                f,
+                "Can't have more than 255 elements in a list literal on line {}",
+                bracket.line
+            ),
+            CompileError::TooManyEntries { brace } => write!(
+                f,
+                "Can't have more than 255 entries in a map literal on line {}",
+                brace.line
+            ),
+            CompileError::OutsideLoop { keyword } => write!(
+                f,
+                "Can't use {} outside of a loop on line {}",
+                str::from_utf8(keyword.lexeme).unwrap(),
+                keyword.line
+            ),
+        }
+    }
+}
@@ -0,0 +1,1382 @@
+mod compile_error;
+mod test;
+
+use crate::byte_string::{Byte, ByteSlice, ByteVector};
+use crate::chunk::Chunk;
+use crate::expr::Expr;
+use crate::object::{Capture, Function, Obj};
+use crate::opcode::OpCode;
+use crate::scanner;
+use crate::stmt::{Pattern, Stmt};
+use crate::token::Token;
+use crate::token_type::TokenType;
+use crate::value::{bit_not, bitwise, Value};
+use std::cmp::Ordering;
+use std::str;
+
+use self::compile_error::CompileError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptLevel {
+    // emit bytecode for the program exactly as it was written
+    O0,
+    // fold constant expressions, drop code that can never run and fuse
+    // common sequences of instructions
+    #[default]
+    O1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompileMode {
+    // only `print` statements print anything
+    #[default]
+    Script,
+    // expression statements at the top level print their value, for the REPL
+    Repl,
+}
+
+// value of an expression which is already known at compile time
+#[derive(Debug, Clone, PartialEq)]
+enum Constant {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(ByteVector),
+}
+
+impl Constant {
+    fn is_truthy(&self) -> bool {
+        !matches!(self, Constant::Nil | Constant::Boolean(false))
+    }
+}
+
+struct Local<'a> {
+    name: &'a ByteSlice,
+    // `None` while the initializer of the variable is being compiled
+    depth: Option<usize>,
+    // whether a nested function uses it, in which case it's closed over
+    // rather than popped when it goes out of scope
+    captured: bool,
+}
+
+// A loop whose body is being compiled. `break` and `continue` jump forward
+// to the end of the loop and to its increment, patched once those are
+// reached. Locals deeper than `scope_depth` are popped before jumping.
+struct Loop {
+    scope_depth: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+// A `try` statement whose handler is installed while the code being compiled
+// runs, which is the case in its body and, if there is a `finally` clause, in
+// its `catch` clause. Jumping out of it with `break`, `continue` or `return`
+// removes the handler and runs the `finally` clause first.
+#[derive(Clone, Copy)]
+struct Try<'a> {
+    // number of locals around the statement
+    locals: usize,
+    // number of loops around the statement
+    loops: usize,
+    finally: Option<&'a Stmt<'a>>,
+}
+
+struct Compiler<'a, 'c> {
+    chunk: &'c mut Chunk,
+    locals: Vec<Local<'a>>,
+    scope_depth: usize,
+    opt_level: OptLevel,
+    // always `Script` when compiling a function
+    mode: CompileMode,
+    // names of the locals of all enclosing functions, which are captured
+    enclosing_locals: Vec<&'a ByteSlice>,
+    // the variables of enclosing functions this function uses, which the
+    // enclosing function resolves once this one is compiled
+    upvalues: Vec<&'a Token<'a>>,
+    // the loops around the code being compiled, innermost last
+    loops: Vec<Loop>,
+    // the `try` statements around the code being compiled, innermost last
+    tries: Vec<Try<'a>>,
+}
+
+fn parse_number(lexeme: &ByteSlice) -> f64 {
+    unsafe { str::from_utf8_unchecked(lexeme) }.parse().unwrap()
+}
+
+fn fold_expr(expr: &Expr<'_>) -> Option<Constant> {
+    match expr {
+        Expr::NumericLiteral(n) => Some(Constant::Number(parse_number(n))),
+        Expr::StringLiteral(bytestring) => Some(Constant::String(scanner::unescape(bytestring))),
+        Expr::TrueLiteral => Some(Constant::Boolean(true)),
+        Expr::FalseLiteral => Some(Constant::Boolean(false)),
+        Expr::NilLiteral => Some(Constant::Nil),
+        Expr::Grouping(expr) => fold_expr(expr),
+        Expr::Unary { op, expr } => match (op.token_type, fold_expr(expr)?) {
+            (TokenType::Minus, Constant::Number(n)) => Some(Constant::Number(-n)),
+            (TokenType::Bang, constant) => Some(Constant::Boolean(!constant.is_truthy())),
+            (TokenType::Tilde, Constant::Number(n)) => bit_not(n).ok().map(Constant::Number),
+            // leave type errors for the VM to report at runtime
+            _ => None,
+        },
+        Expr::Binary { left, op, right } => {
+            fold_binary(op.token_type, fold_expr(left)?, fold_expr(right)?)
+        }
+        Expr::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+        } => match fold_expr(condition)?.is_truthy() {
+            true => fold_expr(then_branch),
+            false => fold_expr(else_branch),
+        },
+        Expr::Logical { left, op, right } => {
+            let left = fold_expr(left)?;
+            match (op.token_type, left.is_truthy()) {
+                (TokenType::Or, true) | (TokenType::And, false) => Some(left),
+                _ => fold_expr(right),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn fold_binary(op: TokenType, left: Constant, right: Constant) -> Option<Constant> {
+    use Constant::{Boolean, Number};
+    match (op, left, right) {
+        (TokenType::Plus, Number(a), Number(b)) => Some(Number(a + b)),
+        (TokenType::Plus, Constant::String(a), Constant::String(b)) => {
+            Some(Constant::String([a, b].concat()))
+        }
+        (TokenType::Minus, Number(a), Number(b)) => Some(Number(a - b)),
+        (TokenType::Star, Number(a), Number(b)) => Some(Number(a * b)),
+        (TokenType::Slash, Number(a), Number(b)) => Some(Number(a / b)),
+        (TokenType::Percent, Number(a), Number(b)) => Some(Number(a % b)),
+        (TokenType::StarStar, Number(a), Number(b)) => Some(Number(a.powf(b))),
+        (
+            TokenType::Ampersand
+            | TokenType::Pipe
+            | TokenType::Caret
+            | TokenType::LessLess
+            | TokenType::GreaterGreater,
+            Number(a),
+            Number(b),
+        ) => bitwise(binary_instruction(op), a, b).ok().map(Number),
+        (TokenType::Greater, Number(a), Number(b)) => Some(Boolean(a > b)),
+        // `>=` and `<=` compile to negated `<` and `>`, which matters for NaN
+        (TokenType::GreaterEqual, Number(a), Number(b)) => {
+            Some(Boolean(a.partial_cmp(&b) != Some(Ordering::Less)))
+        }
+        (TokenType::Less, Number(a), Number(b)) => Some(Boolean(a < b)),
+        (TokenType::LessEqual, Number(a), Number(b)) => {
+            Some(Boolean(a.partial_cmp(&b) != Some(Ordering::Greater)))
+        }
+        (TokenType::EqualEqual, a, b) => Some(Boolean(a == b)),
+        (TokenType::BangEqual, a, b) => Some(Boolean(a != b)),
+        _ => None,
+    }
+}
+
+// The line to report for `expr`, that of the token it is about. Literals don't
+// keep their tokens, so they are on line 0 like the constants they compile to.
+fn expr_line(expr: &Expr<'_>) -> usize {
+    match expr {
+        Expr::StringLiteral(_)
+        | Expr::NumericLiteral(_)
+        | Expr::TrueLiteral
+        | Expr::FalseLiteral
+        | Expr::NilLiteral => 0,
+        Expr::Logical { op, .. }
+        | Expr::Unary { op, .. }
+        | Expr::Binary { op, .. }
+        | Expr::Compound { op, .. }
+        | Expr::Increment { op, .. } => op.line,
+        Expr::Grouping(expr)
+        | Expr::Conditional {
+            condition: expr, ..
+        } => expr_line(expr),
+        Expr::Variable(name)
+        | Expr::Assign { name, .. }
+        | Expr::Get { name, .. }
+        | Expr::Set { name, .. } => name.line,
+        Expr::Call { paren, .. } => paren.line,
+        Expr::List { bracket, .. }
+        | Expr::Index { bracket, .. }
+        | Expr::SetIndex { bracket, .. } => bracket.line,
+        Expr::Map { brace, .. } => brace.line,
+        Expr::Interpolation { start, .. } => start.line,
+        Expr::Lambda { keyword, .. } => keyword.line,
+    }
+}
+
+// the instruction for a binary operator that has one of its own
+fn binary_instruction(op: TokenType) -> OpCode {
+    match op {
+        TokenType::Plus | TokenType::PlusEqual | TokenType::PlusPlus => OpCode::Add,
+        TokenType::Minus | TokenType::MinusEqual | TokenType::MinusMinus => OpCode::Subtract,
+        TokenType::Star | TokenType::StarEqual => OpCode::Multiply,
+        TokenType::Slash | TokenType::SlashEqual => OpCode::Divide,
+        TokenType::Percent => OpCode::Modulo,
+        TokenType::StarStar => OpCode::Power,
+        TokenType::Ampersand => OpCode::BitAnd,
+        TokenType::Pipe => OpCode::BitOr,
+        TokenType::Caret => OpCode::BitXor,
+        TokenType::LessLess => OpCode::ShiftLeft,
+        TokenType::GreaterGreater => OpCode::ShiftRight,
+        TokenType::EqualEqual => OpCode::Equal,
+        TokenType::Greater => OpCode::Greater,
+        TokenType::Less => OpCode::Less,
+        TokenType::DotDot => OpCode::Range,
+        _ => unreachable!(),
+    }
+}
+
+// whether control can never continue past this statement
+fn always_returns(stmt: &Stmt<'_>) -> bool {
+    match stmt {
+        Stmt::Return { .. } | Stmt::Break(_) | Stmt::Continue(_) | Stmt::Throw { .. } => true,
+        Stmt::Block(statements) => statements.iter().any(always_returns),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            let else_returns = else_branch.as_deref().is_some_and(always_returns);
+            match fold_expr(condition) {
+                Some(condition) if condition.is_truthy() => always_returns(then_branch),
+                Some(_) => else_returns,
+                None => always_returns(then_branch) && else_returns,
+            }
+        }
+        Stmt::Match { cases, default, .. } => {
+            default.as_deref().is_some_and(always_returns)
+                && cases.iter().all(|(_, body)| always_returns(body))
+        }
+        _ => false,
+    }
+}
+
+impl<'a, 'c> Compiler<'a, 'c> {
+    fn new(chunk: &'c mut Chunk, opt_level: OptLevel) -> Self {
+        Self {
+            chunk,
+            locals: Vec::new(),
+            scope_depth: 0,
+            opt_level,
+            mode: CompileMode::Script,
+            enclosing_locals: Vec::new(),
+            upvalues: Vec::new(),
+            loops: Vec::new(),
+            tries: Vec::new(),
+        }
+    }
+
+    fn emit_byte(&mut self, byte: Byte, line: usize) {
+        self.chunk.write(byte, line);
+    }
+
+    fn emit_bytes(&mut self, byte1: Byte, byte2: Byte, line: usize) {
+        self.chunk.write(byte1, line);
+        self.chunk.write(byte2, line);
+    }
+
+    fn emit_constant(&mut self, value: Value, line: usize) {
+        self.chunk.write_constant(value, line);
+    }
+
+    // `line` is that of the expression folded into `constant`
+    fn emit_folded(&mut self, constant: Constant, line: usize) {
+        match constant {
+            Constant::Nil => self.emit_byte(OpCode::Nil as u8, line),
+            Constant::Boolean(true) => self.emit_byte(OpCode::True as u8, line),
+            Constant::Boolean(false) => self.emit_byte(OpCode::False as u8, line),
+            Constant::Number(n) => self.emit_constant(Value::Number(n), line),
+            Constant::String(bytestring) => {
+                self.emit_constant(Value::ObjPtr(bytestring.into()), line)
+            }
+        }
+    }
+
+    fn emit_jump(&mut self, instruction: OpCode, line: usize) -> usize {
+        self.emit_byte(instruction as u8, line);
+        self.emit_bytes(0xff, 0xff, line);
+        self.chunk.code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) -> Result<(), CompileError<'a>> {
+        // -2 to adjust for the bytecode for the jump offset itself
+        let jump = self.chunk.code.len() - offset - 2;
+        let [h, l] = u16::try_from(jump)
+            .map_err(|_| CompileError::JumpTooLarge)?
+            .to_be_bytes();
+        self.chunk.code[offset] = h;
+        self.chunk.code[offset + 1] = l;
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, loop_start: usize, line: usize) -> Result<(), CompileError<'a>> {
+        self.emit_byte(OpCode::Loop as u8, line);
+        // +2 to also jump back over the operand of the loop instruction
+        let jump = self.chunk.code.len() - loop_start + 2;
+        let [h, l] = u16::try_from(jump)
+            .map_err(|_| CompileError::JumpTooLarge)?
+            .to_be_bytes();
+        self.emit_bytes(h, l, line);
+        Ok(())
+    }
+
+    fn identifier_constant(&mut self, name: &'a Token<'a>) -> Result<Byte, CompileError<'a>> {
+        let idx = self.chunk.add_constant(Value::ObjPtr(name.lexeme.into()));
+        Byte::try_from(idx).map_err(|_| CompileError::TooManyConstants { name })
+    }
+
+    fn resolve_local(&self, name: &'a Token<'a>) -> Result<Option<Byte>, CompileError<'a>> {
+        match self
+            .locals
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, local)| local.name == name.lexeme)
+        {
+            Some((_, Local { depth: None, .. })) => {
+                Err(CompileError::ReadInOwnInitializer { name })
+            }
+            // the number of locals never exceeds what fits in a byte
+            Some((slot, _)) => Ok(Some(slot as Byte)),
+            None => Ok(None),
+        }
+    }
+
+    // the upvalue for a local of an enclosing function, if `name` is one
+    fn resolve_upvalue(&mut self, name: &'a Token<'a>) -> Result<Option<Byte>, CompileError<'a>> {
+        if !self.enclosing_locals.contains(&name.lexeme) {
+            return Ok(None);
+        }
+        self.add_upvalue(name).map(Some)
+    }
+
+    fn add_upvalue(&mut self, name: &'a Token<'a>) -> Result<Byte, CompileError<'a>> {
+        let idx = match (self.upvalues.iter()).position(|upvalue| upvalue.lexeme == name.lexeme) {
+            Some(idx) => idx,
+            None => {
+                self.upvalues.push(name);
+                self.upvalues.len() - 1
+            }
+        };
+        Byte::try_from(idx).map_err(|_| CompileError::TooManyUpvalues { name })
+    }
+
+    // Where a function nested in this one finds the variable `name` it
+    // captures, which is a local of this function or of one enclosing it.
+    fn capture(&mut self, name: &'a Token<'a>) -> Result<Capture, CompileError<'a>> {
+        match self.resolve_local(name)? {
+            Some(slot) => {
+                self.locals[slot as usize].captured = true;
+                Ok(Capture::Local(slot))
+            }
+            None => self.add_upvalue(name).map(Capture::Upvalue),
+        }
+    }
+
+    // instruction that removes the local on top of the stack
+    fn discard_local(&mut self, captured: bool, line: usize) {
+        let instruction = if captured {
+            OpCode::CloseUpvalue
+        } else {
+            OpCode::Pop
+        };
+        self.emit_byte(instruction as u8, line);
+    }
+
+    // the instruction reading or assigning the variable `name`, along with
+    // its operand
+    fn resolve_variable(
+        &mut self,
+        name: &'a Token<'a>,
+        assign: bool,
+    ) -> Result<(OpCode, Byte), CompileError<'a>> {
+        if let Some(slot) = self.resolve_local(name)? {
+            let instruction = if assign {
+                OpCode::SetLocal
+            } else {
+                OpCode::GetLocal
+            };
+            return Ok((instruction, slot));
+        }
+        if let Some(idx) = self.resolve_upvalue(name)? {
+            let instruction = if assign {
+                OpCode::SetUpvalue
+            } else {
+                OpCode::GetUpvalue
+            };
+            return Ok((instruction, idx));
+        }
+        let instruction = if assign {
+            OpCode::SetGlobal
+        } else {
+            OpCode::GetGlobal
+        };
+        Ok((instruction, self.identifier_constant(name)?))
+    }
+
+    fn mark_initialized(&mut self) {
+        if let Some(local) = self.locals.last_mut() {
+            local.depth = Some(self.scope_depth);
+        }
+    }
+
+    fn define_variable(&mut self, name: &'a Token<'a>) -> Result<(), CompileError<'a>> {
+        if self.scope_depth > 0 {
+            // the value left on the stack by the initializer is the local's slot
+            self.mark_initialized();
+        } else {
+            let global = self.identifier_constant(name)?;
+            self.emit_bytes(OpCode::DefineGlobal as u8, global, name.line);
+        }
+        Ok(())
+    }
+
+    fn declare_local(&mut self, name: &'a Token<'a>) -> Result<(), CompileError<'a>> {
+        for local in self.locals.iter().rev() {
+            if local.depth.is_some_and(|depth| depth < self.scope_depth) {
+                break;
+            }
+            if local.name == name.lexeme {
+                return Err(CompileError::LocalAlreadyDeclared { name });
+            }
+        }
+        if self.locals.len() > Byte::MAX as usize {
+            return Err(CompileError::TooManyLocals { name });
+        }
+        self.locals.push(Local {
+            name: name.lexeme,
+            depth: None,
+            captured: false,
+        });
+        Ok(())
+    }
+
+    // A local holding a value the compiler needs to keep around, which can't
+    // be named from Lox. It takes the value on top of the stack.
+    fn add_hidden_local(
+        &mut self,
+        name: &'static ByteSlice,
+        token: &'a Token<'a>,
+    ) -> Result<(), CompileError<'a>> {
+        if self.locals.len() > Byte::MAX as usize {
+            return Err(CompileError::TooManyLocals { name: token });
+        }
+        self.locals.push(Local {
+            name,
+            depth: Some(self.scope_depth),
+            captured: false,
+        });
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.scope_depth -= 1;
+        while let Some(&Local {
+            depth: Some(depth),
+            captured,
+            ..
+        }) = self.locals.last()
+        {
+            if depth <= self.scope_depth {
+                break;
+            }
+            self.locals.pop();
+            self.discard_local(captured, 0);
+        }
+    }
+
+    fn compile_stmts(&mut self, statements: &'a [Stmt<'a>]) -> Result<(), CompileError<'a>> {
+        for statement in statements {
+            self.compile_stmt(statement)?;
+            if self.opt_level == OptLevel::O1 && always_returns(statement) {
+                // nothing after this statement can ever be reached
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_stmt(&mut self, stmt: &'a Stmt<'a>) -> Result<(), CompileError<'a>> {
+        match stmt {
+            Stmt::Print(expr) => {
+                self.compile_expr(expr)?;
+                self.emit_byte(OpCode::Print as u8, expr_line(expr));
+            }
+            Stmt::Expression(expr) => {
+                self.compile_expr(expr)?;
+                let line = expr_line(expr);
+                if self.mode == CompileMode::Repl && self.scope_depth == 0 {
+                    self.emit_byte(OpCode::Print as u8, line);
+                } else {
+                    self.emit_byte(OpCode::Pop as u8, line);
+                }
+            }
+            Stmt::Return { keyword, value } => {
+                match value {
+                    Some(value) => self.compile_expr(value)?,
+                    None => self.emit_byte(OpCode::Nil as u8, keyword.line),
+                }
+                self.leave_tries(0, true, keyword)?;
+                self.emit_byte(OpCode::Return as u8, keyword.line);
+            }
+            Stmt::Var(name, initializer) => self.compile_var(name, initializer.as_ref())?,
+            Stmt::Block(statements) => {
+                self.begin_scope();
+                self.compile_stmts(statements)?;
+                self.end_scope();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => self.compile_if(condition, then_branch, else_branch.as_deref())?,
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => self.compile_while(condition, body, increment.as_ref())?,
+            Stmt::Match {
+                keyword,
+                subject,
+                cases,
+                default,
+            } => self.compile_match(keyword, subject, cases, default.as_deref())?,
+            Stmt::Break(keyword) | Stmt::Continue(keyword) => self.compile_loop_jump(keyword)?,
+            Stmt::Throw { keyword, value } => {
+                self.compile_expr(value)?;
+                self.emit_byte(OpCode::Throw as u8, keyword.line);
+            }
+            Stmt::Try {
+                keyword,
+                body,
+                catch,
+                finally,
+            } => self.compile_try(keyword, body, catch.as_ref(), finally.as_deref())?,
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => self.compile_for_in(name, iterable, body)?,
+            Stmt::Function { name, params, body } => {
+                if self.scope_depth > 0 {
+                    self.declare_local(name)?;
+                    self.mark_initialized();
+                }
+                self.compile_function(name, name.lexeme, params, body)?;
+                self.define_variable(name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_var(
+        &mut self,
+        name: &'a Token<'a>,
+        initializer: Option<&'a Expr<'a>>,
+    ) -> Result<(), CompileError<'a>> {
+        if self.scope_depth > 0 {
+            self.declare_local(name)?;
+        }
+        match initializer {
+            Some(initializer) => self.compile_expr(initializer)?,
+            None => self.emit_byte(OpCode::Nil as u8, name.line),
+        }
+        self.define_variable(name)
+    }
+
+    // Compiles a function declaration or a lambda and leaves the function or a
+    // closure for it on the stack. `token` is the name of the declaration or
+    // the keyword starting the lambda.
+    fn compile_function(
+        &mut self,
+        token: &'a Token<'a>,
+        name: &'a ByteSlice,
+        params: &'a [&'a Token<'a>],
+        body: &'a Stmt<'a>,
+    ) -> Result<(), CompileError<'a>> {
+        if params.len() > Byte::MAX as usize {
+            return Err(CompileError::TooManyParameters { name: token });
+        }
+        let mut chunk = Chunk::default();
+        let mut compiler = Compiler::new(&mut chunk, self.opt_level);
+        compiler.enclosing_locals = (self.locals.iter().map(|local| local.name))
+            .chain(self.enclosing_locals.iter().copied())
+            .collect();
+        compiler.begin_scope();
+        // the first slot of every call frame holds the function being called
+        compiler.locals.push(Local {
+            name: b"",
+            depth: Some(compiler.scope_depth),
+            captured: false,
+        });
+        for param in params {
+            compiler.declare_local(param)?;
+            compiler.mark_initialized();
+        }
+        match body {
+            Stmt::Block(statements) => compiler.compile_stmts(statements)?,
+            body => compiler.compile_stmt(body)?,
+        }
+        if self.opt_level == OptLevel::O0 || !always_returns(body) {
+            compiler.emit_byte(OpCode::Nil as u8, token.line);
+            compiler.emit_byte(OpCode::Return as u8, token.line);
+        }
+        let upvalues = std::mem::take(&mut compiler.upvalues);
+        let captures = (upvalues.into_iter())
+            .map(|upvalue| self.capture(upvalue))
+            .collect::<Result<Vec<_>, _>>()?;
+        if self.opt_level == OptLevel::O1 {
+            chunk.peephole(0);
+        }
+
+        let verified = chunk
+            .verify(0, params.len() + 1, captures.len())
+            .expect("compiler emitted invalid bytecode");
+        debug_assert!(!verified.falls_through, "function can run past its end");
+        // functions that don't capture anything don't need a closure
+        let captures_nothing = captures.is_empty();
+        let function = Value::ObjPtr(
+            Obj::Function(Function {
+                name: name.into(),
+                arity: params.len(),
+                chunk,
+                max_stack: verified.max_stack,
+                captures,
+            })
+            .into_obj_ptr(),
+        );
+        if captures_nothing {
+            self.emit_constant(function, token.line);
+            return Ok(());
+        }
+        let idx = self.chunk.add_constant(function);
+        let idx =
+            Byte::try_from(idx).map_err(|_| CompileError::TooManyConstants { name: token })?;
+        self.emit_bytes(OpCode::Closure as u8, idx, token.line);
+        Ok(())
+    }
+
+    fn compile_if(
+        &mut self,
+        condition: &'a Expr<'a>,
+        then_branch: &'a Stmt<'a>,
+        else_branch: Option<&'a Stmt<'a>>,
+    ) -> Result<(), CompileError<'a>> {
+        if self.opt_level == OptLevel::O1 {
+            if let Some(condition) = fold_expr(condition) {
+                // only the branch that is always taken gets compiled
+                return match (condition.is_truthy(), else_branch) {
+                    (true, _) => self.compile_stmt(then_branch),
+                    (false, Some(else_branch)) => self.compile_stmt(else_branch),
+                    (false, None) => Ok(()),
+                };
+            }
+        }
+        let line = expr_line(condition);
+        self.compile_expr(condition)?;
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse, line);
+        self.emit_byte(OpCode::Pop as u8, line);
+        self.compile_stmt(then_branch)?;
+        let else_jump = self.emit_jump(OpCode::Jump, line);
+        self.patch_jump(then_jump)?;
+        self.emit_byte(OpCode::Pop as u8, line);
+        if let Some(else_branch) = else_branch {
+            self.compile_stmt(else_branch)?;
+        }
+        self.patch_jump(else_jump)
+    }
+
+    fn compile_while(
+        &mut self,
+        condition: &'a Expr<'a>,
+        body: &'a Stmt<'a>,
+        increment: Option<&'a Expr<'a>>,
+    ) -> Result<(), CompileError<'a>> {
+        let loop_start = self.chunk.code.len();
+        let line = expr_line(condition);
+        let exit_jump = match fold_expr(condition) {
+            // a constant condition either never enters the loop or never leaves it
+            Some(condition) if self.opt_level == OptLevel::O1 => {
+                if !condition.is_truthy() {
+                    return Ok(());
+                }
+                None
+            }
+            _ => {
+                self.compile_expr(condition)?;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse, line);
+                self.emit_byte(OpCode::Pop as u8, line);
+                Some(exit_jump)
+            }
+        };
+        let body_loop = self.compile_loop_body(|compiler| compiler.compile_stmt(body))?;
+        for continue_jump in body_loop.continues {
+            self.patch_jump(continue_jump)?;
+        }
+        if let Some(increment) = increment {
+            self.compile_expr(increment)?;
+            self.emit_byte(OpCode::Pop as u8, expr_line(increment));
+        }
+        self.emit_loop(loop_start, line)?;
+        if let Some(exit_jump) = exit_jump {
+            self.patch_jump(exit_jump)?;
+            self.emit_byte(OpCode::Pop as u8, line);
+        }
+        for break_jump in body_loop.breaks {
+            self.patch_jump(break_jump)?;
+        }
+        Ok(())
+    }
+
+    // Compiles the body of a loop with `compile_body` and returns the jumps out
+    // of it that still need patching.
+    fn compile_loop_body(
+        &mut self,
+        compile_body: impl FnOnce(&mut Self) -> Result<(), CompileError<'a>>,
+    ) -> Result<Loop, CompileError<'a>> {
+        self.loops.push(Loop {
+            scope_depth: self.scope_depth,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+        let result = compile_body(self);
+        let body_loop = self.loops.pop().unwrap();
+        result.map(|_| body_loop)
+    }
+
+    fn compile_loop_jump(&mut self, keyword: &'a Token<'a>) -> Result<(), CompileError<'a>> {
+        let scope_depth = match self.loops.last() {
+            Some(innermost) => innermost.scope_depth,
+            None => return Err(CompileError::OutsideLoop { keyword }),
+        };
+        let loop_tries = (self.tries.iter().rev())
+            .take_while(|entered| entered.loops == self.loops.len())
+            .count();
+        let top = self.leave_tries(self.tries.len() - loop_tries, false, keyword)?;
+        // the locals stay declared for the code after the jump, which is still
+        // in their scope
+        let body_locals = (self.locals[..top].iter().rev())
+            .take_while(|local| local.depth.is_some_and(|depth| depth > scope_depth))
+            .count();
+        self.discard_locals(top - body_locals, top, keyword.line);
+        let jump = self.emit_jump(OpCode::Jump, keyword.line);
+        let innermost = self.loops.last_mut().unwrap();
+        match keyword.token_type {
+            TokenType::Break => innermost.breaks.push(jump),
+            _ => innermost.continues.push(jump),
+        }
+        Ok(())
+    }
+
+    // pops the locals from `to` up to `from` off the stack, while they stay
+    // declared
+    fn discard_locals(&mut self, to: usize, from: usize, line: usize) {
+        let captured = (self.locals[to..from].iter().rev())
+            .map(|local| local.captured)
+            .collect::<Vec<_>>();
+        for captured in captured {
+            self.discard_local(captured, line);
+        }
+    }
+
+    // Leaves the `try` statements from `tries[first..]`, innermost first, for
+    // a jump out of them: pops their locals, removes their handlers and runs
+    // their `finally` clauses. With `keep_value` the value on top of the stack
+    // is kept on top, which is what `return` returns. Returns the number of
+    // locals still on the stack.
+    fn leave_tries(
+        &mut self,
+        first: usize,
+        keep_value: bool,
+        keyword: &'a Token<'a>,
+    ) -> Result<usize, CompileError<'a>> {
+        let line = keyword.line;
+        let mut top = self.locals.len();
+        for idx in (first..self.tries.len()).rev() {
+            let Try {
+                locals,
+                loops,
+                finally,
+            } = self.tries[idx];
+            if keep_value && top > locals {
+                let depth = Byte::try_from(top - locals)
+                    .map_err(|_| CompileError::TooManyLocals { name: keyword })?;
+                self.emit_bytes(OpCode::Bury as u8, depth, line);
+            }
+            self.discard_locals(locals, top, line);
+            top = locals;
+            self.emit_byte(OpCode::PopTry as u8, line);
+            let Some(finally) = finally else {
+                continue;
+            };
+            // the clause only sees what is around the statement
+            let inner_locals = self.locals.split_off(locals);
+            let inner_tries = self.tries.split_off(idx);
+            let inner_loops = self.loops.split_off(loops);
+            if keep_value {
+                self.begin_scope();
+                self.add_hidden_local(b"return value", keyword)?;
+            }
+            let result = self.compile_stmt(finally);
+            if keep_value {
+                // the value stays on the stack for the next clause
+                self.locals.pop();
+                self.scope_depth -= 1;
+            }
+            self.locals.extend(inner_locals);
+            self.tries.extend(inner_tries);
+            self.loops.extend(inner_loops);
+            result?;
+        }
+        Ok(top)
+    }
+
+    // The body runs with a handler installed, which starts with the exception
+    // on top of the locals around the statement. A `finally` clause is
+    // compiled once for every way out of the statement: after the body, after
+    // the `catch` clause, for exceptions that aren't caught, which it throws
+    // again afterwards, and for every jump out of the statement.
+    fn compile_try(
+        &mut self,
+        keyword: &'a Token<'a>,
+        body: &'a Stmt<'a>,
+        catch: Option<&'a (&'a Token<'a>, Box<Stmt<'a>>)>,
+        finally: Option<&'a Stmt<'a>>,
+    ) -> Result<(), CompileError<'a>> {
+        let line = keyword.line;
+        let handler_jump = self.emit_jump(OpCode::SetupTry, line);
+        self.compile_guarded(body, finally, self.locals.len())?;
+        self.emit_byte(OpCode::PopTry as u8, line);
+        if let Some(finally) = finally {
+            self.compile_stmt(finally)?;
+        }
+        let mut end_jumps = vec![self.emit_jump(OpCode::Jump, line)];
+        self.patch_jump(handler_jump)?;
+
+        if let Some((name, handler)) = catch {
+            self.begin_scope();
+            self.declare_local(name)?;
+            self.mark_initialized();
+            let Some(finally) = finally else {
+                self.compile_stmt(handler)?;
+                self.end_scope();
+                return self.patch_jumps(end_jumps);
+            };
+            // exceptions thrown by the `catch` clause run the `finally` clause too
+            let finally_jump = self.emit_jump(OpCode::SetupTry, line);
+            // the caught exception is popped before jumps out of the clause run it
+            self.compile_guarded(handler, Some(finally), self.locals.len() - 1)?;
+            self.emit_byte(OpCode::PopTry as u8, line);
+            let captured = self.locals.last().unwrap().captured;
+            self.end_scope();
+            self.compile_stmt(finally)?;
+            end_jumps.push(self.emit_jump(OpCode::Jump, line));
+            // the new exception takes the place of the caught one
+            self.patch_jump(finally_jump)?;
+            self.emit_bytes(OpCode::Bury as u8, 1, line);
+            self.discard_local(captured, line);
+        }
+
+        if let Some(finally) = finally {
+            self.begin_scope();
+            self.add_hidden_local(b"exception", keyword)?;
+            let slot = (self.locals.len() - 1) as Byte;
+            self.compile_stmt(finally)?;
+            self.emit_bytes(OpCode::GetLocal as u8, slot, line);
+            self.emit_byte(OpCode::Throw as u8, line);
+            // nothing runs after throwing, so the exception isn't popped
+            self.locals.pop();
+            self.scope_depth -= 1;
+        }
+        self.patch_jumps(end_jumps)
+    }
+
+    // compiles `stmt` while the handler of a `try` statement with `finally`
+    // as its `finally` clause is installed, with `locals` locals around it
+    fn compile_guarded(
+        &mut self,
+        stmt: &'a Stmt<'a>,
+        finally: Option<&'a Stmt<'a>>,
+        locals: usize,
+    ) -> Result<(), CompileError<'a>> {
+        self.tries.push(Try {
+            locals,
+            loops: self.loops.len(),
+            finally,
+        });
+        let result = self.compile_stmt(stmt);
+        self.tries.pop();
+        result
+    }
+
+    fn patch_jumps(&mut self, jumps: Vec<usize>) -> Result<(), CompileError<'a>> {
+        for jump in jumps {
+            self.patch_jump(jump)?;
+        }
+        Ok(())
+    }
+
+    // The sequence and its iterator live in locals that can't be named from
+    // Lox, which `ForIter` reads and updates on every iteration. The element
+    // it pushes becomes the loop variable, in a scope of its own.
+    fn compile_for_in(
+        &mut self,
+        name: &'a Token<'a>,
+        iterable: &'a Expr<'a>,
+        body: &'a Stmt<'a>,
+    ) -> Result<(), CompileError<'a>> {
+        self.begin_scope();
+        self.compile_expr(iterable)?;
+        self.emit_byte(OpCode::Nil as u8, name.line);
+        self.add_hidden_local(b"for sequence", name)?;
+        self.add_hidden_local(b"for iterator", name)?;
+        let sequence_slot = (self.locals.len() - 2) as Byte;
+
+        let loop_start = self.chunk.code.len();
+        self.emit_bytes(OpCode::ForIter as u8, sequence_slot, name.line);
+        self.emit_bytes(0xff, 0xff, name.line);
+        let exit_jump = self.chunk.code.len() - 2;
+        // the loop variable is popped along with the locals of the body
+        let body_loop = self.compile_loop_body(|compiler| {
+            compiler.begin_scope();
+            compiler.declare_local(name)?;
+            compiler.mark_initialized();
+            compiler.compile_stmt(body)?;
+            compiler.end_scope();
+            Ok(())
+        })?;
+        for continue_jump in body_loop.continues {
+            self.patch_jump(continue_jump)?;
+        }
+        self.emit_loop(loop_start, name.line)?;
+        self.patch_jump(exit_jump)?;
+        // the nil pushed in place of an element once the loop is done
+        self.emit_byte(OpCode::Pop as u8, name.line);
+        for break_jump in body_loop.breaks {
+            self.patch_jump(break_jump)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
+    // The subject is kept in a hidden local and tested against the patterns of
+    // one case after the other. A case with several patterns jumps to its
+    // body as soon as one of them matches.
+    fn compile_match(
+        &mut self,
+        keyword: &'a Token<'a>,
+        subject: &'a Expr<'a>,
+        cases: &'a [(Vec<Pattern<'a>>, Stmt<'a>)],
+        default: Option<&'a Stmt<'a>>,
+    ) -> Result<(), CompileError<'a>> {
+        let line = keyword.line;
+        self.begin_scope();
+        self.compile_expr(subject)?;
+        self.add_hidden_local(b"match subject", keyword)?;
+        let slot = (self.locals.len() - 1) as Byte;
+
+        let mut end_jumps = Vec::new();
+        for (patterns, body) in cases {
+            let mut body_jumps = Vec::new();
+            let mut next_case = None;
+            for (idx, pattern) in patterns.iter().enumerate() {
+                self.emit_bytes(OpCode::GetLocal as u8, slot, line);
+                match pattern {
+                    Pattern::Literal(value) => {
+                        self.compile_expr(value)?;
+                        self.emit_byte(OpCode::MatchValue as u8, line);
+                    }
+                    Pattern::Range { start, end } => {
+                        self.compile_expr(start)?;
+                        self.compile_expr(end)?;
+                        self.emit_byte(OpCode::MatchRange as u8, line);
+                    }
+                }
+                let miss = self.emit_jump(OpCode::JumpIfFalse, line);
+                self.emit_byte(OpCode::Pop as u8, line);
+                if idx + 1 < patterns.len() {
+                    body_jumps.push(self.emit_jump(OpCode::Jump, line));
+                    self.patch_jump(miss)?;
+                    self.emit_byte(OpCode::Pop as u8, line);
+                } else {
+                    next_case = Some(miss);
+                }
+            }
+            for body_jump in body_jumps {
+                self.patch_jump(body_jump)?;
+            }
+            self.compile_stmt(body)?;
+            end_jumps.push(self.emit_jump(OpCode::Jump, line));
+            if let Some(next_case) = next_case {
+                self.patch_jump(next_case)?;
+                self.emit_byte(OpCode::Pop as u8, line);
+            }
+        }
+        if let Some(default) = default {
+            self.compile_stmt(default)?;
+        }
+        for end_jump in end_jumps {
+            self.patch_jump(end_jump)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
+    // Concatenates the pieces of an interpolated string, converting the values
+    // of the expressions to strings the way `print` would. Empty pieces are
+    // left out.
+    fn compile_interpolation(
+        &mut self,
+        start: &'a Token<'a>,
+        parts: &'a [Expr<'a>],
+    ) -> Result<(), CompileError<'a>> {
+        let mut first = true;
+        for part in parts {
+            match part {
+                Expr::StringLiteral([]) => continue,
+                Expr::StringLiteral(_) => self.compile_expr(part)?,
+                _ => {
+                    self.compile_expr(part)?;
+                    self.emit_byte(OpCode::Stringify as u8, start.line);
+                }
+            }
+            if !first {
+                self.emit_byte(OpCode::Add as u8, start.line);
+            }
+            first = false;
+        }
+        Ok(())
+    }
+
+    fn compile_expr(&mut self, expr: &'a Expr<'a>) -> Result<(), CompileError<'a>> {
+        if self.opt_level == OptLevel::O1 {
+            if let Some(constant) = fold_expr(expr) {
+                self.emit_folded(constant, expr_line(expr));
+                return Ok(());
+            }
+        }
+        match expr {
+            Expr::NumericLiteral(n) => self.emit_constant(Value::Number(parse_number(n)), 0), // TODO: use the actual line number
+            Expr::Unary { op, expr } => {
+                self.compile_expr(expr)?;
+                match op.token_type {
+                    TokenType::Minus => self.emit_byte(OpCode::Negate as u8, op.line),
+                    TokenType::Bang => self.emit_byte(OpCode::Not as u8, op.line),
+                    TokenType::Tilde => self.emit_byte(OpCode::BitNot as u8, op.line),
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Binary { left, op, right } => {
+                self.compile_expr(left)?;
+                self.compile_expr(right)?;
+                match op.token_type {
+                    TokenType::BangEqual => {
+                        self.emit_bytes(OpCode::Equal as u8, OpCode::Not as u8, op.line)
+                    }
+                    TokenType::GreaterEqual => {
+                        self.emit_bytes(OpCode::Less as u8, OpCode::Not as u8, op.line)
+                    }
+                    TokenType::LessEqual => {
+                        self.emit_bytes(OpCode::Greater as u8, OpCode::Not as u8, op.line)
+                    }
+                    op_type => self.emit_byte(binary_instruction(op_type) as u8, op.line),
+                }
+            }
+            Expr::Logical { left, op, right } => self.compile_logical(left, op, right)?,
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => self.compile_conditional(condition, then_branch, else_branch)?,
+            Expr::Grouping(expr) => self.compile_expr(expr)?,
+            Expr::NilLiteral => self.emit_byte(OpCode::Nil as u8, 0), // TODO: use actual line number
+            Expr::TrueLiteral => self.emit_byte(OpCode::True as u8, 0), // TODO: use actual line number
+            Expr::FalseLiteral => self.emit_byte(OpCode::False as u8, 0), // TODO: use actual line number
+            Expr::StringLiteral(bytestring) => {
+                let ptr = scanner::unescape(bytestring).as_slice().into();
+                self.emit_constant(
+                    Value::ObjPtr(ptr),
+                    0, // TODO: use actual line number
+                )
+            }
+            Expr::Interpolation { start, parts } => self.compile_interpolation(start, parts)?,
+            Expr::Variable(name) => {
+                let (instruction, operand) = self.resolve_variable(name, false)?;
+                self.emit_bytes(instruction as u8, operand, name.line);
+            }
+            Expr::Assign { name, value } => {
+                self.compile_expr(value)?;
+                let (instruction, operand) = self.resolve_variable(name, true)?;
+                self.emit_bytes(instruction as u8, operand, name.line);
+            }
+            Expr::Compound { target, op, value } => {
+                self.compile_update(target, op, Some(value), false)?
+            }
+            Expr::Increment {
+                target,
+                op,
+                postfix,
+            } => self.compile_update(target, op, None, *postfix)?,
+            Expr::Lambda {
+                keyword,
+                params,
+                body,
+            } => self.compile_function(keyword, b"lambda", params, body)?,
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                let method = match callee.as_ref() {
+                    Expr::Get { object, name } => {
+                        self.compile_expr(object)?;
+                        Some(self.identifier_constant(name)?)
+                    }
+                    callee => {
+                        self.compile_expr(callee)?;
+                        None
+                    }
+                };
+                for argument in arguments {
+                    self.compile_expr(argument)?;
+                }
+                let arg_count = Byte::try_from(arguments.len())
+                    .map_err(|_| CompileError::TooManyArguments { paren })?;
+                match method {
+                    Some(name) => {
+                        self.emit_bytes(OpCode::Invoke as u8, name, paren.line);
+                        self.emit_byte(arg_count, paren.line);
+                    }
+                    None => self.emit_bytes(OpCode::Call as u8, arg_count, paren.line),
+                }
+            }
+            Expr::Get { object, name } => {
+                self.compile_expr(object)?;
+                let name_constant = self.identifier_constant(name)?;
+                self.emit_bytes(OpCode::GetProperty as u8, name_constant, name.line);
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                self.compile_expr(object)?;
+                self.compile_expr(value)?;
+                let name_constant = self.identifier_constant(name)?;
+                self.emit_bytes(OpCode::SetProperty as u8, name_constant, name.line);
+            }
+            Expr::List { bracket, elements } => {
+                for element in elements {
+                    self.compile_expr(element)?;
+                }
+                let count = Byte::try_from(elements.len())
+                    .map_err(|_| CompileError::TooManyElements { bracket })?;
+                self.emit_bytes(OpCode::BuildList as u8, count, bracket.line);
+            }
+            Expr::Map { brace, entries } => {
+                for (key, value) in entries {
+                    self.compile_expr(key)?;
+                    self.compile_expr(value)?;
+                }
+                let count = Byte::try_from(entries.len())
+                    .map_err(|_| CompileError::TooManyEntries { brace })?;
+                self.emit_bytes(OpCode::BuildMap as u8, count, brace.line);
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                self.compile_expr(object)?;
+                self.compile_expr(index)?;
+                self.emit_byte(OpCode::GetIndex as u8, bracket.line);
+            }
+            Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                self.compile_expr(object)?;
+                self.compile_expr(index)?;
+                self.compile_expr(value)?;
+                self.emit_byte(OpCode::SetIndex as u8, bracket.line);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_conditional(
+        &mut self,
+        condition: &'a Expr<'a>,
+        then_branch: &'a Expr<'a>,
+        else_branch: &'a Expr<'a>,
+    ) -> Result<(), CompileError<'a>> {
+        if self.opt_level == OptLevel::O1 {
+            if let Some(condition) = fold_expr(condition) {
+                return match condition.is_truthy() {
+                    true => self.compile_expr(then_branch),
+                    false => self.compile_expr(else_branch),
+                };
+            }
+        }
+        self.compile_expr(condition)?;
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse, 0);
+        self.emit_byte(OpCode::Pop as u8, 0);
+        self.compile_expr(then_branch)?;
+        let else_jump = self.emit_jump(OpCode::Jump, 0);
+        self.patch_jump(then_jump)?;
+        self.emit_byte(OpCode::Pop as u8, 0);
+        self.compile_expr(else_branch)?;
+        self.patch_jump(else_jump)
+    }
+
+    // Assigns the result of applying `op` to the target and `value`, or to the
+    // target and 1 for `++` and `--`. The object and index of a field or an
+    // element are evaluated once and duplicated for reading and writing it.
+    // Postfix updates bury a copy of the old value under them, which is left
+    // once the new one is popped.
+    fn compile_update(
+        &mut self,
+        target: &'a Expr<'a>,
+        op: &'a Token<'a>,
+        value: Option<&'a Expr<'a>>,
+        postfix: bool,
+    ) -> Result<(), CompileError<'a>> {
+        let line = op.line;
+        let property = match target {
+            Expr::Get { name, .. } => Some(self.identifier_constant(name)?),
+            _ => None,
+        };
+        let operands = match (target, property) {
+            (Expr::Variable(name), _) => {
+                let (instruction, operand) = self.resolve_variable(name, false)?;
+                self.emit_bytes(instruction as u8, operand, line);
+                0
+            }
+            (Expr::Get { object, .. }, Some(name_constant)) => {
+                self.compile_expr(object)?;
+                self.emit_bytes(OpCode::Dup as u8, 1, line);
+                self.emit_bytes(OpCode::GetProperty as u8, name_constant, line);
+                1
+            }
+            (Expr::Index { object, index, .. }, _) => {
+                self.compile_expr(object)?;
+                self.compile_expr(index)?;
+                self.emit_bytes(OpCode::Dup as u8, 2, line);
+                self.emit_byte(OpCode::GetIndex as u8, line);
+                2
+            }
+            _ => unreachable!("the parser only allows assignable targets"),
+        };
+        if postfix {
+            self.emit_bytes(OpCode::Dup as u8, 1, line);
+            if operands > 0 {
+                self.emit_bytes(OpCode::Bury as u8, operands + 1, line);
+            }
+        }
+        match value {
+            Some(value) => self.compile_expr(value)?,
+            None => self.emit_constant(Value::Number(1.0), line),
+        }
+        self.emit_byte(binary_instruction(op.token_type) as u8, line);
+        match (target, property) {
+            (Expr::Variable(name), _) => {
+                let (instruction, operand) = self.resolve_variable(name, true)?;
+                self.emit_bytes(instruction as u8, operand, line);
+            }
+            (_, Some(name_constant)) => {
+                self.emit_bytes(OpCode::SetProperty as u8, name_constant, line)
+            }
+            _ => self.emit_byte(OpCode::SetIndex as u8, line),
+        }
+        if postfix {
+            self.emit_byte(OpCode::Pop as u8, line);
+        }
+        Ok(())
+    }
+
+    fn compile_logical(
+        &mut self,
+        left: &'a Expr<'a>,
+        op: &'a Token<'a>,
+        right: &'a Expr<'a>,
+    ) -> Result<(), CompileError<'a>> {
+        if self.opt_level == OptLevel::O1 {
+            if let Some(left) = fold_expr(left) {
+                // short circuiting is already decided by the constant operand
+                return match (op.token_type, left.is_truthy()) {
+                    (TokenType::Or, true) | (TokenType::And, false) => {
+                        self.emit_folded(left, op.line);
+                        Ok(())
+                    }
+                    _ => self.compile_expr(right),
+                };
+            }
+        }
+        self.compile_expr(left)?;
+        match op.token_type {
+            TokenType::And => {
+                let end_jump = self.emit_jump(OpCode::JumpIfFalse, op.line);
+                self.emit_byte(OpCode::Pop as u8, op.line);
+                self.compile_expr(right)?;
+                self.patch_jump(end_jump)
+            }
+            TokenType::Or => {
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse, op.line);
+                let end_jump = self.emit_jump(OpCode::Jump, op.line);
+                self.patch_jump(else_jump)?;
+                self.emit_byte(OpCode::Pop as u8, op.line);
+                self.compile_expr(right)?;
+                self.patch_jump(end_jump)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+pub fn compile<'a>(
+    statements: &'a [Stmt<'a>],
+    chunk: &mut Chunk,
+    opt_level: OptLevel,
+    mode: CompileMode,
+) -> Result<(), CompileError<'a>> {
+    let start = chunk.code.len();
+    let mut compiler = Compiler::new(chunk, opt_level);
+    compiler.mode = mode;
+    compiler.compile_stmts(statements)?;
+    if opt_level == OptLevel::O1 {
+        chunk.peephole(start);
+    }
+    Ok(())
+}
+
+// Compiles top level code into a function of its own that ends in `Halt`, so
+// every chunk of code run by the VM is a fresh function.
+pub fn compile_script<'a>(
+    statements: &'a [Stmt<'a>],
+    opt_level: OptLevel,
+    mode: CompileMode,
+) -> Result<Function, CompileError<'a>> {
+    let mut chunk = Chunk::default();
+    compile(statements, &mut chunk, opt_level, mode)?;
+    chunk.write(OpCode::Halt as u8, 0);
+    let verified = chunk
+        .verify(0, 0, 0)
+        .expect("compiler emitted invalid bytecode");
+    Ok(Function {
+        name: b"script".to_vec(),
+        arity: 0,
+        chunk,
+        max_stack: verified.max_stack,
+        captures: Vec::new(),
+    })
+}
@@ -0,0 +1,510 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chunk::Chunk,
+        compiler::{compile, CompileError, CompileMode, OptLevel},
+        object::Capture,
+        opcode::OpCode,
+        parser::parse,
+        scanner::scan,
+        value::Value,
+    };
+
+    fn compile_source(source: &str, opt_level: OptLevel) -> Chunk {
+        let tokens = scan(source.as_bytes()).unwrap();
+        let (statements, errors) = parse(&tokens);
+        assert!(errors.is_empty());
+        let mut chunk = Chunk::default();
+        compile(&statements, &mut chunk, opt_level, CompileMode::Script).unwrap();
+        chunk
+    }
+
+    #[test]
+    fn compile_arithmetic() {
+        let chunk = compile_source("print 1 + 2 * 3;", OptLevel::O0);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::Constant as u8,
+                0,
+                OpCode::Constant as u8,
+                1,
+                OpCode::Constant as u8,
+                2,
+                OpCode::Multiply as u8,
+                OpCode::Add as u8,
+                OpCode::Print as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_locals_and_globals() {
+        let chunk = compile_source("var a = 1; { var b = a; b = 2; }", OptLevel::O0);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::Constant as u8,
+                0,
+                OpCode::DefineGlobal as u8,
+                1,
+                OpCode::GetGlobal as u8,
+                2,
+                OpCode::Constant as u8,
+                3,
+                OpCode::SetLocal as u8,
+                0,
+                OpCode::Pop as u8,
+                OpCode::Pop as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_if() {
+        let chunk = compile_source("if (false) print 1;", OptLevel::O0);
+        assert_eq!(chunk.code[0], OpCode::False as u8);
+        assert_eq!(chunk.code[1], OpCode::JumpIfFalse as u8);
+    }
+
+    #[test]
+    fn statements_take_the_line_of_their_expression() {
+        let chunk = compile_source("var a;\nprint a;\na = 1;", OptLevel::O0);
+        // literals don't know their line
+        assert_eq!(chunk.lines, vec![(3, 1), (3, 2), (2, 0), (3, 3)]);
+    }
+
+    #[test]
+    fn report_reading_local_in_own_initializer() {
+        let tokens = scan(b"{ var a = a; }").unwrap();
+        let (statements, _) = parse(&tokens);
+        let mut chunk = Chunk::default();
+        assert!(compile(&statements, &mut chunk, OptLevel::O0, CompileMode::Script).is_err());
+    }
+
+    #[test]
+    fn fold_arithmetic() {
+        let chunk = compile_source("print 1 + 2 * 3;", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![OpCode::Constant as u8, 0, OpCode::Print as u8]
+        );
+        assert_eq!(chunk.constants, vec![Value::Number(7f64)]);
+    }
+
+    #[test]
+    fn fold_comparison_and_not() {
+        let chunk = compile_source("print 1 != 2; print 3 <= 2; print !nil;", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::True as u8,
+                OpCode::Print as u8,
+                OpCode::False as u8,
+                OpCode::Print as u8,
+                OpCode::True as u8,
+                OpCode::Print as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn fold_string_concatenation() {
+        let chunk = compile_source("print \"foo\" + \"bar\";", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![OpCode::Constant as u8, 0, OpCode::Print as u8]
+        );
+        let ptr = chunk.constants[0].as_obj_ptr().unwrap();
+        assert_eq!(ptr.as_string(), b"foobar");
+    }
+
+    #[test]
+    fn fold_string_equality() {
+        let chunk = compile_source(
+            "print \"a\" + \"b\" == \"ab\"; print \"a\" != \"a\";",
+            OptLevel::O1,
+        );
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::True as u8,
+                OpCode::Print as u8,
+                OpCode::False as u8,
+                OpCode::Print as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn fold_leaves_type_errors_for_runtime() {
+        let chunk = compile_source("print -\"foo\";", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::Constant as u8,
+                0,
+                OpCode::Negate as u8,
+                OpCode::Print as u8
+            ]
+        );
+    }
+
+    #[test]
+    fn fold_inside_non_constant_expression() {
+        let chunk = compile_source("print a + -(1 + 1);", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::GetGlobal as u8,
+                0,
+                OpCode::AddConstant as u8,
+                1,
+                OpCode::Print as u8,
+            ]
+        );
+        assert_eq!(chunk.constants[1], Value::Number(-2f64));
+    }
+
+    #[test]
+    fn fold_new_operators() {
+        let chunk = compile_source(
+            "print 7 % 4 + 2 ** 3; print ~1 ^ 1 << 2; print 1 > 2 ? 3 : 4;",
+            OptLevel::O1,
+        );
+        assert_eq!(
+            chunk.constants,
+            vec![
+                Value::Number(11f64),
+                Value::Number(-6f64),
+                Value::Number(4f64)
+            ]
+        );
+        // a bitwise operator on a fraction is left for the VM to reject
+        let chunk = compile_source("print 1.5 & 1;", OptLevel::O1);
+        assert_eq!(chunk.code[4], OpCode::BitAnd as u8);
+    }
+
+    #[test]
+    fn conditional_with_constant_condition() {
+        let chunk = compile_source("print nil ? a : b;", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![OpCode::GetGlobal as u8, 0, OpCode::Print as u8]
+        );
+        let chunk = compile_source("print c ? a : b;", OptLevel::O1);
+        assert_eq!(chunk.code[2], OpCode::JumpIfFalse as u8);
+    }
+
+    #[test]
+    fn update_evaluates_target_once() {
+        let chunk = compile_source("{ var l; var i; l[i] *= 2; l[i]--; }", OptLevel::O0);
+        assert_eq!(
+            chunk.code[2..],
+            vec![
+                OpCode::GetLocal as u8,
+                0,
+                OpCode::GetLocal as u8,
+                1,
+                OpCode::Dup as u8,
+                2,
+                OpCode::GetIndex as u8,
+                OpCode::Constant as u8,
+                0,
+                OpCode::Multiply as u8,
+                OpCode::SetIndex as u8,
+                OpCode::Pop as u8,
+                // the old value ends up below the list and the index
+                OpCode::GetLocal as u8,
+                0,
+                OpCode::GetLocal as u8,
+                1,
+                OpCode::Dup as u8,
+                2,
+                OpCode::GetIndex as u8,
+                OpCode::Dup as u8,
+                1,
+                OpCode::Bury as u8,
+                3,
+                OpCode::Constant as u8,
+                1,
+                OpCode::Subtract as u8,
+                OpCode::SetIndex as u8,
+                OpCode::Pop as u8,
+                OpCode::Pop as u8,
+                OpCode::Pop as u8,
+                OpCode::Pop as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn eliminate_if_false() {
+        let chunk = compile_source("if (false) print 1; print 2;", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![OpCode::Constant as u8, 0, OpCode::Print as u8]
+        );
+        assert_eq!(chunk.constants, vec![Value::Number(2f64)]);
+    }
+
+    #[test]
+    fn eliminate_if_false_keeps_else_branch() {
+        let chunk = compile_source("if (1 > 2) print 1; else print 2;", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![OpCode::Constant as u8, 0, OpCode::Print as u8]
+        );
+        assert_eq!(chunk.constants, vec![Value::Number(2f64)]);
+    }
+
+    #[test]
+    fn eliminate_while_false() {
+        let chunk = compile_source("while (nil) print 1;", OptLevel::O1);
+        assert!(chunk.code.is_empty());
+    }
+
+    #[test]
+    fn eliminate_code_after_return() {
+        let chunk = compile_source("{ return 1; print 2; } print 3;", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![OpCode::Constant as u8, 0, OpCode::Return as u8]
+        );
+    }
+
+    #[test]
+    fn eliminate_code_after_if_returning_on_both_branches() {
+        let chunk = compile_source("if (a) return 1; else return 2; print 3;", OptLevel::O1);
+        assert_eq!(chunk.code.last(), Some(&(OpCode::Return as u8)));
+        assert_eq!(chunk.constants.len(), 3);
+    }
+
+    #[test]
+    fn folded_constants_keep_the_line_of_the_expression() {
+        let chunk = compile_source("print 1 +\n2;\nprint 3 > 4;", OptLevel::O1);
+        assert_eq!(chunk.lines, vec![(3, 1), (2, 3)]);
+    }
+
+    #[test]
+    fn fuse_comparisons() {
+        let chunk = compile_source("print a >= b; print a <= b; print a != b;", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::GetGlobal as u8,
+                0,
+                OpCode::GetGlobal as u8,
+                1,
+                OpCode::GreaterEqual as u8,
+                OpCode::Print as u8,
+                OpCode::GetGlobal as u8,
+                2,
+                OpCode::GetGlobal as u8,
+                3,
+                OpCode::LessEqual as u8,
+                OpCode::Print as u8,
+                OpCode::GetGlobal as u8,
+                4,
+                OpCode::GetGlobal as u8,
+                5,
+                OpCode::NotEqual as u8,
+                OpCode::Print as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn fuse_local_reads() {
+        let chunk = compile_source("{ var a = 1; var b = 2; print a + b; }", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::Constant as u8,
+                0,
+                OpCode::Constant as u8,
+                1,
+                OpCode::GetLocalGetLocal as u8,
+                0,
+                1,
+                OpCode::Add as u8,
+                OpCode::Print as u8,
+                OpCode::Pop as u8,
+                OpCode::Pop as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn no_fusing_without_optimizations() {
+        let chunk = compile_source("print a >= b;", OptLevel::O0);
+        assert_eq!(
+            &chunk.code[4..],
+            &[OpCode::Less as u8, OpCode::Not as u8, OpCode::Print as u8]
+        );
+    }
+
+    #[test]
+    fn compile_function() {
+        let chunk = compile_source("fun add(a, b) { return a + b; }", OptLevel::O1);
+        assert_eq!(
+            chunk.code,
+            vec![OpCode::Constant as u8, 0, OpCode::DefineGlobal as u8, 1]
+        );
+        let function = chunk.constants[0].as_obj_ptr().unwrap();
+        let function = function.as_function().unwrap();
+        assert_eq!(function.arity, 2);
+        assert_eq!(function.max_stack, 5);
+        assert_eq!(
+            function.chunk.code,
+            vec![
+                OpCode::GetLocalGetLocal as u8,
+                1,
+                2,
+                OpCode::Add as u8,
+                OpCode::Return as u8
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_call() {
+        let chunk = compile_source("f(1, 2);", OptLevel::O0);
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::GetGlobal as u8,
+                0,
+                OpCode::Constant as u8,
+                1,
+                OpCode::Constant as u8,
+                2,
+                OpCode::Call as u8,
+                2,
+                OpCode::Pop as u8
+            ]
+        );
+    }
+
+    #[test]
+    fn implicit_return_of_nil() {
+        let chunk = compile_source("fun f() { print 1; }", OptLevel::O1);
+        let function = chunk.constants[0].as_obj_ptr().unwrap();
+        assert_eq!(
+            function.as_function().unwrap().chunk.code,
+            vec![
+                OpCode::Constant as u8,
+                0,
+                OpCode::Print as u8,
+                OpCode::Nil as u8,
+                OpCode::Return as u8
+            ]
+        );
+    }
+
+    #[test]
+    fn close_over_captured_locals() {
+        let source = "{ var x = 1; var y = 2; fun f() { return x; } }";
+        let chunk = compile_source(source, OptLevel::O1);
+        // `y` and `f` aren't captured by anything
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::Constant as u8,
+                0,
+                OpCode::Constant as u8,
+                1,
+                OpCode::Closure as u8,
+                2,
+                OpCode::Pop as u8,
+                OpCode::Pop as u8,
+                OpCode::CloseUpvalue as u8
+            ]
+        );
+        let function = chunk.constants[2].as_obj_ptr().unwrap();
+        let function = function.as_function().unwrap();
+        assert_eq!(function.captures, vec![Capture::Local(0)]);
+        assert_eq!(
+            function.chunk.code,
+            vec![OpCode::GetUpvalue as u8, 0, OpCode::Return as u8]
+        );
+    }
+
+    #[test]
+    fn capture_through_enclosing_functions() {
+        let source = "fun a(x) { fun b() { fun c() { return x; } return c; } return b; }";
+        let chunk = compile_source(source, OptLevel::O1);
+        let a = chunk.constants[0].as_obj_ptr().unwrap();
+        let a = a.as_function().unwrap();
+        let b = a.chunk.constants[0].as_obj_ptr().unwrap();
+        let b = b.as_function().unwrap();
+        let c = b.chunk.constants[0].as_obj_ptr().unwrap();
+        let c = c.as_function().unwrap();
+        assert_eq!(a.captures, vec![]);
+        assert_eq!(b.captures, vec![Capture::Local(1)]);
+        assert_eq!(c.captures, vec![Capture::Upvalue(0)]);
+    }
+
+    #[test]
+    fn reject_break_and_continue_outside_loops() {
+        for source in [
+            "break;",
+            "if (true) { continue; }",
+            "while (true) { fun f() { break; } }",
+        ] {
+            let tokens = scan(source.as_bytes()).unwrap();
+            let (statements, _) = parse(&tokens);
+            let mut chunk = Chunk::default();
+            assert!(matches!(
+                compile(&statements, &mut chunk, OptLevel::O0, CompileMode::Script),
+                Err(CompileError::OutsideLoop { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn break_pops_locals_of_the_body() {
+        let chunk = compile_source(
+            "while (a) { var x = 1; { var y = 2; break; } }",
+            OptLevel::O1,
+        );
+        let jump = chunk.code.len() - 9;
+        assert_eq!(
+            chunk.code[jump - 2..jump + 1],
+            [OpCode::Pop as u8, OpCode::Pop as u8, OpCode::Jump as u8]
+        );
+        // past the `Pop` of the condition at the end of the loop
+        let offset = u16::from_be_bytes([chunk.code[jump + 1], chunk.code[jump + 2]]);
+        assert_eq!(jump + 3 + offset as usize, chunk.code.len());
+    }
+
+    #[test]
+    fn return_runs_finally_clause() {
+        let source = "fun f() { try { var x = 1; return x; } finally { g(); } }";
+        let chunk = compile_source(source, OptLevel::O1);
+        let function = chunk.constants[0].as_obj_ptr().unwrap();
+        let function = function.as_function().unwrap();
+        // the value being returned goes below the local, which is popped
+        // along with the handler before the clause runs
+        assert_eq!(
+            function.chunk.code[3..17],
+            [
+                OpCode::Constant as u8,
+                0,
+                OpCode::GetLocal as u8,
+                1,
+                OpCode::Bury as u8,
+                1,
+                OpCode::Pop as u8,
+                OpCode::PopTry as u8,
+                OpCode::GetGlobal as u8,
+                1,
+                OpCode::Call as u8,
+                0,
+                OpCode::Pop as u8,
+                OpCode::Return as u8
+            ]
+        );
+        assert_eq!(function.chunk.code[0], OpCode::SetupTry as u8);
+    }
+}
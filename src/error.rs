@@ -1,9 +1,56 @@
-use std::{error::Error, fmt::Display};
+use std::{error::Error, fmt::Display, io};
+
+use crate::chunk::VerifyError;
+use crate::value::Value;
 
 #[derive(Debug)]
 pub enum RuntimeError {
     OperandMustBeNumber,
     OperandsMustBeNumber,
+    // operands of the bitwise operators, which have to be whole numbers that
+    // fit in 64 bits
+    OperandMustBeInteger,
+    OperandsMustBeIntegers,
+    ShiftOutOfRange,
+    UndefinedVariable(String),
+    InvalidBytecode(VerifyError),
+    StackOverflow,
+    NotCallable,
+    WrongArity { expected: usize, got: usize },
+    Output(io::Error),
+    OnlyInstancesHaveProperties,
+    OnlyInstancesHaveFields,
+    UndefinedProperty(String),
+    // returned by a constructor or method of a host class
+    Native(String),
+    // more instructions ran than the limit set by the host
+    StepLimit(u64),
+    NotIndexable,
+    IndexMustBeInteger,
+    IndexOutOfBounds { index: f64, len: usize },
+    PopFromEmptyList,
+    InvalidKey,
+    UndefinedKey(String),
+    NotIterable,
+    ArgumentMustBeString,
+    SubstringOutOfBounds { start: f64, end: f64, len: usize },
+    ComparisonMustBeNumber,
+    // a value thrown by `throw` on its way to a handler, which never makes it
+    // back to the host
+    Thrown(Value),
+    // a thrown value that no handler caught, as it would be printed
+    Uncaught(String),
+}
+
+impl RuntimeError {
+    // whether a `catch` clause can handle the error, which it can't for
+    // errors about the host or the bytecode rather than the program
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            RuntimeError::InvalidBytecode(_) | RuntimeError::Output(_) | RuntimeError::StepLimit(_)
+        )
+    }
 }
 
 impl Error for RuntimeError {}
@@ -13,6 +60,85 @@ impl Display for RuntimeError {
         match self {
             RuntimeError::OperandMustBeNumber => write!(f, "Operand should be number"),
             RuntimeError::OperandsMustBeNumber => write!(f, "Operands should be number"),
+            RuntimeError::OperandMustBeInteger => write!(f, "Operand should be an integer"),
+            RuntimeError::OperandsMustBeIntegers => write!(f, "Operands should be integers"),
+            RuntimeError::ShiftOutOfRange => {
+                write!(f, "Shift amount should be between 0 and 63")
+            }
+            RuntimeError::UndefinedVariable(name) => write!(f, "Undefined variable {}", name),
+            RuntimeError::InvalidBytecode(error) => write!(f, "{}", error),
+            RuntimeError::StackOverflow => write!(f, "Stack overflow"),
+            RuntimeError::NotCallable => write!(f, "Can only call functions"),
+            RuntimeError::WrongArity { expected, got } => {
+                write!(f, "Expected {} arguments but got {}", expected, got)
+            }
+            RuntimeError::Output(error) => write!(f, "Failed to write output: {}", error),
+            RuntimeError::OnlyInstancesHaveProperties => {
+                write!(f, "Only instances have properties")
+            }
+            RuntimeError::OnlyInstancesHaveFields => write!(f, "Only instances have fields"),
+            RuntimeError::UndefinedProperty(name) => write!(f, "Undefined property {}", name),
+            RuntimeError::Native(message) => write!(f, "{}", message),
+            RuntimeError::StepLimit(max_steps) => {
+                write!(f, "Exceeded the limit of {} steps", max_steps)
+            }
+            RuntimeError::NotIndexable => write!(f, "Can only index lists and maps"),
+            RuntimeError::IndexMustBeInteger => write!(f, "Index must be a whole number"),
+            RuntimeError::IndexOutOfBounds { index, len } => write!(
+                f,
+                "Index {} is out of bounds for a list of length {}",
+                index, len
+            ),
+            RuntimeError::PopFromEmptyList => write!(f, "Can't pop from an empty list"),
+            RuntimeError::InvalidKey => {
+                write!(f, "Map keys must be strings, numbers, booleans or nil")
+            }
+            RuntimeError::UndefinedKey(key) => write!(f, "Undefined key {}", key),
+            RuntimeError::NotIterable => write!(
+                f,
+                "Can only iterate over lists, maps, strings, ranges and instances"
+            ),
+            RuntimeError::ArgumentMustBeString => write!(f, "Argument should be a string"),
+            RuntimeError::SubstringOutOfBounds { start, end, len } => write!(
+                f,
+                "Substring {}..{} is out of bounds for a string of length {}",
+                start, end, len
+            ),
+            RuntimeError::ComparisonMustBeNumber => {
+                write!(f, "Comparison function should return a number")
+            }
+            RuntimeError::Thrown(_) => write!(f, "Uncaught exception"),
+            RuntimeError::Uncaught(value) => write!(f, "Uncaught exception: {}", value),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum InterpretError {
+    // scan, parse and compile errors, formatted because they borrow from the source
+    Compile(Vec<String>),
+    Runtime(RuntimeError),
+}
+
+impl Error for InterpretError {}
+
+impl Display for InterpretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpretError::Compile(errors) => write!(f, "{}", errors.join("\n")),
+            InterpretError::Runtime(error) => write!(f, "{}", error),
         }
     }
 }
+
+impl From<RuntimeError> for InterpretError {
+    fn from(error: RuntimeError) -> Self {
+        InterpretError::Runtime(error)
+    }
+}
+
+impl From<io::Error> for RuntimeError {
+    fn from(error: io::Error) -> Self {
+        RuntimeError::Output(error)
+    }
+}
@@ -1,8 +1,15 @@
-use crate::{byte_string::ByteSlice, token::Token};
+use crate::{byte_string::ByteSlice, stmt::Stmt, token::Token};
 
 #[derive(Debug, PartialEq)]
 pub enum Expr<'a> {
+    // the contents between the quotes, escape sequences and all
     StringLiteral(&'a ByteSlice),
+    // an interpolated string, alternating between the pieces of the string
+    // and the expressions in it
+    Interpolation {
+        start: &'a Token<'a>,
+        parts: Vec<Expr<'a>>,
+    },
     NumericLiteral(&'a ByteSlice),
     TrueLiteral,
     FalseLiteral,
@@ -21,6 +28,12 @@ pub enum Expr<'a> {
         op: &'a Token<'a>,
         right: Box<Expr<'a>>,
     },
+    // `condition ? then_branch : else_branch`
+    Conditional {
+        condition: Box<Expr<'a>>,
+        then_branch: Box<Expr<'a>>,
+        else_branch: Box<Expr<'a>>,
+    },
     Grouping(Box<Expr<'a>>),
     Variable(&'a Token<'a>),
     Assign {
@@ -34,4 +47,56 @@ pub enum Expr<'a> {
         paren: &'a Token<'a>,
         arguments: Vec<Expr<'a>>,
     },
+    Get {
+        object: Box<Expr<'a>>,
+        name: &'a Token<'a>,
+    },
+    Set {
+        object: Box<Expr<'a>>,
+        name: &'a Token<'a>,
+        value: Box<Expr<'a>>,
+    },
+    List {
+        // closing bracket, for reporting errors about the list
+        bracket: &'a Token<'a>,
+        elements: Vec<Expr<'a>>,
+    },
+    Map {
+        // closing brace, for reporting errors about the map
+        brace: &'a Token<'a>,
+        entries: Vec<(Expr<'a>, Expr<'a>)>,
+    },
+    Index {
+        object: Box<Expr<'a>>,
+        bracket: &'a Token<'a>,
+        index: Box<Expr<'a>>,
+    },
+    SetIndex {
+        object: Box<Expr<'a>>,
+        bracket: &'a Token<'a>,
+        index: Box<Expr<'a>>,
+        value: Box<Expr<'a>>,
+    },
+    // `target op= value`, where the target is a variable, a field or an
+    // element and only gets evaluated once
+    Compound {
+        target: Box<Expr<'a>>,
+        op: &'a Token<'a>,
+        value: Box<Expr<'a>>,
+    },
+    // `++` or `--` before or after a target like that of `Compound`, which
+    // evaluates to the updated value before it and the old one after it
+    Increment {
+        target: Box<Expr<'a>>,
+        op: &'a Token<'a>,
+        postfix: bool,
+    },
+    // `fun (params) { ... }` or `(params) => body`, `keyword` being the `fun`
+    // or the arrow. An arrow followed by an expression returns it, which
+    // ends up as the body.
+    Lambda {
+        keyword: &'a Token<'a>,
+        params: Vec<&'a Token<'a>>,
+        body: Box<Stmt<'a>>,
+    },
 }
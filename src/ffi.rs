@@ -0,0 +1,249 @@
+// C API for embedding the interpreter, declared in `include/rlox.h`. Every
+// function here is unsafe to call with pointers that didn't come from this
+// API or that have already been freed.
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    ptr,
+};
+
+use crate::{error::InterpretError, host::NativeContext, interpreter::Interpreter, value::Value};
+
+pub struct LoxVM {
+    interpreter: Interpreter,
+    // message of the error returned by the last call to `lox_interpret`
+    error: Option<CString>,
+}
+
+// Values handed to C. Strings are copied out of the VM so that they stay valid
+// for as long as the handle does, other objects are referred to directly and
+// can be freed by the garbage collector once Lox code can't reach them.
+pub enum LoxValue {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(CString),
+    Object(Value),
+    // returned by native callbacks to raise a runtime error
+    Error(String),
+}
+
+#[repr(C)]
+pub enum LoxType {
+    Nil,
+    Bool,
+    Number,
+    String,
+    Object,
+    Error,
+}
+
+#[repr(C)]
+pub enum LoxResult {
+    Ok,
+    CompileError,
+    RuntimeError,
+}
+
+pub type LoxNativeFn =
+    unsafe extern "C" fn(*mut c_void, c_int, *const *const LoxValue) -> *mut LoxValue;
+
+impl LoxValue {
+    fn from_value(value: Value) -> Self {
+        if value.is_nil() {
+            LoxValue::Nil
+        } else if let Some(boolean) = value.as_boolean() {
+            LoxValue::Bool(boolean)
+        } else if let Some(number) = value.as_number() {
+            LoxValue::Number(number)
+        } else if let Some(string) = value.as_str() {
+            // C strings end at the first nul byte anyway
+            let end = string.find('\0').unwrap_or(string.len());
+            LoxValue::String(CString::new(&string[..end]).unwrap())
+        } else {
+            LoxValue::Object(value)
+        }
+    }
+
+    // `string` allocates strings in the VM
+    fn to_value(&self, string: impl FnOnce(&str) -> Value) -> Result<Value, String> {
+        match self {
+            LoxValue::Nil => Ok(Value::Nil),
+            LoxValue::Bool(boolean) => Ok(Value::Boolean(*boolean)),
+            LoxValue::Number(number) => Ok(Value::Number(*number)),
+            LoxValue::String(contents) => Ok(string(&contents.to_string_lossy())),
+            LoxValue::Object(value) => Ok(*value),
+            LoxValue::Error(message) => Err(message.clone()),
+        }
+    }
+}
+
+unsafe fn to_str<'a>(string: *const c_char) -> std::borrow::Cow<'a, str> {
+    CStr::from_ptr(string).to_string_lossy()
+}
+
+fn into_handle(value: LoxValue) -> *mut LoxValue {
+    Box::into_raw(Box::new(value))
+}
+
+#[no_mangle]
+pub extern "C" fn lox_vm_new() -> *mut LoxVM {
+    Box::into_raw(Box::new(LoxVM {
+        interpreter: Interpreter::new(),
+        error: None,
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lox_vm_free(vm: *mut LoxVM) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lox_interpret(vm: *mut LoxVM, source: *const c_char) -> LoxResult {
+    let vm = &mut *vm;
+    // the scanner reports invalid UTF-8 with its line
+    let result = vm.interpreter.eval(CStr::from_ptr(source).to_bytes());
+    vm.error = result
+        .as_ref()
+        .err()
+        .map(|error| CString::new(error.to_string().replace('\0', "")).unwrap());
+    match result {
+        Ok(_) => LoxResult::Ok,
+        Err(InterpretError::Compile(_)) => LoxResult::CompileError,
+        Err(InterpretError::Runtime(_)) => LoxResult::RuntimeError,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lox_last_error(vm: *const LoxVM) -> *const c_char {
+    match &(*vm).error {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lox_get_global(vm: *const LoxVM, name: *const c_char) -> *mut LoxValue {
+    match (*vm).interpreter.get_global(&to_str(name)) {
+        Some(value) => into_handle(LoxValue::from_value(value)),
+        None => ptr::null_mut(),
+    }
+}
+
+// Returns false if `value` is an error, which can't be stored in a variable.
+#[no_mangle]
+pub unsafe extern "C" fn lox_set_global(
+    vm: *mut LoxVM,
+    name: *const c_char,
+    value: *const LoxValue,
+) -> bool {
+    let interpreter = &mut (*vm).interpreter;
+    match (*value).to_value(|string| interpreter.string(string)) {
+        Ok(value) => {
+            interpreter.set_global(&to_str(name), value);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+// The callback gets `user_data` and handles to the arguments, which are freed
+// once it returns, and has to return a new handle, which the VM frees. A null
+// return value is nil.
+#[no_mangle]
+pub unsafe extern "C" fn lox_define_native(
+    vm: *mut LoxVM,
+    name: *const c_char,
+    arity: c_int,
+    callback: LoxNativeFn,
+    user_data: *mut c_void,
+) {
+    let function = move |context: &mut NativeContext, args: &[Value]| {
+        let args = args
+            .iter()
+            .map(|arg| into_handle(LoxValue::from_value(*arg)))
+            .collect::<Vec<_>>();
+        let result = callback(user_data, args.len() as c_int, args.as_ptr().cast());
+        for arg in args {
+            drop(Box::from_raw(arg));
+        }
+        if result.is_null() {
+            return Ok(Value::Nil);
+        }
+        let result = Box::from_raw(result);
+        result.to_value(|string| context.string(string))
+    };
+    (*vm)
+        .interpreter
+        .define_native(&to_str(name), arity as usize, function);
+}
+
+#[no_mangle]
+pub extern "C" fn lox_nil() -> *mut LoxValue {
+    into_handle(LoxValue::Nil)
+}
+
+#[no_mangle]
+pub extern "C" fn lox_bool(boolean: bool) -> *mut LoxValue {
+    into_handle(LoxValue::Bool(boolean))
+}
+
+#[no_mangle]
+pub extern "C" fn lox_number(number: f64) -> *mut LoxValue {
+    into_handle(LoxValue::Number(number))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lox_string(string: *const c_char) -> *mut LoxValue {
+    into_handle(LoxValue::String(CStr::from_ptr(string).into()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lox_error(message: *const c_char) -> *mut LoxValue {
+    into_handle(LoxValue::Error(to_str(message).into_owned()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_type(value: *const LoxValue) -> LoxType {
+    match &*value {
+        LoxValue::Nil => LoxType::Nil,
+        LoxValue::Bool(_) => LoxType::Bool,
+        LoxValue::Number(_) => LoxType::Number,
+        LoxValue::String(_) => LoxType::String,
+        LoxValue::Object(_) => LoxType::Object,
+        LoxValue::Error(_) => LoxType::Error,
+    }
+}
+
+// false for anything but booleans
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_as_bool(value: *const LoxValue) -> bool {
+    matches!(&*value, LoxValue::Bool(true))
+}
+
+// 0 for anything but numbers
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_as_number(value: *const LoxValue) -> f64 {
+    match &*value {
+        LoxValue::Number(number) => *number,
+        _ => 0f64,
+    }
+}
+
+// Null for anything but strings. The string is owned by the handle.
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_as_string(value: *const LoxValue) -> *const c_char {
+    match &*value {
+        LoxValue::String(string) => string.as_ptr(),
+        _ => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_free(value: *mut LoxValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
@@ -0,0 +1,305 @@
+mod test;
+
+// Formats Lox source in one standard style for `rlox-bytecode fmt`. The parser
+// desugars `for` loops and the scanner drops comments, so rather than printing
+// the syntax tree this lays out the tokens again, picking up the comments from
+// the source between them. Blank lines between statements are kept, but no
+// more than one in a row.
+use crate::{
+    byte_string::ByteSlice, error::InterpretError, parser::parse, scanner::scan, token::Token,
+    token_type::TokenType,
+};
+
+const INDENT: &str = "    ";
+
+struct Formatter {
+    out: String,
+    // the line being laid out, without its indentation
+    line: String,
+    indent: usize,
+    // open parentheses, `;` inside them doesn't end a line (`for` loops)
+    parens: usize,
+    // whether each open brace started a map rather than a block
+    braces: Vec<bool>,
+    // the nesting of each `?` still waiting for its `:`, which unlike the
+    // colons in maps gets spaces around it
+    questions: Vec<usize>,
+    prev: Option<TokenType>,
+    // whether `prev` was a unary operator, which sticks to its operand
+    prev_unary: bool,
+}
+
+// tokens after which `-` subtracts rather than negates
+fn ends_operand(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::Identifier
+            | TokenType::Number
+            | TokenType::String
+            | TokenType::RightParen
+            | TokenType::RightBracket
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::This
+            // as in `x++`, nothing this matters for can follow a prefix `++`
+            | TokenType::PlusPlus
+            | TokenType::MinusMinus
+    )
+}
+
+fn offset(source: &ByteSlice, token: &Token) -> usize {
+    match token.token_type {
+        // the lexeme of the end of file token doesn't point into the source
+        TokenType::Eof => source.len(),
+        _ => token.lexeme.as_ptr() as usize - source.as_ptr() as usize,
+    }
+}
+
+impl Formatter {
+    fn new() -> Self {
+        Self {
+            out: String::new(),
+            line: String::new(),
+            indent: 0,
+            parens: 0,
+            braces: Vec::new(),
+            questions: Vec::new(),
+            prev: None,
+            prev_unary: false,
+        }
+    }
+
+    fn end_line(&mut self) {
+        if !self.line.is_empty() {
+            self.out.push_str(&INDENT.repeat(self.indent));
+            self.out.push_str(self.line.trim_end());
+            self.out.push('\n');
+            self.line.clear();
+        }
+    }
+
+    fn blank_line(&mut self) {
+        self.end_line();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") && !self.out.ends_with("{\n") {
+            self.out.push('\n');
+        }
+    }
+
+    // Lays out the comments in the whitespace between two tokens. Comments on
+    // the line of the previous token stay there, the others get lines of
+    // their own.
+    fn gap(&mut self, gap: &str, before_closing_brace: bool) {
+        let mut newlines = 0;
+        for (idx, segment) in gap.split('\n').enumerate() {
+            if idx > 0 {
+                newlines += 1;
+            }
+            let segment = segment.trim();
+            if segment.is_empty() {
+                continue;
+            }
+            if newlines == 0 && !self.line.is_empty() {
+                self.line.push(' ');
+                self.line.push_str(segment);
+            } else if newlines == 0 && self.prev.is_some() {
+                // the previous token already ended its line
+                self.out.pop();
+                self.out.push(' ');
+                self.out.push_str(segment);
+                self.out.push('\n');
+                continue;
+            } else {
+                if newlines > 1 {
+                    self.blank_line();
+                }
+                self.end_line();
+                self.line.push_str(segment);
+            }
+            self.end_line();
+            newlines = 0;
+        }
+        if newlines > 1 && self.line.is_empty() && !before_closing_brace {
+            self.blank_line();
+        }
+    }
+
+    fn nesting(&self) -> usize {
+        self.parens + self.braces.len()
+    }
+
+    // whether a `:` belongs to the innermost `?` rather than a map
+    fn ends_condition(&self) -> bool {
+        self.questions.last() == Some(&self.nesting())
+    }
+
+    fn space_before(&self, token_type: TokenType) -> bool {
+        let prev = match self.prev {
+            Some(prev) => prev,
+            None => return false,
+        };
+        if self.line.is_empty() || self.prev_unary {
+            return false;
+        }
+        let after_opening = matches!(
+            prev,
+            TokenType::LeftParen
+                | TokenType::LeftBracket
+                | TokenType::LeftBrace
+                | TokenType::Dot
+                | TokenType::DotDot
+                | TokenType::Interpolation
+        );
+        match token_type {
+            TokenType::Colon => self.ends_condition(),
+            // postfix `++` and `--` stick to their operand
+            TokenType::PlusPlus | TokenType::MinusMinus if ends_operand(prev) => false,
+            TokenType::RightParen
+            | TokenType::RightBracket
+            | TokenType::Comma
+            | TokenType::Semicolon
+            | TokenType::Dot
+            | TokenType::DotDot => false,
+            // calls, parameter lists and indexing, but not groupings and lists
+            TokenType::LeftParen | TokenType::LeftBracket => !ends_operand(prev) && !after_opening,
+            // the braces of blocks start lines of their own, so this only
+            // keeps maps from getting a space after their opening brace
+            _ => !after_opening,
+        }
+    }
+
+    // Braces at the start of a statement open a block, anywhere else they
+    // open a map, the same as in the parser.
+    fn starts_map(&self) -> bool {
+        !matches!(
+            self.prev,
+            None | Some(
+                TokenType::Semicolon
+                    | TokenType::LeftBrace
+                    | TokenType::RightBrace
+                    | TokenType::RightParen
+                    | TokenType::Else
+                    | TokenType::Arrow
+                    | TokenType::Try
+                    | TokenType::Finally
+            )
+        )
+    }
+
+    fn token(&mut self, token: &Token, next: TokenType) {
+        let token_type = token.token_type;
+        let lexeme = String::from_utf8_lossy(token.lexeme);
+        let in_map = self.braces.last() == Some(&true);
+        match token_type {
+            TokenType::LeftBrace if self.starts_map() => {
+                if self.space_before(token_type) {
+                    self.line.push(' ');
+                }
+                self.line.push('{');
+                self.braces.push(true);
+            }
+            TokenType::RightBrace if in_map => {
+                self.line.push('}');
+                self.braces.pop();
+            }
+            TokenType::LeftBrace if next == TokenType::RightBrace => {
+                // the closing brace of an empty block goes on the same line
+                if self.space_before(token_type) {
+                    self.line.push(' ');
+                }
+                self.line.push('{');
+                self.braces.push(false);
+            }
+            TokenType::LeftBrace => {
+                if self.space_before(token_type) {
+                    self.line.push(' ');
+                }
+                self.line.push('{');
+                self.end_line();
+                self.indent += 1;
+                self.braces.push(false);
+            }
+            TokenType::RightBrace if self.prev == Some(TokenType::LeftBrace) => {
+                self.line.push('}');
+                self.braces.pop();
+            }
+            TokenType::RightBrace => {
+                self.end_line();
+                self.indent = self.indent.saturating_sub(1);
+                self.line.push('}');
+                self.braces.pop();
+            }
+            // the rest of an interpolated string after an expression
+            TokenType::String | TokenType::Interpolation if token.lexeme[0] == b'}' => {
+                self.line.push_str(&lexeme);
+            }
+            _ => {
+                if self.space_before(token_type) {
+                    self.line.push(' ');
+                }
+                self.line.push_str(&lexeme);
+            }
+        }
+        match token_type {
+            TokenType::LeftParen => self.parens += 1,
+            TokenType::RightParen => self.parens = self.parens.saturating_sub(1),
+            TokenType::Semicolon if self.parens == 0 => self.end_line(),
+            TokenType::Question => self.questions.push(self.nesting()),
+            TokenType::Colon if self.ends_condition() => {
+                self.questions.pop();
+            }
+            // the body of a lambda can be followed by the rest of the expression
+            TokenType::RightBrace
+                if !in_map
+                    && !matches!(
+                        next,
+                        TokenType::Else
+                            | TokenType::Catch
+                            | TokenType::Finally
+                            | TokenType::Semicolon
+                            | TokenType::RightParen
+                            | TokenType::Comma
+                    ) =>
+            {
+                self.end_line()
+            }
+            _ => (),
+        }
+        self.prev_unary = match token_type {
+            TokenType::Bang | TokenType::Tilde => true,
+            TokenType::Minus | TokenType::PlusPlus | TokenType::MinusMinus => {
+                !self.prev.is_some_and(ends_operand)
+            }
+            _ => false,
+        };
+        self.prev = Some(token_type);
+    }
+}
+
+pub fn format_source(source: impl AsRef<[u8]>) -> Result<String, InterpretError> {
+    let bytes = source.as_ref();
+    let tokens = scan(bytes).map_err(|error| InterpretError::Compile(vec![error.to_string()]))?;
+    let (_, errors) = parse(&tokens);
+    if !errors.is_empty() {
+        let errors = errors.iter().map(|error| error.to_string()).collect();
+        return Err(InterpretError::Compile(errors));
+    }
+
+    // the scanner only accepts valid UTF-8
+    let source = std::str::from_utf8(bytes).unwrap();
+    let mut formatter = Formatter::new();
+    let mut end = 0;
+    for (idx, token) in tokens.iter().enumerate() {
+        let start = offset(bytes, token);
+        let before_closing_brace = token.token_type == TokenType::RightBrace;
+        formatter.gap(&source[end..start], before_closing_brace);
+        if token.token_type == TokenType::Eof {
+            break;
+        }
+        let next = tokens[idx + 1].token_type;
+        formatter.token(token, next);
+        end = start + token.lexeme.len();
+    }
+    formatter.end_line();
+    Ok(formatter.out)
+}
@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod tests {
+    use crate::formatter::format_source;
+
+    #[test]
+    fn test_layout() {
+        let source = "var a=1;fun add(x,y){return x+y;}\nif(a>=1){print add(a,-2);}else print !true;\nfor(var i=0;i<3;i=i+1){print i;}{}\nwhile (a) { a = a.b(1).c; }\nvar l=[ 1,[2]];l [0]=l[1][0]-1;\nvar m={ \"a\" :{},1:[ ]};{print m;}\nfor(var x in 0 .. a){print x;}\nprint \"${ x+1 }\\t${ \"${x}\" }\";\nmatch(x){case 1,-2..3=>{print x;} default=>print 0;}\nvar f=fun(a){return a;};print l.map(( x )=>x*2);\nl.sort((a,b)=>{return a-b;});";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "\
+var a = 1;
+fun add(x, y) {
+    return x + y;
+}
+if (a >= 1) {
+    print add(a, -2);
+} else print !true;
+for (var i = 0; i < 3; i = i + 1) {
+    print i;
+}
+{}
+while (a) {
+    a = a.b(1).c;
+}
+var l = [1, [2]];
+l[0] = l[1][0] - 1;
+var m = {\"a\": {}, 1: []};
+{
+    print m;
+}
+for (var x in 0..a) {
+    print x;
+}
+print \"${x + 1}\\t${\"${x}\"}\";
+match (x) {
+    case 1, -2..3 => {
+        print x;
+    }
+    default => print 0;
+}
+var f = fun (a) {
+    return a;
+};
+print l.map((x) => x * 2);
+l.sort((a, b) => {
+    return a - b;
+});
+"
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        let source = "var a=b?{\"k\":c?1:2}:-d;a+=~b%2**-c;\nfor(var i=0;i<n;i++)l[i]--;\nprint ++a-a-- - --b|1<<2;";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "\
+var a = b ? {\"k\": c ? 1 : 2} : -d;
+a += ~b % 2 ** -c;
+for (var i = 0; i < n; i++) l[i]--;
+print ++a - a-- - --b | 1 << 2;
+"
+        );
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let source =
+            "try{throw {\"k\":1};}catch(e){print e;}finally{}\ntry {\nf();\n}\nfinally{g();}";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "\
+try {
+    throw {\"k\": 1};
+} catch (e) {
+    print e;
+} finally {}
+try {
+    f();
+} finally {
+    g();
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_comments_and_blank_lines() {
+        let source = "// header\n\n\n\nvar a = 1; // one\n\nfun f() {\n\n  // inside\n  return (a - 1) * -a;\n\n}\n// trailer\n";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "\
+// header
+
+var a = 1; // one
+
+fun f() {
+    // inside
+    return (a - 1) * -a;
+}
+// trailer
+"
+        );
+    }
+
+    #[test]
+    fn test_idempotent() {
+        let source = "fun fib(n){if(n<2)return n;// base\nreturn fib(n-1)+fib(n-2);}\n\n\nprint fib(10)==55 and \"a\"!=\"b\";";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_rejects_invalid_source() {
+        assert!(format_source("var = 1;").is_err());
+        assert!(format_source("print \"a").is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use std::{any::Any, collections::HashMap, collections::LinkedList, fmt::Debug};
+
+use crate::byte_string::ByteVector;
+use crate::object::{Obj, ObjPtr};
+use crate::value::Value;
+
+// Creates the payload of a new instance from the arguments the class was
+// called with.
+pub type Constructor = fn(&mut NativeContext, &[Value]) -> Result<Box<dyn Any>, String>;
+
+// Called with the payload of the instance and the arguments of the call.
+// Methods check the number and types of their arguments themselves.
+pub type NativeMethod = fn(&mut NativeContext, &mut dyn Any, &[Value]) -> Result<Value, String>;
+
+// A function implemented by the host. It is boxed rather than a plain `fn`
+// so that it can carry state, like the callbacks registered through the C API.
+pub type NativeFunction = Box<dyn Fn(&mut NativeContext, &[Value]) -> Result<Value, String>>;
+
+// Reports every value the payload holds on to, so the garbage collector
+// doesn't free objects that are only reachable through it.
+pub type TraceHook = fn(&dyn Any, &mut dyn FnMut(Value));
+
+// A class implemented in Rust. Calling it from Lox creates an instance
+// carrying the payload returned by the constructor, which its methods get to
+// downcast. Instances can also have fields set on them from Lox, and fields
+// are looked up before methods.
+pub struct HostClass {
+    pub(crate) name: ByteVector,
+    pub(crate) arity: usize,
+    pub(crate) constructor: Constructor,
+    pub(crate) methods: HashMap<ByteVector, NativeMethod>,
+    pub(crate) trace: Option<TraceHook>,
+}
+
+impl Debug for HostClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HostClass")
+            .field("name", &String::from_utf8_lossy(&self.name))
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HostClass {
+    pub fn new(name: &str, arity: usize, constructor: Constructor) -> Self {
+        Self {
+            name: name.into(),
+            arity,
+            constructor,
+            methods: HashMap::new(),
+            trace: None,
+        }
+    }
+
+    pub fn method(mut self, name: &str, method: NativeMethod) -> Self {
+        self.methods.insert(name.into(), method);
+        self
+    }
+
+    // only needed when the payload holds Lox values
+    pub fn trace(mut self, trace: TraceHook) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+}
+
+// What constructors and native methods can do to the VM while they run.
+pub struct NativeContext<'vm> {
+    objects: &'vm mut LinkedList<ObjPtr>,
+}
+
+impl<'vm> NativeContext<'vm> {
+    pub(crate) fn new(objects: &'vm mut LinkedList<ObjPtr>) -> Self {
+        Self { objects }
+    }
+
+    pub fn string(&mut self, string: &str) -> Value {
+        let ptr = ObjPtr::from(string.as_bytes());
+        self.objects.push_back(ptr);
+        Value::ObjPtr(ptr)
+    }
+
+    pub fn list(&mut self, elements: Vec<Value>) -> Value {
+        let ptr = Obj::List(elements).into_obj_ptr();
+        self.objects.push_back(ptr);
+        Value::ObjPtr(ptr)
+    }
+}
@@ -0,0 +1,220 @@
+mod test;
+
+use std::io::Write;
+
+use crate::{
+    compiler::{CompileMode, OptLevel},
+    error::{InterpretError, RuntimeError},
+    host::{HostClass, NativeContext},
+    object::Obj,
+    parser::parse,
+    scanner::{scan, scan_error::ScanError},
+    token_type::TokenType,
+    value::Value,
+    vm::{HeapStats, RunStats, VM},
+};
+
+// Embeddable Lox interpreter. Everything defined at the top level by one call
+// to `eval` can be used by the following ones.
+#[derive(Debug)]
+pub struct Interpreter {
+    vm: VM,
+    opt_level: OptLevel,
+    mode: CompileMode,
+    trace: bool,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            vm: VM::new(),
+            opt_level: OptLevel::default(),
+            mode: CompileMode::default(),
+            trace: false,
+        }
+    }
+
+    pub fn set_opt_level(&mut self, opt_level: OptLevel) {
+        self.opt_level = opt_level;
+    }
+
+    // In `Repl` mode expression statements at the top level print their
+    // value, like in the REPL. Scripts only print with `print`.
+    pub fn set_compile_mode(&mut self, mode: CompileMode) {
+        self.mode = mode;
+    }
+
+    // `print` statements write to `out`, stdout by default
+    pub fn set_output(&mut self, out: impl Write + 'static) {
+        self.vm.set_output(Box::new(out));
+    }
+
+    // traces go to `err`, stderr by default
+    pub fn set_error_output(&mut self, err: impl Write + 'static) {
+        self.vm.set_error_output(Box::new(err));
+    }
+
+    // collects garbage before every allocation, for testing the collector
+    pub fn set_gc_stress(&mut self, gc_stress: bool) {
+        self.vm.set_gc_stress(gc_stress);
+    }
+
+    // Fails with a runtime error once more than `max_steps` instructions have
+    // run since the interpreter was created, `None` for no limit.
+    pub fn set_max_steps(&mut self, max_steps: Option<u64>) {
+        self.vm.set_max_steps(max_steps);
+    }
+
+    // Counting the instructions that run for `stats` slows down the
+    // interpreter, so it has to be turned on.
+    pub fn set_count_instructions(&mut self, count_instructions: bool) {
+        self.vm.set_count_instructions(count_instructions);
+    }
+
+    pub fn stats(&self) -> RunStats {
+        self.vm.stats()
+    }
+
+    // prints the compiled code and every instruction as it runs
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    // Runs `source` as top level code, returning the value of a top level
+    // `return` or nil if there is none.
+    pub fn eval(&mut self, source: impl AsRef<[u8]>) -> Result<Value, InterpretError> {
+        self.vm
+            .run(source.as_ref(), self.opt_level, self.mode, self.trace)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.vm.get_global(name.as_bytes())
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.vm.set_global(name.as_bytes(), value);
+    }
+
+    // Compiles `source` without running it and returns the disassembly of the
+    // top level code followed by that of the functions defined in it.
+    pub fn disassemble(&self, source: impl AsRef<[u8]>) -> Result<String, InterpretError> {
+        let script = VM::compile(source.as_ref(), self.opt_level, self.mode)?;
+        let mut out = Vec::new();
+        let mut functions = vec![&script];
+        while let Some(function) = functions.pop() {
+            let name = String::from_utf8_lossy(&function.name);
+            (function.chunk)
+                .disassemble(&name, &mut out)
+                .expect("writing to a Vec can't fail");
+            let nested = (function.chunk.constants.iter()).filter_map(|constant| {
+                match constant.as_obj_ptr()?.as_obj() {
+                    Obj::Function(function) => Some(function),
+                    _ => None,
+                }
+            });
+            functions.extend(nested.rev());
+        }
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    // reports the errors compiling `source` would run into, without running it
+    pub fn compile(&self, source: impl AsRef<[u8]>) -> Result<(), InterpretError> {
+        VM::compile(source.as_ref(), self.opt_level, self.mode).map(|_| ())
+    }
+
+    // reports syntax errors in `source`, without compiling it
+    pub fn check(source: impl AsRef<[u8]>) -> Result<(), InterpretError> {
+        Self::ast(source).map(|_| ())
+    }
+
+    // the statements `source` parses into, as printed by `Debug`
+    pub fn ast(source: impl AsRef<[u8]>) -> Result<String, InterpretError> {
+        let tokens = scan(source.as_ref())
+            .map_err(|error| InterpretError::Compile(vec![error.to_string()]))?;
+        let (statements, errors) = parse(&tokens);
+        if !errors.is_empty() {
+            let errors = errors.iter().map(|error| error.to_string()).collect();
+            return Err(InterpretError::Compile(errors));
+        }
+        Ok(format!("{:#?}", statements))
+    }
+
+    // every global sorted by name
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        let mut globals = (self.vm.globals())
+            .map(|(name, value)| (String::from_utf8_lossy(name).into_owned(), value))
+            .collect::<Vec<_>>();
+        globals.sort_by(|(a, _), (b, _)| a.cmp(b));
+        globals
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.vm.heap_stats()
+    }
+
+    // Starts over with no globals, including classes and native functions
+    // registered by the host. Values handed out earlier can't be used anymore.
+    pub fn reset(&mut self) {
+        self.vm.reset();
+    }
+
+    // makes `function` available to Lox code as a global called `name`
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&mut NativeContext, &[Value]) -> Result<Value, String> + 'static,
+    ) {
+        self.vm.define_native(name, arity, Box::new(function));
+    }
+
+    // Strings passed to Lox have to be allocated by the interpreter. Like any
+    // other object the string is freed once Lox code can't reach it anymore.
+    pub fn string(&mut self, string: &str) -> Value {
+        self.vm.new_string(string)
+    }
+
+    // makes `class` available to Lox code as a global with the same name
+    pub fn register_class(&mut self, class: HostClass) {
+        self.vm.define_host_class(class);
+    }
+
+    // Frees the objects no longer reachable from the globals. Values handed
+    // out earlier that refer to such objects can't be used afterwards.
+    pub fn collect_garbage(&mut self) {
+        self.vm.collect_garbage();
+    }
+
+    // Calls the global function `name`, returning what it returns.
+    pub fn call_function(&mut self, name: &str, args: &[Value]) -> Result<Value, InterpretError> {
+        let function = self
+            .get_global(name)
+            .ok_or_else(|| RuntimeError::UndefinedVariable(name.to_string()))?;
+        Ok(self.vm.call(function, args, self.trace)?)
+    }
+}
+
+// Whether `source` is cut off in the middle of a string or has brackets that
+// aren't closed yet, in which case the REPL asks for more lines.
+pub fn is_incomplete(source: &str) -> bool {
+    let tokens = match scan(source.as_bytes()) {
+        Ok(tokens) => tokens,
+        Err(ScanError::UnterminatedString(_)) => return true,
+        Err(_) => return false,
+    };
+    let mut depth = 0;
+    for token in tokens {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => depth -= 1,
+            _ => (),
+        }
+    }
+    depth > 0
+}
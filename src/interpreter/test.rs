@@ -0,0 +1,497 @@
+#[cfg(test)]
+mod tests {
+    use std::{any::Any, cell::Cell, rc::Rc};
+
+    use crate::{
+        compiler::CompileMode,
+        error::{InterpretError, RuntimeError},
+        host::{HostClass, NativeContext},
+        interpreter::{is_incomplete, Interpreter},
+        output::OutputBuffer,
+        value::Value,
+    };
+
+    #[test]
+    fn test_eval_keeps_globals() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var a = 1;").unwrap();
+        interpreter.eval("var b = a + 1;").unwrap();
+        assert_eq!(interpreter.get_global("b"), Some(Value::Number(2f64)));
+        assert_eq!(interpreter.get_global("c"), None);
+    }
+
+    #[test]
+    fn test_eval_keeps_functions_and_strings() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("fun greet(name) { return \"hi \" + name; }")
+            .unwrap();
+        interpreter.eval("var who = \"lox\";").unwrap();
+        // the chunks the function and the string came from are gone by now
+        let greeting = interpreter.eval("return greet(who);").unwrap();
+        assert_eq!(greeting.as_str(), Some("hi lox"));
+    }
+
+    #[test]
+    fn test_eval_returns_value() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval("var a = 1;").unwrap(), Value::Nil);
+        assert_eq!(
+            interpreter.eval("return 1 + 2;").unwrap(),
+            Value::Number(3f64)
+        );
+    }
+
+    #[test]
+    fn test_set_global() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("limit", Value::Number(10f64));
+        interpreter.eval("var twice = limit * 2;").unwrap();
+        assert_eq!(interpreter.get_global("twice"), Some(Value::Number(20f64)));
+    }
+
+    #[test]
+    fn test_call_function() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("fun add(a, b) { var sum = a + b; return sum; }")
+            .unwrap();
+        let sum = interpreter
+            .call_function("add", &[Value::Number(1f64), Value::Number(2f64)])
+            .unwrap();
+        assert_eq!(sum, Value::Number(3f64));
+        // the interpreter can still be used afterwards
+        interpreter.eval("var c = add(3, 4);").unwrap();
+        assert_eq!(interpreter.get_global("c"), Some(Value::Number(7f64)));
+    }
+
+    #[test]
+    fn test_call_function_errors() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("fun f(a) {} var g = 1;").unwrap();
+        assert!(matches!(
+            interpreter.call_function("f", &[]),
+            Err(InterpretError::Runtime(RuntimeError::WrongArity {
+                expected: 1,
+                got: 0
+            }))
+        ));
+        assert!(matches!(
+            interpreter.call_function("g", &[]),
+            Err(InterpretError::Runtime(RuntimeError::NotCallable))
+        ));
+        assert!(matches!(
+            interpreter.call_function("h", &[]),
+            Err(InterpretError::Runtime(RuntimeError::UndefinedVariable(_)))
+        ));
+    }
+
+    #[test]
+    fn test_errors_do_not_break_later_evals() {
+        let mut interpreter = Interpreter::new();
+        assert!(matches!(
+            interpreter.eval("var a = ;"),
+            Err(InterpretError::Compile(_))
+        ));
+        assert!(matches!(
+            interpreter.eval("{ var a = 1; var a = 2; }"),
+            Err(InterpretError::Compile(_))
+        ));
+        assert!(matches!(
+            interpreter.eval("print undefined; var b = 1;"),
+            Err(InterpretError::Runtime(_))
+        ));
+        interpreter.eval("var c = 3;").unwrap();
+        assert_eq!(interpreter.get_global("b"), None);
+        assert_eq!(interpreter.get_global("c"), Some(Value::Number(3f64)));
+    }
+
+    #[test]
+    fn test_capture_output() {
+        let out = OutputBuffer::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(out.clone());
+        interpreter
+            .eval("fun greet(name) { print \"hello \" + name; } greet(\"lox\");")
+            .unwrap();
+        // returning from the top level doesn't print anything
+        assert_eq!(
+            interpreter.eval("return 42;").unwrap(),
+            Value::Number(42f64)
+        );
+        assert_eq!(out.contents(), "hello lox\n");
+
+        // output can also be thrown away
+        interpreter.set_output(std::io::sink());
+        interpreter.eval("print 1;").unwrap();
+        assert_eq!(out.contents(), "hello lox\n");
+    }
+
+    fn counter_class() -> HostClass {
+        fn new(_: &mut NativeContext, args: &[Value]) -> Result<Box<dyn Any>, String> {
+            args[0]
+                .as_number()
+                .map(|start| Box::new(start) as Box<dyn Any>)
+                .ok_or_else(|| "Start must be a number.".to_string())
+        }
+        fn increment(
+            _: &mut NativeContext,
+            count: &mut dyn Any,
+            _: &[Value],
+        ) -> Result<Value, String> {
+            let count = count.downcast_mut::<f64>().unwrap();
+            *count += 1f64;
+            Ok(Value::Number(*count))
+        }
+        fn describe(
+            ctx: &mut NativeContext,
+            count: &mut dyn Any,
+            _: &[Value],
+        ) -> Result<Value, String> {
+            let count = count.downcast_ref::<f64>().unwrap();
+            Ok(ctx.string(&format!("counted to {}", count)))
+        }
+        HostClass::new("Counter", 1, new)
+            .method("increment", increment)
+            .method("describe", describe)
+    }
+
+    #[test]
+    fn test_host_class() {
+        let out = OutputBuffer::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(out.clone());
+        interpreter.register_class(counter_class());
+        interpreter
+            .eval(
+                "var c = Counter(1);
+                 print Counter;
+                 print c;
+                 print c.increment();
+                 var inc = c.increment;
+                 print inc;
+                 inc();
+                 print c.describe();",
+            )
+            .unwrap();
+        assert_eq!(
+            out.contents(),
+            "Counter\nCounter instance\n2\n<native fn increment>\ncounted to 3\n"
+        );
+        let described = interpreter.eval("return c.describe();").unwrap();
+        assert_eq!(described.as_str(), Some("counted to 3"));
+    }
+
+    #[test]
+    fn test_host_instance_fields() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_class(counter_class());
+        interpreter
+            .eval(
+                "var c = Counter(0);
+                 c.label = \"clicks\";
+                 fun twice(x) { return x * 2; }
+                 c.increment = twice;
+                 var a = c.label;
+                 var b = c.increment(21);",
+            )
+            .unwrap();
+        assert_eq!(
+            interpreter.get_global("a").unwrap().as_str(),
+            Some("clicks")
+        );
+        // fields shadow methods
+        assert_eq!(interpreter.get_global("b"), Some(Value::Number(42f64)));
+    }
+
+    #[test]
+    fn test_host_instance_field_updates() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_class(counter_class());
+        interpreter
+            .eval(
+                "var c = Counter(0);
+                 c.n = 1;
+                 c.n *= 3;
+                 var a = c.n++;
+                 var b = --c.n + c.n;",
+            )
+            .unwrap();
+        assert_eq!(interpreter.get_global("a"), Some(Value::Number(3f64)));
+        assert_eq!(interpreter.get_global("b"), Some(Value::Number(6f64)));
+        assert_eq!(
+            interpreter.eval("return c.n;").unwrap(),
+            Value::Number(3f64)
+        );
+    }
+
+    #[test]
+    fn test_host_instances_are_iterable() {
+        // counts down from the number it was created with
+        fn new(_: &mut NativeContext, args: &[Value]) -> Result<Box<dyn Any>, String> {
+            Ok(Box::new(args[0].as_number().unwrap()))
+        }
+        fn iterate(
+            _: &mut NativeContext,
+            from: &mut dyn Any,
+            args: &[Value],
+        ) -> Result<Value, String> {
+            let next = match args[0].as_number() {
+                Some(n) => n - 1f64,
+                None => *from.downcast_ref::<f64>().unwrap(),
+            };
+            Ok(if next > 0f64 {
+                Value::Number(next)
+            } else {
+                Value::Boolean(false)
+            })
+        }
+        fn iterator_value(
+            _: &mut NativeContext,
+            _: &mut dyn Any,
+            args: &[Value],
+        ) -> Result<Value, String> {
+            Ok(args[0])
+        }
+        let out = OutputBuffer::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(out.clone());
+        interpreter.register_class(
+            HostClass::new("Countdown", 1, new)
+                .method("iterate", iterate)
+                .method("iteratorValue", iterator_value),
+        );
+        interpreter
+            .eval("for (var n in Countdown(3)) print n; for (var n in Countdown(0)) print n;")
+            .unwrap();
+        assert_eq!(out.contents(), "3\n2\n1\n");
+        // without the methods instances can't be iterated over
+        interpreter.register_class(counter_class());
+        let error = interpreter
+            .eval("for (var n in Counter(0)) print n;")
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            InterpretError::Runtime(RuntimeError::UndefinedProperty(_))
+        ));
+    }
+
+    #[test]
+    fn test_host_class_errors() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_class(counter_class());
+        let errors = [
+            ("Counter();", "Expected 1 arguments but got 0"),
+            ("Counter(\"a\");", "Start must be a number."),
+            ("Counter(0).decrement();", "Undefined property decrement"),
+            ("print Counter(0).count;", "Undefined property count"),
+            ("var n = 1; n.x = 2;", "Only instances have fields"),
+            ("var n = 1; print n.x;", "Only instances have properties"),
+            ("Counter(0)();", "Can only call functions"),
+        ];
+        for (source, message) in errors {
+            match interpreter.eval(source) {
+                Err(InterpretError::Runtime(error)) => {
+                    assert!(error.to_string().starts_with(message), "{}", error)
+                }
+                result => panic!("{}: {:?}", source, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_host_errors_are_catchable() {
+        let out = OutputBuffer::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(out.clone());
+        interpreter.register_class(counter_class());
+        interpreter
+            .eval("try { Counter(\"a\"); } catch (e) { print e.message; }")
+            .unwrap();
+        assert_eq!(out.take(), "Start must be a number.\n");
+        // nothing is left behind by the handler for later evals
+        assert!(matches!(
+            interpreter.eval("throw Counter(1);"),
+            Err(InterpretError::Runtime(RuntimeError::Uncaught(_)))
+        ));
+        interpreter.eval("print 1;").unwrap();
+        assert_eq!(out.take(), "1\n");
+    }
+
+    // the payload of a Box holds a Lox value and counts how many boxes have
+    // been freed
+    struct LoxBox {
+        value: Value,
+        freed: Rc<Cell<usize>>,
+    }
+
+    impl Drop for LoxBox {
+        fn drop(&mut self) {
+            self.freed.set(self.freed.get() + 1);
+        }
+    }
+
+    thread_local! {
+        static FREED: Rc<Cell<usize>> = Rc::new(Cell::new(0));
+    }
+
+    fn box_class() -> HostClass {
+        fn new(_: &mut NativeContext, args: &[Value]) -> Result<Box<dyn Any>, String> {
+            Ok(Box::new(LoxBox {
+                value: args[0],
+                freed: FREED.with(Rc::clone),
+            }))
+        }
+        fn get(_: &mut NativeContext, lox_box: &mut dyn Any, _: &[Value]) -> Result<Value, String> {
+            Ok(lox_box.downcast_ref::<LoxBox>().unwrap().value)
+        }
+        fn trace(lox_box: &dyn Any, mark: &mut dyn FnMut(Value)) {
+            mark(lox_box.downcast_ref::<LoxBox>().unwrap().value);
+        }
+        HostClass::new("Box", 1, new)
+            .method("get", get)
+            .trace(trace)
+    }
+
+    #[test]
+    fn test_host_instances_are_collected() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_class(box_class());
+        let freed = FREED.with(Rc::clone);
+        interpreter
+            .eval(
+                "var kept = Box(\"a\" + \"b\");
+                 for (var i = 0; i < 10; i = i + 1) { Box(i); }",
+            )
+            .unwrap();
+        interpreter.collect_garbage();
+        assert_eq!(freed.get(), 10);
+        // the string is only reachable through the payload of `kept`
+        let value = interpreter.eval("return kept.get();").unwrap();
+        assert_eq!(value.as_str(), Some("ab"));
+
+        // enough garbage triggers collections while running
+        interpreter
+            .eval("for (var i = 0; i < 5000; i = i + 1) { Box(i); }")
+            .unwrap();
+        assert!(freed.get() > 10);
+        interpreter.eval("kept = nil;").unwrap();
+        interpreter.collect_garbage();
+        assert_eq!(freed.get(), 5011);
+    }
+
+    #[test]
+    fn test_native_functions() {
+        let mut interpreter = Interpreter::new();
+        let calls = Rc::new(Cell::new(0));
+        let counted = Rc::clone(&calls);
+        interpreter.define_native("twice", 1, move |_, args| {
+            counted.set(counted.get() + 1);
+            match args[0].as_number() {
+                Some(number) => Ok(Value::Number(number * 2f64)),
+                None => Err("Argument must be a number.".to_string()),
+            }
+        });
+        interpreter.define_native("name", 0, |context, _| Ok(context.string("lox")));
+        let greeting = interpreter.string("hi ");
+        interpreter.set_global("greeting", greeting);
+        interpreter
+            .eval("var a = twice(twice(3)); var b = greeting + name();")
+            .unwrap();
+        assert_eq!(interpreter.get_global("a"), Some(Value::Number(12f64)));
+        assert_eq!(
+            interpreter.get_global("b").unwrap().as_str(),
+            Some("hi lox")
+        );
+        assert_eq!(calls.get(), 2);
+        assert!(matches!(
+            interpreter.eval("twice(nil);"),
+            Err(InterpretError::Runtime(RuntimeError::Native(_)))
+        ));
+        assert!(matches!(
+            interpreter.eval("twice();"),
+            Err(InterpretError::Runtime(RuntimeError::WrongArity { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(!is_incomplete("print 1;"));
+        assert!(!is_incomplete(""));
+        assert!(is_incomplete("fun f() {"));
+        assert!(is_incomplete("fun f() {\n  if (a) {\n  }"));
+        assert!(is_incomplete("print (1 +"));
+        assert!(is_incomplete("var a = [1,"));
+        assert!(is_incomplete("print \"multi\nline"));
+        assert!(!is_incomplete("fun f() {\n}\n"));
+        // too many closing brackets is an error for the parser to report
+        assert!(!is_incomplete("print 1);"));
+        assert!(!is_incomplete("print @;"));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let interpreter = Interpreter::new();
+        let disassembly = interpreter
+            .disassemble("fun f(a) { return a; } print f(1);")
+            .unwrap();
+        let script = disassembly.find("== script ==").unwrap();
+        let function = disassembly.find("== f ==").unwrap();
+        assert!(script < function);
+        assert!(disassembly[..function].contains("OP_CALL"));
+        assert!(disassembly[function..].contains("OP_RETURN"));
+        assert!(matches!(
+            interpreter.disassemble("print ;"),
+            Err(InterpretError::Compile(_))
+        ));
+        // nothing is run or defined
+        assert!(interpreter.globals().is_empty());
+    }
+
+    #[test]
+    fn test_ast() {
+        let ast = Interpreter::ast("print -a;").unwrap();
+        assert!(ast.starts_with("[\n    Print("));
+        assert!(ast.contains("Unary"));
+        assert!(Interpreter::ast("print (;").is_err());
+    }
+
+    #[test]
+    fn test_globals_heap_and_reset() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("var b = 1; var s = \"a\"; s = s + s; var a = s + s;")
+            .unwrap();
+        let globals = interpreter.globals();
+        let names = globals
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "s"]);
+        let stats = interpreter.heap_stats();
+        assert_eq!(stats.objects, 2);
+        assert_eq!(stats.kinds["string"], 2);
+        assert!(stats.bytes > 0);
+
+        interpreter.reset();
+        assert!(interpreter.globals().is_empty());
+        assert_eq!(interpreter.heap_stats().objects, 0);
+        interpreter.eval("var c = 1;").unwrap();
+        assert_eq!(interpreter.get_global("c"), Some(Value::Number(1f64)));
+    }
+
+    #[test]
+    fn test_repl_mode_prints_expressions() {
+        let out = OutputBuffer::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(out.clone());
+        let source = "var a = 1; a + 1; fun f() { a; } f(); { a; } \"s\";";
+        interpreter.eval(source).unwrap();
+        assert_eq!(out.take(), "");
+
+        interpreter.set_compile_mode(CompileMode::Repl);
+        interpreter.eval(source).unwrap();
+        // declarations and expressions inside functions and blocks print nothing
+        assert_eq!(out.take(), "2\nnil\ns\n");
+    }
+}
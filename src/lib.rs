@@ -0,0 +1,32 @@
+mod builtins;
+mod byte_string;
+mod chunk;
+mod compiler;
+mod error;
+mod expr;
+mod ffi;
+mod formatter;
+mod host;
+mod interpreter;
+// kept around for reference, the interpreter uses `scanner` instead
+#[allow(dead_code)]
+mod lazy_scanner;
+mod object;
+mod opcode;
+mod output;
+mod parser;
+mod scanner;
+mod stmt;
+mod token;
+mod token_type;
+mod value;
+mod vm;
+
+pub use compiler::{CompileMode, OptLevel};
+pub use error::{InterpretError, RuntimeError};
+pub use formatter::format_source;
+pub use host::{Constructor, HostClass, NativeContext, NativeFunction, NativeMethod, TraceHook};
+pub use interpreter::{is_incomplete, Interpreter};
+pub use output::OutputBuffer;
+pub use value::Value;
+pub use vm::{HeapStats, RunStats};
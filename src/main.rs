@@ -1,74 +1,197 @@
-mod byte_string;
-mod chunk;
-mod compiler;
-mod error;
-mod expr;
-mod lazy_scanner;
-mod object;
-mod opcode;
-mod parser;
-mod scanner;
-mod stmt;
-mod token;
-mod token_type;
-mod value;
-mod vm;
+mod bench;
+mod repl;
 
 use std::{
     env,
     error::Error,
-    fs::File,
-    io::{self, BufRead, Read, Write},
+    fs,
+    io::{self, Read},
+    process,
+    time::Instant,
 };
 
-use chunk::Chunk;
+use rlox_bytecode::{format_source, InterpretError, Interpreter, OptLevel};
 
-use vm::VM;
+// exit codes from sysexits.h, like the interpreters from the book
+const EXIT_USAGE: i32 = 64;
+const EXIT_COMPILE_ERROR: i32 = 65;
+const EXIT_NO_INPUT: i32 = 66;
+const EXIT_RUNTIME_ERROR: i32 = 70;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = env::args().collect::<Vec<_>>();
-    match &args[..] {
-        [_] => run_prompt()?,
-        [_, script_name] => run_file(script_name)?,
-        [prog_name, ..] => println!("Usage: {} [script]", prog_name),
-        [] => unreachable!(),
-    }
-    Ok(())
+// `bench` is handled on its own, it takes options of its own
+const COMMANDS: [&str; 6] = ["run", "repl", "compile", "disasm", "check", "fmt"];
+
+fn help(prog_name: &str) -> String {
+    format!(
+        "\
+Usage: {prog_name} [options] [command] [script | -]
+
+Commands:
+  run <script>       run a script, the default when given a script
+  repl               start the REPL, the default without a script
+  compile <script>   compile a script and report errors without running it
+  disasm <script>    print the bytecode a script compiles to
+  check <script>     parse a script and report syntax errors
+  fmt <script>       print a script laid out in the standard style
+  bench [-n runs] <script>...
+                     time scripts, see the README
+
+A script of - is read from stdin.
+
+Options:
+  -e <code>          use <code> instead of a script
+  -O0, -O1           turn optimizations off or on, they are on by default
+  --trace            print the bytecode and every instruction as it runs
+  --gc-stress        collect garbage before every allocation
+  --max-steps <n>    fail with a runtime error after <n> instructions
+  --stats            print statistics about the run to stderr when done
+  -h, --help         print this message
+
+Exit codes: 64 for wrong usage, 65 for compile errors, 66 for scripts that
+can't be read and 70 for runtime errors."
+    )
 }
 
-fn run_file(script_name: &str) -> io::Result<()> {
-    let mut chunk = Chunk::default();
-    let mut vm = VM::new();
-    let mut file = File::open(script_name)?;
-    let mut source = String::new();
-    file.read_to_string(&mut source)?;
-    vm.run(&source, &mut chunk, true).unwrap();
-    Ok(())
+fn usage_error(prog_name: &str, message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("Run {} --help for usage.", prog_name);
+    process::exit(EXIT_USAGE);
 }
 
-fn run_prompt() -> io::Result<()> {
-    // we need to incrementally update the chunk to make this work
-    let mut chunk = Chunk::default();
-    let mut input_history: Vec<String> = Vec::new();
-    let stdin = io::stdin();
-    let mut vm = VM::new();
-    loop {
-        print!("> ");
-        io::stdout().flush()?;
-        let mut line = String::new();
-        stdin.lock().read_line(&mut line)?;
-        if line.trim().is_empty() {
-            continue;
-        } else {
-            match vm.run(&line, &mut chunk, true) {
-                Ok(_) => (),
-                Err(e) => {
-                    vm.reset_stack();
-                    print!("{}", e)
+#[derive(Default)]
+struct Options {
+    opt_level: OptLevel,
+    trace: bool,
+    gc_stress: bool,
+    max_steps: Option<u64>,
+    stats: bool,
+    code: Option<String>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().collect::<Vec<_>>();
+    let prog_name = args.first().map_or("rlox-bytecode", String::as_str);
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // everything after `bench` is for the bench runner
+            "bench" if positional.is_empty() => {
+                let rest = args.cloned().collect::<Vec<_>>();
+                if !bench::run(prog_name, &rest, options.opt_level) {
+                    process::exit(1);
                 }
-            };
-            input_history.push(line);
+                return Ok(());
+            }
+            "-h" | "--help" => {
+                println!("{}", help(prog_name));
+                return Ok(());
+            }
+            "-O0" => options.opt_level = OptLevel::O0,
+            "-O1" => options.opt_level = OptLevel::O1,
+            "--trace" => options.trace = true,
+            "--gc-stress" => options.gc_stress = true,
+            "--stats" => options.stats = true,
+            "--max-steps" => match args.next().map(|steps| steps.parse()) {
+                Some(Ok(steps)) => options.max_steps = Some(steps),
+                _ => usage_error(prog_name, "--max-steps needs a number of steps"),
+            },
+            "-e" => match args.next() {
+                Some(code) => options.code = Some(code.clone()),
+                None => usage_error(prog_name, "-e needs code to run"),
+            },
+            flag if flag.starts_with('-') && flag != "-" => {
+                usage_error(prog_name, &format!("Unknown option {}", flag))
+            }
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    let (command, script) = match positional[..] {
+        [] if options.code.is_some() => ("run", None),
+        [] => ("repl", None),
+        [command] if COMMANDS.contains(&command) => (command, None),
+        [script] => ("run", Some(script)),
+        [command, script] if COMMANDS.contains(&command) => (command, Some(script)),
+        _ => usage_error(prog_name, "Too many arguments"),
+    };
+    if command == "repl" {
+        if script.is_some() || options.code.is_some() {
+            usage_error(prog_name, "repl doesn't take a script");
+        }
+        repl::run(interpreter(&options))?;
+        return Ok(());
+    }
+    let source = match (&options.code, script) {
+        (Some(code), None) => code.clone().into_bytes(),
+        (None, Some(script)) => read_script(script),
+        (Some(_), Some(_)) => usage_error(prog_name, "Give either -e or a script, not both"),
+        (None, None) => usage_error(prog_name, &format!("{} needs a script", command)),
+    };
+
+    let mut interpreter = interpreter(&options);
+    let result = match command {
+        "run" => {
+            let start = Instant::now();
+            let result = interpreter.eval(&source).map(|_| ());
+            if options.stats {
+                print_stats(&interpreter, start);
+            }
+            result
         }
+        "compile" => interpreter.compile(&source),
+        "disasm" => interpreter
+            .disassemble(&source)
+            .map(|disassembly| print!("{}", disassembly)),
+        "check" => Interpreter::check(&source),
+        "fmt" => format_source(&source).map(|formatted| print!("{}", formatted)),
+        _ => unreachable!(),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(match error {
+            InterpretError::Compile(_) => EXIT_COMPILE_ERROR,
+            InterpretError::Runtime(_) => EXIT_RUNTIME_ERROR,
+        });
     }
     Ok(())
 }
+
+fn interpreter(options: &Options) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_opt_level(options.opt_level);
+    interpreter.set_trace(options.trace);
+    interpreter.set_gc_stress(options.gc_stress);
+    interpreter.set_max_steps(options.max_steps);
+    interpreter.set_count_instructions(options.stats);
+    interpreter
+}
+
+// `-` reads the script from stdin. The script is read as bytes so the scanner
+// can report invalid UTF-8 with its line.
+fn read_script(script: &str) -> Vec<u8> {
+    let source = if script == "-" {
+        let mut source = Vec::new();
+        io::stdin().read_to_end(&mut source).map(|_| source)
+    } else {
+        fs::read(script)
+    };
+    source.unwrap_or_else(|error| {
+        eprintln!("Could not read {}: {}", script, error);
+        process::exit(EXIT_NO_INPUT);
+    })
+}
+
+fn print_stats(interpreter: &Interpreter, start: Instant) {
+    let stats = interpreter.stats();
+    let heap = interpreter.heap_stats();
+    eprintln!("time          {:.2?}", start.elapsed());
+    eprintln!("instructions  {}", stats.instructions);
+    eprintln!("allocations   {}", stats.allocations);
+    eprintln!("collections   {}", stats.collections);
+    eprintln!(
+        "live objects  {} (about {} bytes)",
+        heap.objects, heap.bytes
+    );
+}
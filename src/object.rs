@@ -1,26 +1,37 @@
-use std::{fmt::Display, ptr::NonNull};
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::Display,
+    hash::{Hash, Hasher},
+    ptr::NonNull,
+};
 
+use crate::builtins::Builtin;
 use crate::byte_string::{ByteSlice, ByteVector};
+use crate::chunk::Chunk;
+use crate::host::{HostClass, NativeFunction, NativeMethod};
+use crate::value::Value;
 
-// TODO: Need to GC these
+// Objects allocated while running are freed by the garbage collector in the
+// VM, constants live as long as the program.
 #[repr(C)]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ObjPtr(NonNull<Obj>);
 
 impl From<&ByteSlice> for ObjPtr {
     fn from(byte_string: &ByteSlice) -> Self {
-        Obj::String(byte_string.into()).to_obj_ptr()
+        Obj::String(byte_string.into()).into_obj_ptr()
     }
 }
 
 impl From<ByteVector> for ObjPtr {
     fn from(byte_string: ByteVector) -> Self {
-        Obj::String(byte_string).to_obj_ptr()
+        Obj::String(byte_string).into_obj_ptr()
     }
 }
 
 impl Obj {
-    pub fn to_obj_ptr(self) -> ObjPtr {
+    pub fn into_obj_ptr(self) -> ObjPtr {
         let boxed_obj = Box::new(self);
         let raw_ptr = Box::into_raw(boxed_obj);
         let non_null_ptr = unsafe { NonNull::new_unchecked(raw_ptr) };
@@ -28,16 +39,317 @@ impl Obj {
     }
 }
 
-#[repr(C)]
 #[derive(Debug, Clone)]
+pub struct Function {
+    pub name: ByteVector,
+    pub arity: usize,
+    pub chunk: Chunk,
+    // most values the function ever has on the stack, including its arguments
+    pub max_stack: usize,
+    // variables of enclosing functions it uses, which a `Closure` instruction
+    // captures when it creates a closure for the function
+    pub captures: Vec<Capture>,
+}
+
+// Where a closure being created finds a variable it captures, relative to the
+// function creating it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capture {
+    // a local in the slot of the running frame
+    Local(u8),
+    // an upvalue the running closure captured itself
+    Upvalue(u8),
+}
+
+// A function along with the variables of enclosing functions it captured.
+// Only functions that capture something are turned into closures.
+#[derive(Debug)]
+pub struct Closure {
+    pub function: ObjPtr,
+    pub upvalues: Vec<ObjPtr>,
+}
+
+// A captured variable, which stays on the stack until it goes out of scope
+// and is then moved into the upvalue, so closures outliving it still see the
+// value.
+#[derive(Debug, Clone, Copy)]
+pub enum Upvalue {
+    // index of the stack slot the variable lives in
+    Open(usize),
+    Closed(Value),
+}
+
+// instance of a `HostClass`
+#[derive(Debug)]
+pub struct Foreign {
+    pub class: ObjPtr,
+    pub fields: HashMap<ByteVector, Value>,
+    pub payload: Box<dyn Any>,
+}
+
+// method of a host class looked up on an instance without calling it
+#[derive(Debug)]
+pub struct BoundNative {
+    pub receiver: ObjPtr,
+    pub name: ByteVector,
+    pub method: NativeMethod,
+}
+
+// Key of a map, which can be a string, a number, a boolean or nil. Strings
+// are hashed by their contents, so that any string with the same contents
+// finds the entry.
+#[derive(Debug, Clone, Copy)]
+pub struct MapKey(Value);
+
+impl MapKey {
+    pub fn new(value: Value) -> Option<Self> {
+        match value.as_obj_ptr() {
+            Some(ptr) if !ptr.is_string() => None,
+            _ => Some(MapKey(value)),
+        }
+    }
+
+    // `0` and `-0` are the same key
+    fn number_bits(n: f64) -> u64 {
+        if n == 0f64 {
+            0
+        } else {
+            n.to_bits()
+        }
+    }
+}
+
+impl PartialEq for MapKey {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = (self.0, other.0);
+        match (a.as_obj_ptr(), b.as_obj_ptr()) {
+            (Some(a), Some(b)) => a.as_string() == b.as_string(),
+            (None, None) => match (a.as_number(), b.as_number()) {
+                (Some(a), Some(b)) => Self::number_bits(a) == Self::number_bits(b),
+                _ => a == b,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl Eq for MapKey {}
+
+impl Hash for MapKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        if let Some(ptr) = self.0.as_obj_ptr() {
+            ptr.as_string().hash(state);
+        } else if let Some(n) = self.0.as_number() {
+            Self::number_bits(n).hash(state);
+        } else {
+            self.0.as_boolean().hash(state);
+        }
+    }
+}
+
+// Entries are kept in the order they were first inserted in, which is the
+// order maps print and iterate in.
+#[derive(Debug, Default)]
+pub struct Map {
+    entries: Vec<(Value, Value)>,
+    indices: HashMap<MapKey, usize>,
+}
+
+impl Map {
+    pub fn entries(&self) -> &[(Value, Value)] {
+        &self.entries
+    }
+
+    pub fn get(&self, key: MapKey) -> Option<Value> {
+        self.indices.get(&key).map(|idx| self.entries[*idx].1)
+    }
+
+    pub fn insert(&mut self, key: MapKey, value: Value) {
+        match self.indices.get(&key) {
+            Some(idx) => self.entries[*idx].1 = value,
+            None => {
+                self.indices.insert(key, self.entries.len());
+                self.entries.push((key.0, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: MapKey) -> Option<Value> {
+        let idx = self.indices.remove(&key)?;
+        let (_, value) = self.entries.remove(idx);
+        for later in self.indices.values_mut() {
+            if *later > idx {
+                *later -= 1;
+            }
+        }
+        Some(value)
+    }
+}
+
+// numbers from `start` up to but not including `end`, going up by one
+#[derive(Debug, Clone, Copy)]
+pub struct Range {
+    pub start: f64,
+    pub end: f64,
+}
+
+// A runtime error caught by a `catch` clause, which Lox code can read the
+// `message` and `line` of.
+#[derive(Debug)]
+pub struct Exception {
+    pub message: String,
+    // line of the instruction that failed
+    pub line: usize,
+}
+
+// method of a built-in type looked up on an object without calling it
+#[derive(Debug)]
+pub struct BoundBuiltin {
+    pub receiver: ObjPtr,
+    pub name: ByteVector,
+    pub builtin: Builtin,
+}
+
+pub struct Native {
+    pub name: ByteVector,
+    pub arity: usize,
+    pub function: NativeFunction,
+}
+
+impl std::fmt::Debug for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Native")
+            .field("name", &String::from_utf8_lossy(&self.name))
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+#[repr(C)]
+#[derive(Debug)]
 pub enum Obj {
     String(ByteVector),
+    Function(Function),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    HostClass(HostClass),
+    Foreign(Foreign),
+    BoundNative(BoundNative),
+    Native(Native),
+    List(Vec<Value>),
+    Map(Map),
+    Range(Range),
+    BoundBuiltin(BoundBuiltin),
+    Exception(Exception),
+}
+
+impl Obj {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Obj::String(_) => "string",
+            Obj::Function(_) => "function",
+            Obj::Closure(_) => "closure",
+            Obj::Upvalue(_) => "upvalue",
+            Obj::HostClass(_) => "host class",
+            Obj::Foreign(_) => "instance",
+            Obj::BoundNative(_) => "bound method",
+            Obj::Native(_) => "native function",
+            Obj::List(_) => "list",
+            Obj::Map(_) => "map",
+            Obj::Range(_) => "range",
+            Obj::BoundBuiltin(_) => "bound method",
+            Obj::Exception(_) => "error",
+        }
+    }
+
+    // Roughly how many bytes the object takes up on the heap, only counting
+    // the memory it owns directly.
+    pub fn size(&self) -> usize {
+        let owned = match self {
+            Obj::String(string) => string.capacity(),
+            Obj::Function(function) => {
+                function.chunk.code.capacity()
+                    + function.chunk.constants.capacity() * size_of::<Value>()
+            }
+            Obj::Closure(closure) => closure.upvalues.capacity() * size_of::<ObjPtr>(),
+            Obj::Upvalue(_) => 0,
+            Obj::HostClass(class) => {
+                class.methods.capacity() * size_of::<(ByteVector, NativeMethod)>()
+            }
+            Obj::Foreign(foreign) => {
+                foreign.fields.capacity() * size_of::<(ByteVector, Value)>()
+                    + size_of_val(&*foreign.payload)
+            }
+            Obj::BoundNative(bound) => bound.name.capacity(),
+            Obj::Native(native) => native.name.capacity() + size_of_val(&*native.function),
+            Obj::List(elements) => elements.capacity() * size_of::<Value>(),
+            Obj::Map(map) => {
+                map.entries.capacity() * size_of::<(Value, Value)>()
+                    + map.indices.capacity() * size_of::<(MapKey, usize)>()
+            }
+            Obj::Range(_) => 0,
+            Obj::BoundBuiltin(bound) => bound.name.capacity(),
+            Obj::Exception(exception) => exception.message.capacity(),
+        };
+        size_of::<Obj>() + owned
+    }
 }
 
 impl Display for Obj {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Obj::String(bytestring) => write!(f, "{}", std::str::from_utf8(bytestring).unwrap()),
+            // strings from the host might not be valid UTF-8
+            Obj::String(bytestring) => write!(f, "{}", String::from_utf8_lossy(bytestring)),
+            Obj::Function(function) => {
+                write!(f, "<fn {}>", std::str::from_utf8(&function.name).unwrap())
+            }
+            Obj::Closure(closure) => write!(f, "{}", closure.function),
+            Obj::Upvalue(_) => write!(f, "upvalue"),
+            Obj::HostClass(class) => write!(f, "{}", std::str::from_utf8(&class.name).unwrap()),
+            Obj::Foreign(foreign) => write!(f, "{} instance", foreign.class),
+            Obj::BoundNative(bound) => {
+                write!(
+                    f,
+                    "<native fn {}>",
+                    std::str::from_utf8(&bound.name).unwrap()
+                )
+            }
+            Obj::Native(native) => {
+                write!(
+                    f,
+                    "<native fn {}>",
+                    std::str::from_utf8(&native.name).unwrap()
+                )
+            }
+            Obj::List(elements) => {
+                write!(f, "[")?;
+                for (idx, element) in elements.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Obj::Map(map) => {
+                write!(f, "{{")?;
+                for (idx, (key, value)) in map.entries.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+            Obj::Range(range) => write!(f, "{}..{}", range.start, range.end),
+            Obj::BoundBuiltin(bound) => {
+                write!(
+                    f,
+                    "<native fn {}>",
+                    std::str::from_utf8(&bound.name).unwrap()
+                )
+            }
+            Obj::Exception(exception) => write!(f, "{}", exception.message),
         }
     }
 }
@@ -45,24 +357,57 @@ impl Display for Obj {
 impl Display for ObjPtr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let derefed_obj = unsafe { &(*self.0.as_ptr()) };
-        match derefed_obj {
-            Obj::String(bytestring) => write!(f, "{}", std::str::from_utf8(bytestring).unwrap()),
-        }
+        write!(f, "{}", derefed_obj)
     }
 }
 
 impl ObjPtr {
+    #[cfg(feature = "nan_boxing")]
+    pub fn to_bits(self) -> u64 {
+        self.0.as_ptr() as u64
+    }
+
+    // SAFETY: `bits` has to come from `ObjPtr::to_bits`
+    #[cfg(feature = "nan_boxing")]
+    pub unsafe fn from_bits(bits: u64) -> Self {
+        ObjPtr(NonNull::new_unchecked(bits as *mut Obj))
+    }
+
     pub fn is_string(&self) -> bool {
+        let derefed_obj = unsafe { &(*self.0.as_ptr()) };
+        matches!(derefed_obj, Obj::String(_))
+    }
+
+    pub fn as_string(&self) -> &ByteSlice {
         let derefed_obj = unsafe { &(*self.0.as_ptr()) };
         match derefed_obj {
-            Obj::String(_) => true,
+            Obj::String(byte_string) => byte_string,
+            _ => panic!("{} is not a string", derefed_obj),
         }
     }
 
-    pub fn into_string(&self) -> &ByteSlice {
+    pub fn as_function(&self) -> Option<&Function> {
         let derefed_obj = unsafe { &(*self.0.as_ptr()) };
         match derefed_obj {
-            Obj::String(byte_string) => byte_string,
+            Obj::Function(function) => Some(function),
+            _ => None,
         }
     }
+
+    // The object has to stay alive for as long as the returned reference is
+    // used, which the VM makes sure of by keeping it reachable.
+    pub fn as_obj<'a>(self) -> &'a Obj {
+        unsafe { &(*self.0.as_ptr()) }
+    }
+
+    // Same as `as_obj`, and nothing else may be looking at the object.
+    pub fn as_obj_mut<'a>(self) -> &'a mut Obj {
+        unsafe { &mut (*self.0.as_ptr()) }
+    }
+
+    // SAFETY: the object has to be unreachable, which is what the garbage
+    // collector checks before calling this
+    pub unsafe fn free(self) {
+        drop(Box::from_raw(self.0.as_ptr()));
+    }
 }
@@ -1,7 +1,7 @@
 use std::fmt::Debug;
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum OpCode {
     Return,
     Constant,
@@ -11,6 +11,15 @@ pub enum OpCode {
     Subtract,
     Multiply,
     Divide,
+    Modulo,
+    Power,
+    // bitwise operators on numbers taken as 64 bit integers
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
     Nil,
     True,
     False,
@@ -18,6 +27,69 @@ pub enum OpCode {
     Equal,
     Greater,
     Less,
+    Pop,
+    Print,
+    DefineGlobal,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    // fused instructions emitted by the peephole pass
+    GreaterEqual,
+    LessEqual,
+    NotEqual,
+    AddConstant,
+    GetLocalGetLocal,
+    Call,
+    GetProperty,
+    SetProperty,
+    // calls a method on an object without looking it up as a property first
+    Invoke,
+    // collects the number of values given by the operand into a new list
+    BuildList,
+    // collects the number of key and value pairs given by the operand into a
+    // new map
+    BuildMap,
+    GetIndex,
+    SetIndex,
+    // creates a range from two numbers
+    Range,
+    // turns the value on top of the stack into a string, for interpolation
+    Stringify,
+    // Pop a value and the pattern for a `match` case, or a value and the
+    // bounds of a range pattern, and push whether the value matches.
+    MatchValue,
+    MatchRange,
+    // Advances the iterator of a `for-in` loop, which is kept in the local
+    // after the sequence in the slot given by the first operand, and pushes
+    // the next element. Once there are no more it pushes nil and jumps.
+    ForIter,
+    // Creates a closure for the function constant given by the operand,
+    // capturing the variables listed in the function's `captures`.
+    Closure,
+    GetUpvalue,
+    SetUpvalue,
+    // moves the local on top of the stack into its upvalue and pops it
+    CloseUpvalue,
+    // pushes copies of the number of values on top of the stack given by the
+    // operand, for compound assignments reading what they assign to
+    Dup,
+    // moves the value on top of the stack below the number of values under
+    // it given by the operand, where postfix `++` and `--` keep the old value
+    Bury,
+    // Installs a handler for exceptions at the offset given by the operand,
+    // which runs with the stack cut back to its current height and the
+    // exception pushed on top.
+    SetupTry,
+    // removes the handler installed last
+    PopTry,
+    // throws the value on top of the stack
+    Throw,
+    // stops running the top level code, has to stay the last opcode
+    Halt,
 }
 
 impl Debug for OpCode {
@@ -31,6 +103,14 @@ impl Debug for OpCode {
             OpCode::Subtract => "OP_SUBTRACT",
             OpCode::Multiply => "OP_MULTIPLY",
             OpCode::Divide => "OP_DIVIDE",
+            OpCode::Modulo => "OP_MODULO",
+            OpCode::Power => "OP_POWER",
+            OpCode::BitAnd => "OP_BIT_AND",
+            OpCode::BitOr => "OP_BIT_OR",
+            OpCode::BitXor => "OP_BIT_XOR",
+            OpCode::BitNot => "OP_BIT_NOT",
+            OpCode::ShiftLeft => "OP_SHIFT_LEFT",
+            OpCode::ShiftRight => "OP_SHIFT_RIGHT",
             OpCode::Nil => "OP_NIL",
             OpCode::True => "OP_TRUE",
             OpCode::False => "OP_FALSE",
@@ -38,8 +118,46 @@ impl Debug for OpCode {
             OpCode::Equal => "OP_EQUAL",
             OpCode::Greater => "OP_GREATER",
             OpCode::Less => "OP_LESS",
+            OpCode::Pop => "OP_POP",
+            OpCode::Print => "OP_PRINT",
+            OpCode::DefineGlobal => "OP_DEFINE_GLOBAL",
+            OpCode::GetGlobal => "OP_GET_GLOBAL",
+            OpCode::SetGlobal => "OP_SET_GLOBAL",
+            OpCode::GetLocal => "OP_GET_LOCAL",
+            OpCode::SetLocal => "OP_SET_LOCAL",
+            OpCode::Jump => "OP_JUMP",
+            OpCode::JumpIfFalse => "OP_JUMP_IF_FALSE",
+            OpCode::Loop => "OP_LOOP",
+            OpCode::GreaterEqual => "OP_GREATER_EQUAL",
+            OpCode::LessEqual => "OP_LESS_EQUAL",
+            OpCode::NotEqual => "OP_NOT_EQUAL",
+            OpCode::AddConstant => "OP_ADD_CONSTANT",
+            OpCode::GetLocalGetLocal => "OP_GET_LOCAL_GET_LOCAL",
+            OpCode::Call => "OP_CALL",
+            OpCode::GetProperty => "OP_GET_PROPERTY",
+            OpCode::SetProperty => "OP_SET_PROPERTY",
+            OpCode::Invoke => "OP_INVOKE",
+            OpCode::BuildList => "OP_BUILD_LIST",
+            OpCode::BuildMap => "OP_BUILD_MAP",
+            OpCode::GetIndex => "OP_GET_INDEX",
+            OpCode::SetIndex => "OP_SET_INDEX",
+            OpCode::Range => "OP_RANGE",
+            OpCode::Stringify => "OP_STRINGIFY",
+            OpCode::MatchValue => "OP_MATCH_VALUE",
+            OpCode::MatchRange => "OP_MATCH_RANGE",
+            OpCode::ForIter => "OP_FOR_ITER",
+            OpCode::Closure => "OP_CLOSURE",
+            OpCode::GetUpvalue => "OP_GET_UPVALUE",
+            OpCode::SetUpvalue => "OP_SET_UPVALUE",
+            OpCode::CloseUpvalue => "OP_CLOSE_UPVALUE",
+            OpCode::Dup => "OP_DUP",
+            OpCode::Bury => "OP_BURY",
+            OpCode::SetupTry => "OP_SETUP_TRY",
+            OpCode::PopTry => "OP_POP_TRY",
+            OpCode::Throw => "OP_THROW",
+            OpCode::Halt => "OP_HALT",
         };
-        write!(f, "{:16}", string_rep)
+        write!(f, "{:22}", string_rep)
     }
 }
 
@@ -48,3 +166,39 @@ impl From<u8> for OpCode {
         unsafe { std::mem::transmute(value) }
     }
 }
+
+impl OpCode {
+    // number of valid opcodes, any byte below this can be turned into an `OpCode`
+    pub const COUNT: u8 = OpCode::Halt as u8 + 1;
+
+    // number of bytes taken up by the instruction including its operands
+    pub fn size(self) -> usize {
+        match self {
+            OpCode::ConstantLong | OpCode::ForIter => 4,
+            OpCode::Jump
+            | OpCode::JumpIfFalse
+            | OpCode::Loop
+            | OpCode::SetupTry
+            | OpCode::GetLocalGetLocal
+            | OpCode::Invoke => 3,
+            OpCode::Constant
+            | OpCode::DefineGlobal
+            | OpCode::GetGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::AddConstant
+            | OpCode::Call
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::BuildList
+            | OpCode::BuildMap
+            | OpCode::Closure
+            | OpCode::GetUpvalue
+            | OpCode::SetUpvalue
+            | OpCode::Dup
+            | OpCode::Bury => 2,
+            _ => 1,
+        }
+    }
+}
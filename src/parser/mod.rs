@@ -1,10 +1,9 @@
-use std::str;
-
 mod parse_error;
 mod tests;
 
+use crate::byte_string::ByteSlice;
 use crate::expr::Expr;
-use crate::stmt::Stmt;
+use crate::stmt::{Pattern, Stmt};
 use crate::token::Token;
 use crate::token_type::TokenType;
 
@@ -14,20 +13,36 @@ use self::parse_error::ParseError;
 
 // statement      → exprStmt
 //                | forStmt
+//                | breakStmt
+//                | continueStmt
 //                | ifStmt
 //                | printStmt
 //                | printStmt
 //                | whileStmt
+//                | matchStmt
+//                | throwStmt
+//                | tryStmt
 //                | block ;
 
 // returnStmt     → "return" expression? ";" ;
+// breakStmt      → "break" ";" ;
+// continueStmt   → "continue" ";" ;
+// throwStmt      → "throw" expression ";" ;
+
+// tryStmt        → "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
 
 // forStmt        → "for" "(" ( varDecl | exprStmt | ";" )
 //                  expression? ";"
-//                  expression? ")" statement ;
+//                  expression? ")" statement
+//                | "for" "(" "var" IDENTIFIER "in" expression ")" statement ;
 
 // whileStmt      → "while" "(" expression ")" statement ;
 
+// matchStmt      → "match" "(" expression ")" "{" matchCase* ( "default" "=>" statement )? "}" ;
+// matchCase      → "case" pattern ( "," pattern )* "=>" statement ;
+// pattern        → literal ( ".." literal )? ;
+// literal        → "-"? NUMBER | STRING | "true" | "false" | "nil" ;
+
 // ifStmt         → "if" "(" expression ")" statement
 //                ( "else" statement )? ;
 
@@ -47,25 +62,45 @@ use self::parse_error::ParseError;
 // printStmt      → "print" expression ";" ;
 
 // expression     → assignment ;
-// assignment     → IDENTIFIER "=" assignment
-//                | logic_or ;
+// assignment     → target assign_op assignment
+//                | conditional ;
+// target         → ( call "." )? IDENTIFIER
+//                | call "[" expression "]" ;
+// assign_op      → "=" | "+=" | "-=" | "*=" | "/=" ;
+// conditional    → logic_or ( "?" expression ":" conditional )? ;
 // logic_or       → logic_and ( "or" logic_and )* ;
 // logic_and      → equality ( "and" equality )* ;
 
 // equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-// comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+// comparison     → range ( ( ">" | ">=" | "<" | "<=" ) range )* ;
+// range          → bit_or ( ".." bit_or )? ;
+// bit_or         → bit_xor ( "|" bit_xor )* ;
+// bit_xor        → bit_and ( "^" bit_and )* ;
+// bit_and        → shift ( "&" shift )* ;
+// shift          → term ( ( "<<" | ">>" ) term )* ;
 // term           → factor ( ( "-" | "+" ) factor )* ;
-// factor         → unary ( ( "/" | "*" ) unary )* ;
-// unary          → ( "!" | "-" ) unary | call ;
-// call           → primary ( "(" arguments? ")" )* ;
+// factor         → unary ( ( "/" | "*" | "%" ) unary )* ;
+// unary          → ( "!" | "-" | "~" ) unary | power ;
+// power          → update ( "**" unary )? ;
+// update         → ( "++" | "--" ) target
+//                | call ( "++" | "--" )? ;
+// call           → primary ( "(" arguments? ")" | "." IDENTIFIER
+//                | "[" expression "]" )* ;
 //                | primary ;
 // arguments      → expression ( "," expression )* ;
 // primary        → "true" | "false" | "nil"
-//                | NUMBER | STRING
+//                | NUMBER | STRING | interpolation
 //                | "(" expression ")"
+//                | "[" arguments? "]"
+//                | "{" entries? "}"
+//                | lambda
 //                | IDENTIFIER ;
+// lambda         → "fun" "(" parameters? ")" block
+//                | "(" parameters? ")" "=>" ( block | expression ) ;
+// entries        → expression ":" expression ( "," expression ":" expression )* ;
+// interpolation  → ( INTERPOLATION expression )+ STRING ;
 
-pub fn parse<'a>(tokens: &'a [Token<'a>]) -> (Vec<Stmt<'a>>, Vec<ParseError>) {
+pub fn parse<'a>(tokens: &'a [Token<'a>]) -> (Vec<Stmt<'a>>, Vec<ParseError<'a>>) {
     let mut statements = Vec::new();
     let mut errors = Vec::new();
     let mut pos: usize = 0;
@@ -113,9 +148,12 @@ fn synchronize(tokens: &[Token<'_>], pos: usize) -> usize {
 fn parse_declaration<'a>(
     tokens: &'a [Token<'a>],
     pos: usize,
-) -> Result<(Stmt<'a>, usize), ParseError> {
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
     match tokens[pos].token_type {
-        TokenType::Fun => parse_function(tokens, pos + 1),
+        // without a name it's a lambda starting an expression statement
+        TokenType::Fun if tokens[pos + 1].token_type != TokenType::LeftParen => {
+            parse_function(tokens, pos + 1)
+        }
         TokenType::Var => parse_var_declaration(tokens, pos + 1),
         _ => parse_statement(tokens, pos),
     }
@@ -124,10 +162,29 @@ fn parse_declaration<'a>(
 fn parse_function<'a>(
     tokens: &'a [Token<'a>],
     pos: usize,
-) -> Result<(Stmt<'a>, usize), ParseError> {
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
     let (name, pos) = consume(tokens, pos, TokenType::Identifier)?;
-    let (_, mut pos) = consume(tokens, pos, TokenType::LeftParen)?;
+    let (params, pos) = parse_parameters(tokens, pos)?;
 
+    let (_, pos) = consume(tokens, pos, TokenType::LeftBrace)?;
+    let (body, pos) = parse_block(tokens, pos)?;
+
+    Ok((
+        Stmt::Function {
+            name,
+            params,
+            body: Box::new(body),
+        },
+        pos,
+    ))
+}
+
+// the parameters of a function, parentheses included
+fn parse_parameters<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Vec<&'a Token<'a>>, usize), ParseError<'a>> {
+    let (_, mut pos) = consume(tokens, pos, TokenType::LeftParen)?;
     let mut params = Vec::new();
     if tokens[pos].token_type != TokenType::RightParen {
         loop {
@@ -142,24 +199,13 @@ fn parse_function<'a>(
         }
     }
     let (_, pos) = consume(tokens, pos, TokenType::RightParen)?;
-
-    let (_, pos) = consume(tokens, pos, TokenType::LeftBrace)?;
-    let (body, pos) = parse_block(tokens, pos)?;
-
-    Ok((
-        Stmt::Function {
-            name,
-            params,
-            body: Box::new(body),
-        },
-        pos,
-    ))
+    Ok((params, pos))
 }
 
 fn parse_var_declaration<'a>(
     tokens: &'a [Token<'a>],
     pos: usize,
-) -> Result<(Stmt<'a>, usize), ParseError> {
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
     let (name, pos) = consume(tokens, pos, TokenType::Identifier)?;
 
     match matchh(tokens, pos, vec![TokenType::Equal]) {
@@ -178,18 +224,35 @@ fn parse_var_declaration<'a>(
 fn parse_statement<'a>(
     tokens: &'a [Token<'a>],
     pos: usize,
-) -> Result<(Stmt<'a>, usize), ParseError> {
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
     match tokens[pos].token_type {
         TokenType::For => parse_for_statement(tokens, pos + 1),
         TokenType::If => parse_if_statment(tokens, pos + 1),
         TokenType::Print => parse_print_statement(tokens, pos + 1),
         TokenType::Return => parse_return_statement(tokens, pos),
+        TokenType::Break | TokenType::Continue => parse_loop_jump(tokens, pos),
         TokenType::While => parse_while_statement(tokens, pos + 1),
+        TokenType::Match => parse_match_statement(tokens, pos),
+        TokenType::Throw => parse_throw_statement(tokens, pos),
+        TokenType::Try => parse_try_statement(tokens, pos),
         TokenType::LeftBrace => parse_block(tokens, pos + 1),
         _ => parse_expression_statement(tokens, pos),
     }
 }
 
+// `break` or `continue`, whether they are in a loop is up to the compiler
+fn parse_loop_jump<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
+    let keyword = &tokens[pos];
+    let (_, pos) = consume(tokens, pos + 1, TokenType::Semicolon)?;
+    match keyword.token_type {
+        TokenType::Break => Ok((Stmt::Break(keyword), pos)),
+        _ => Ok((Stmt::Continue(keyword), pos)),
+    }
+}
+
 fn parse_return_statement<'a>(
     tokens: &'a [Token<'a>],
     mut pos: usize,
@@ -210,12 +273,74 @@ fn parse_return_statement<'a>(
     Ok((Stmt::Return { keyword, value }, pos))
 }
 
+fn parse_throw_statement<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
+    let keyword = &tokens[pos];
+    let (value, pos) = parse_expression(tokens, pos + 1)?;
+    let (_, pos) = consume(tokens, pos, TokenType::Semicolon)?;
+    Ok((Stmt::Throw { keyword, value }, pos))
+}
+
+fn parse_try_statement<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
+    let keyword = &tokens[pos];
+    let (_, pos) = consume(tokens, pos + 1, TokenType::LeftBrace)?;
+    let (body, mut pos) = parse_block(tokens, pos)?;
+
+    let mut catch = None;
+    if let Some((_, new_pos)) = matchh(tokens, pos, vec![TokenType::Catch]) {
+        let (_, new_pos) = consume(tokens, new_pos, TokenType::LeftParen)?;
+        let (name, new_pos) = consume(tokens, new_pos, TokenType::Identifier)?;
+        let (_, new_pos) = consume(tokens, new_pos, TokenType::RightParen)?;
+        let (_, new_pos) = consume(tokens, new_pos, TokenType::LeftBrace)?;
+        let (handler, new_pos) = parse_block(tokens, new_pos)?;
+        catch = Some((name, Box::new(handler)));
+        pos = new_pos;
+    }
+
+    let mut finally = None;
+    if let Some((_, new_pos)) = matchh(tokens, pos, vec![TokenType::Finally]) {
+        let (_, new_pos) = consume(tokens, new_pos, TokenType::LeftBrace)?;
+        let (block, new_pos) = parse_block(tokens, new_pos)?;
+        finally = Some(Box::new(block));
+        pos = new_pos;
+    }
+
+    if catch.is_none() && finally.is_none() {
+        return Err(ParseError::ExpectedSomething {
+            actual: &tokens[pos],
+            expected: TokenType::Catch,
+        });
+    }
+    Ok((
+        Stmt::Try {
+            keyword,
+            body: Box::new(body),
+            catch,
+            finally,
+        },
+        pos,
+    ))
+}
+
 fn parse_for_statement<'a>(
     tokens: &'a [Token<'a>],
     pos: usize,
 ) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
     let (_, mut pos) = consume(tokens, pos, TokenType::LeftParen)?;
 
+    // `var` and the name of the loop variable are followed by `in`
+    let is_for_in = (tokens.get(pos..pos + 3)).is_some_and(|tokens| {
+        tokens[0].token_type == TokenType::Var && tokens[2].token_type == TokenType::In
+    });
+    if is_for_in {
+        return parse_for_in(tokens, pos + 1);
+    }
+
     let initializer: Option<Stmt> = {
         match &tokens[pos].token_type {
             TokenType::Semicolon => None,
@@ -256,27 +381,15 @@ fn parse_for_statement<'a>(
     };
     let (_, pos) = consume(tokens, pos, TokenType::RightParen)?;
 
-    let (mut body, pos) = parse_statement(tokens, pos)?;
-
-    // we desugar the FOR loop to a WHILE loop
-
-    // add the increment statement if any to the end of the body
-    if let Some(increment) = increment {
-        body = Stmt::Block(vec![body, Stmt::Expression(increment)]);
-    }
+    let (body, pos) = parse_statement(tokens, pos)?;
 
-    // create while loop based on condition
-    if let Some(condition) = condition {
-        body = Stmt::While {
-            condition,
-            body: Box::new(body),
-        }
-    } else {
-        body = Stmt::While {
-            condition: Expr::TrueLiteral,
-            body: Box::new(body),
-        }
-    }
+    // we desugar the FOR loop to a WHILE loop, which runs the increment after
+    // the body
+    let mut body = Stmt::While {
+        condition: condition.unwrap_or(Expr::TrueLiteral),
+        body: Box::new(body),
+        increment,
+    };
 
     // add the initializer statement to the beginning
     if let Some(initializer) = initializer {
@@ -286,6 +399,26 @@ fn parse_for_statement<'a>(
     Ok((body, pos))
 }
 
+// the rest of a `for` loop after `var`
+fn parse_for_in<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
+    let (name, pos) = consume(tokens, pos, TokenType::Identifier)?;
+    let (_, pos) = consume(tokens, pos, TokenType::In)?;
+    let (iterable, pos) = parse_expression(tokens, pos)?;
+    let (_, pos) = consume(tokens, pos, TokenType::RightParen)?;
+    let (body, pos) = parse_statement(tokens, pos)?;
+    Ok((
+        Stmt::ForIn {
+            name,
+            iterable,
+            body: Box::new(body),
+        },
+        pos,
+    ))
+}
+
 fn parse_while_statement<'a>(
     tokens: &'a [Token<'a>],
     pos: usize,
@@ -298,6 +431,7 @@ fn parse_while_statement<'a>(
         Stmt::While {
             condition,
             body: Box::new(body),
+            increment: None,
         },
         pos,
     ))
@@ -334,7 +468,87 @@ fn parse_if_statment<'a>(
     }
 }
 
-fn parse_block<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Stmt<'a>, usize), ParseError> {
+fn parse_match_statement<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
+    let keyword = &tokens[pos];
+    let (_, pos) = consume(tokens, pos + 1, TokenType::LeftParen)?;
+    let (subject, pos) = parse_expression(tokens, pos)?;
+    let (_, pos) = consume(tokens, pos, TokenType::RightParen)?;
+    let (_, mut pos) = consume(tokens, pos, TokenType::LeftBrace)?;
+
+    let mut cases = Vec::new();
+    while tokens[pos].token_type == TokenType::Case {
+        let mut patterns = Vec::new();
+        loop {
+            let (pattern, new_pos) = parse_pattern(tokens, pos + 1)?;
+            patterns.push(pattern);
+            pos = new_pos;
+            if tokens[pos].token_type != TokenType::Comma {
+                break;
+            }
+        }
+        let (_, new_pos) = consume(tokens, pos, TokenType::Arrow)?;
+        let (body, new_pos) = parse_statement(tokens, new_pos)?;
+        cases.push((patterns, body));
+        pos = new_pos;
+    }
+
+    let mut default = None;
+    if let Some((_, new_pos)) = matchh(tokens, pos, vec![TokenType::Default]) {
+        let (_, new_pos) = consume(tokens, new_pos, TokenType::Arrow)?;
+        let (body, new_pos) = parse_statement(tokens, new_pos)?;
+        default = Some(Box::new(body));
+        pos = new_pos;
+    }
+    let (_, pos) = consume(tokens, pos, TokenType::RightBrace)?;
+    Ok((
+        Stmt::Match {
+            keyword,
+            subject,
+            cases,
+            default,
+        },
+        pos,
+    ))
+}
+
+fn parse_pattern<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Pattern<'a>, usize), ParseError<'a>> {
+    let invalid = ParseError::InvalidPattern {
+        token: &tokens[pos],
+    };
+    let is_number = |expr: &Expr| match expr {
+        Expr::NumericLiteral(_) => true,
+        Expr::Unary { op, expr } => {
+            op.token_type == TokenType::Minus && matches!(**expr, Expr::NumericLiteral(_))
+        }
+        _ => false,
+    };
+    let (expr, pos) = parse_range(tokens, pos)?;
+    match expr {
+        Expr::Binary { left, op, right } if op.token_type == TokenType::DotDot => {
+            if !is_number(&left) || !is_number(&right) {
+                return Err(invalid);
+            }
+            let (start, end) = (*left, *right);
+            Ok((Pattern::Range { start, end }, pos))
+        }
+        Expr::StringLiteral(_) | Expr::TrueLiteral | Expr::FalseLiteral | Expr::NilLiteral => {
+            Ok((Pattern::Literal(expr), pos))
+        }
+        _ if is_number(&expr) => Ok((Pattern::Literal(expr), pos)),
+        _ => Err(invalid),
+    }
+}
+
+fn parse_block<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
     let mut pos = pos;
     let mut statements = Vec::new();
 
@@ -356,19 +570,19 @@ fn parse_block<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Stmt<'a>, usi
 fn parse_expression_statement<'a>(
     tokens: &'a [Token<'a>],
     pos: usize,
-) -> Result<(Stmt<'a>, usize), ParseError> {
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
     let (expr, pos) = parse_expression(tokens, pos)?;
     let (_, pos) = consume(tokens, pos, TokenType::Semicolon)?;
-    return Ok((Stmt::Expression(expr), pos));
+    Ok((Stmt::Expression(expr), pos))
 }
 
 fn parse_print_statement<'a>(
     tokens: &'a [Token<'a>],
     pos: usize,
-) -> Result<(Stmt<'a>, usize), ParseError> {
+) -> Result<(Stmt<'a>, usize), ParseError<'a>> {
     let (value, pos) = parse_expression(tokens, pos)?;
     let (_, pos) = consume(tokens, pos, TokenType::Semicolon)?;
-    return Ok((Stmt::Print(value), pos));
+    Ok((Stmt::Print(value), pos))
 }
 
 // TODO: replace all instances of the consuming pattern with this function
@@ -403,15 +617,38 @@ fn matchh<'a>(
 pub fn parse_expression<'a>(
     tokens: &'a [Token<'a>],
     pos: usize,
-) -> Result<(Expr<'a>, usize), ParseError> {
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
     parse_assignment(tokens, pos)
 }
 
 fn parse_assignment<'a>(
     tokens: &'a [Token<'a>],
     pos: usize,
-) -> Result<(Expr<'a>, usize), ParseError> {
-    let (expr, pos) = parse_or(tokens, pos)?;
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    let (expr, pos) = parse_conditional(tokens, pos)?;
+    if let Some((op, pos)) = matchh(
+        tokens,
+        pos,
+        vec![
+            TokenType::PlusEqual,
+            TokenType::MinusEqual,
+            TokenType::StarEqual,
+            TokenType::SlashEqual,
+        ],
+    ) {
+        if !is_target(&expr) {
+            return Err(ParseError::InvalidAssignment { equals: op });
+        }
+        let (value, pos) = parse_assignment(tokens, pos)?;
+        return Ok((
+            Expr::Compound {
+                target: Box::new(expr),
+                op,
+                value: Box::new(value),
+            },
+            pos,
+        ));
+    }
     match matchh(tokens, pos, vec![TokenType::Equal]) {
         Some((equals, pos)) => {
             let (value, pos) = parse_assignment(tokens, pos)?;
@@ -423,6 +660,27 @@ fn parse_assignment<'a>(
                     },
                     pos,
                 )),
+                Expr::Get { object, name } => Ok((
+                    Expr::Set {
+                        object,
+                        name,
+                        value: Box::new(value),
+                    },
+                    pos,
+                )),
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => Ok((
+                    Expr::SetIndex {
+                        object,
+                        bracket,
+                        index,
+                        value: Box::new(value),
+                    },
+                    pos,
+                )),
                 _ => Err(ParseError::InvalidAssignment { equals }),
             }
         }
@@ -430,6 +688,37 @@ fn parse_assignment<'a>(
     }
 }
 
+// whether `expr` can be assigned to
+fn is_target(expr: &Expr<'_>) -> bool {
+    matches!(
+        expr,
+        Expr::Variable(_) | Expr::Get { .. } | Expr::Index { .. }
+    )
+}
+
+fn parse_conditional<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    let (condition, pos) = parse_or(tokens, pos)?;
+    match matchh(tokens, pos, vec![TokenType::Question]) {
+        Some((_, pos)) => {
+            let (then_branch, pos) = parse_expression(tokens, pos)?;
+            let (_, pos) = consume(tokens, pos, TokenType::Colon)?;
+            let (else_branch, pos) = parse_conditional(tokens, pos)?;
+            Ok((
+                Expr::Conditional {
+                    condition: Box::new(condition),
+                    then_branch: Box::new(then_branch),
+                    else_branch: Box::new(else_branch),
+                },
+                pos,
+            ))
+        }
+        None => Ok((condition, pos)),
+    }
+}
+
 fn parse_or<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usize), ParseError<'a>> {
     let (mut expr, mut pos) = parse_and(tokens, pos)?;
     loop {
@@ -471,7 +760,7 @@ fn parse_and<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usize
 fn parse_equality<'a>(
     tokens: &'a [Token<'a>],
     pos: usize,
-) -> Result<(Expr<'a>, usize), ParseError> {
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
     let (mut expr, mut pos) = parse_comp(tokens, pos)?;
     loop {
         let comp_token = &tokens[pos];
@@ -491,8 +780,11 @@ fn parse_equality<'a>(
     Ok((expr, pos))
 }
 
-fn parse_comp<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usize), ParseError> {
-    let (mut expr, mut pos) = parse_term(tokens, pos)?;
+fn parse_comp<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    let (mut expr, mut pos) = parse_range(tokens, pos)?;
     loop {
         let comp_token = &tokens[pos];
         if comp_token.token_type != TokenType::Greater
@@ -502,7 +794,7 @@ fn parse_comp<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usiz
         {
             break;
         }
-        let (right, new_pos) = parse_term(tokens, pos + 1)?;
+        let (right, new_pos) = parse_range(tokens, pos + 1)?;
         pos = new_pos;
         expr = Expr::Binary {
             left: Box::new(expr),
@@ -513,7 +805,84 @@ fn parse_comp<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usiz
     Ok((expr, pos))
 }
 
-fn parse_term<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usize), ParseError> {
+fn parse_range<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    let (expr, pos) = parse_bit_or(tokens, pos)?;
+    match matchh(tokens, pos, vec![TokenType::DotDot]) {
+        Some((op, pos)) => {
+            let (right, pos) = parse_bit_or(tokens, pos)?;
+            Ok((
+                Expr::Binary {
+                    left: Box::new(expr),
+                    op,
+                    right: Box::new(right),
+                },
+                pos,
+            ))
+        }
+        None => Ok((expr, pos)),
+    }
+}
+
+type ParseFn<'a> = fn(&'a [Token<'a>], usize) -> Result<(Expr<'a>, usize), ParseError<'a>>;
+
+// Parses operands with `parse_operand` separated by any of the left
+// associative operators `ops`.
+fn parse_binary<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+    ops: &[TokenType],
+    parse_operand: ParseFn<'a>,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    let (mut expr, mut pos) = parse_operand(tokens, pos)?;
+    while ops.contains(&tokens[pos].token_type) {
+        let op = &tokens[pos];
+        let (right, new_pos) = parse_operand(tokens, pos + 1)?;
+        pos = new_pos;
+        expr = Expr::Binary {
+            left: Box::new(expr),
+            op,
+            right: Box::new(right),
+        };
+    }
+    Ok((expr, pos))
+}
+
+fn parse_bit_or<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    parse_binary(tokens, pos, &[TokenType::Pipe], parse_bit_xor)
+}
+
+fn parse_bit_xor<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    parse_binary(tokens, pos, &[TokenType::Caret], parse_bit_and)
+}
+
+fn parse_bit_and<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    parse_binary(tokens, pos, &[TokenType::Ampersand], parse_shift)
+}
+
+fn parse_shift<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    let ops = [TokenType::LessLess, TokenType::GreaterGreater];
+    parse_binary(tokens, pos, &ops, parse_term)
+}
+
+fn parse_term<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
     let (mut expr, mut pos) = parse_factor(tokens, pos)?;
     loop {
         let op_token = &tokens[pos];
@@ -531,11 +900,17 @@ fn parse_term<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usiz
     Ok((expr, pos))
 }
 
-fn parse_factor<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usize), ParseError> {
+fn parse_factor<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
     let (mut expr, mut pos) = parse_unary(tokens, pos)?;
     loop {
         let op_token = &tokens[pos];
-        if op_token.token_type != TokenType::Star && op_token.token_type != TokenType::Slash {
+        if op_token.token_type != TokenType::Star
+            && op_token.token_type != TokenType::Slash
+            && op_token.token_type != TokenType::Percent
+        {
             break;
         }
         let (right, new_pos) = parse_unary(tokens, pos + 1)?;
@@ -549,10 +924,13 @@ fn parse_factor<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, us
     Ok((expr, pos))
 }
 
-fn parse_unary<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usize), ParseError> {
+fn parse_unary<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
     let operator_token = &tokens[pos];
     match &operator_token.token_type {
-        TokenType::Bang | TokenType::Minus => {
+        TokenType::Bang | TokenType::Minus | TokenType::Tilde => {
             let (right, pos) = parse_unary(tokens, pos + 1)?;
             Ok((
                 Expr::Unary {
@@ -562,11 +940,68 @@ fn parse_unary<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usi
                 pos,
             ))
         }
-        _ => parse_call(tokens, pos),
+        _ => parse_power(tokens, pos),
+    }
+}
+
+// `**` is right associative and binds tighter than a unary operator before
+// it, but not after it, so `-2 ** 2` is -4 and `2 ** -1` is 0.5
+fn parse_power<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    let (expr, pos) = parse_update(tokens, pos)?;
+    match matchh(tokens, pos, vec![TokenType::StarStar]) {
+        Some((op, pos)) => {
+            let (right, pos) = parse_unary(tokens, pos)?;
+            Ok((
+                Expr::Binary {
+                    left: Box::new(expr),
+                    op,
+                    right: Box::new(right),
+                },
+                pos,
+            ))
+        }
+        None => Ok((expr, pos)),
+    }
+}
+
+fn parse_update<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    let increments = vec![TokenType::PlusPlus, TokenType::MinusMinus];
+    let (expr, pos, op, postfix) = match matchh(tokens, pos, increments.clone()) {
+        Some((op, pos)) => {
+            let (target, pos) = parse_call(tokens, pos)?;
+            (target, pos, op, false)
+        }
+        None => {
+            let (expr, pos) = parse_call(tokens, pos)?;
+            match matchh(tokens, pos, increments) {
+                Some((op, pos)) => (expr, pos, op, true),
+                None => return Ok((expr, pos)),
+            }
+        }
+    };
+    if !is_target(&expr) {
+        return Err(ParseError::InvalidAssignment { equals: op });
     }
+    Ok((
+        Expr::Increment {
+            target: Box::new(expr),
+            op,
+            postfix,
+        },
+        pos,
+    ))
 }
 
-fn parse_call<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usize), ParseError> {
+fn parse_call<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
     let (mut expr, mut pos) = parse_primary(tokens, pos)?;
     loop {
         match &tokens[pos].token_type {
@@ -575,6 +1010,24 @@ fn parse_call<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usiz
                 expr = new_expr;
                 pos = new_pos;
             }
+            TokenType::Dot => {
+                let (name, new_pos) = consume(tokens, pos + 1, TokenType::Identifier)?;
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                };
+                pos = new_pos;
+            }
+            TokenType::LeftBracket => {
+                let (index, new_pos) = parse_expression(tokens, pos + 1)?;
+                let (bracket, new_pos) = consume(tokens, new_pos, TokenType::RightBracket)?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                };
+                pos = new_pos;
+            }
             _ => break,
         }
     }
@@ -615,7 +1068,96 @@ fn parse_call_finish<'a>(
     ))
 }
 
-fn parse_primary<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, usize), ParseError> {
+// The text of a string token without its delimiters, which are quotes or the
+// braces around interpolated expressions.
+fn string_contents<'a>(token: &Token<'a>) -> &'a ByteSlice {
+    let lexeme = token.lexeme;
+    match token.token_type {
+        TokenType::Interpolation => &lexeme[1..lexeme.len() - 2],
+        _ => &lexeme[1..lexeme.len() - 1],
+    }
+}
+
+fn parse_interpolation<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    let start = &tokens[pos];
+    let mut parts = vec![Expr::StringLiteral(string_contents(start))];
+    let mut pos = pos;
+    loop {
+        let (expr, new_pos) = parse_expression(tokens, pos + 1)?;
+        parts.push(expr);
+        pos = new_pos;
+        // the rest of the string starts with the brace closing the expression
+        let token = &tokens[pos];
+        match token.token_type {
+            TokenType::String | TokenType::Interpolation if token.lexeme[0] == b'}' => {
+                parts.push(Expr::StringLiteral(string_contents(token)));
+                if token.token_type == TokenType::String {
+                    return Ok((Expr::Interpolation { start, parts }, pos + 1));
+                }
+            }
+            _ => {
+                return Err(ParseError::ExpectedSomething {
+                    actual: token,
+                    expected: TokenType::RightBrace,
+                })
+            }
+        }
+    }
+}
+
+// Whether the parenthesis at `pos` opens the parameters of an arrow function
+// rather than a grouping, which takes looking for the arrow after them.
+fn starts_arrow(tokens: &[Token<'_>], pos: usize) -> bool {
+    let mut pos = pos + 1;
+    if tokens[pos].token_type != TokenType::RightParen {
+        loop {
+            if tokens[pos].token_type != TokenType::Identifier {
+                return false;
+            }
+            pos += 1;
+            if tokens[pos].token_type != TokenType::Comma {
+                break;
+            }
+            pos += 1;
+        }
+    }
+    tokens[pos].token_type == TokenType::RightParen
+        && tokens[pos + 1].token_type == TokenType::Arrow
+}
+
+// `(params) => body`, where a body other than a block is an expression that
+// gets returned
+fn parse_arrow<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
+    let (params, pos) = parse_parameters(tokens, pos)?;
+    let (keyword, pos) = consume(tokens, pos, TokenType::Arrow)?;
+    let (body, pos) = match tokens[pos].token_type {
+        TokenType::LeftBrace => parse_block(tokens, pos + 1)?,
+        _ => {
+            let (value, pos) = parse_expression(tokens, pos)?;
+            let value = Some(value);
+            (Stmt::Return { keyword, value }, pos)
+        }
+    };
+    Ok((
+        Expr::Lambda {
+            keyword,
+            params,
+            body: Box::new(body),
+        },
+        pos,
+    ))
+}
+
+fn parse_primary<'a>(
+    tokens: &'a [Token<'a>],
+    pos: usize,
+) -> Result<(Expr<'a>, usize), ParseError<'a>> {
     let token = &tokens[pos];
     match &token.token_type {
         TokenType::False => Ok((Expr::FalseLiteral, pos + 1)),
@@ -623,7 +1165,26 @@ fn parse_primary<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, u
         TokenType::Nil => Ok((Expr::NilLiteral, pos + 1)),
 
         TokenType::Number => Ok((Expr::NumericLiteral(token.lexeme), pos + 1)),
-        TokenType::String => Ok((Expr::StringLiteral(token.lexeme), pos + 1)),
+        // strings starting with `}` continue an interpolated string
+        TokenType::String if token.lexeme[0] == b'"' => {
+            Ok((Expr::StringLiteral(string_contents(token)), pos + 1))
+        }
+        TokenType::Interpolation if token.lexeme[0] == b'"' => parse_interpolation(tokens, pos),
+
+        TokenType::Fun => {
+            let (params, pos) = parse_parameters(tokens, pos + 1)?;
+            let (_, pos) = consume(tokens, pos, TokenType::LeftBrace)?;
+            let (body, pos) = parse_block(tokens, pos)?;
+            Ok((
+                Expr::Lambda {
+                    keyword: token,
+                    params,
+                    body: Box::new(body),
+                },
+                pos,
+            ))
+        }
+        TokenType::LeftParen if starts_arrow(tokens, pos) => parse_arrow(tokens, pos),
 
         TokenType::LeftParen => {
             let (expr, pos) = parse_expression(tokens, pos + 1)?;
@@ -631,6 +1192,47 @@ fn parse_primary<'a>(tokens: &'a [Token<'a>], pos: usize) -> Result<(Expr<'a>, u
             Ok(((Expr::Grouping(Box::new(expr))), pos))
         }
 
+        TokenType::LeftBracket => {
+            let mut pos = pos + 1;
+            let mut elements = Vec::new();
+            if tokens[pos].token_type != TokenType::RightBracket {
+                loop {
+                    let (element, new_pos) = parse_expression(tokens, pos)?;
+                    elements.push(element);
+                    pos = new_pos;
+                    if tokens[pos].token_type == TokenType::Comma {
+                        pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+            let (bracket, pos) = consume(tokens, pos, TokenType::RightBracket)?;
+            Ok((Expr::List { bracket, elements }, pos))
+        }
+
+        // blocks are parsed as statements, so braces here always start a map
+        TokenType::LeftBrace => {
+            let mut pos = pos + 1;
+            let mut entries = Vec::new();
+            if tokens[pos].token_type != TokenType::RightBrace {
+                loop {
+                    let (key, new_pos) = parse_expression(tokens, pos)?;
+                    let (_, new_pos) = consume(tokens, new_pos, TokenType::Colon)?;
+                    let (value, new_pos) = parse_expression(tokens, new_pos)?;
+                    entries.push((key, value));
+                    pos = new_pos;
+                    if tokens[pos].token_type == TokenType::Comma {
+                        pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+            let (brace, pos) = consume(tokens, pos, TokenType::RightBrace)?;
+            Ok((Expr::Map { brace, entries }, pos))
+        }
+
         TokenType::Identifier => Ok((Expr::Variable(token), pos + 1)),
 
         _ => Err(ParseError::InvalidToken { token }),
@@ -16,6 +16,10 @@ pub enum ParseError<'a> {
     InvalidAssignment {
         equals: &'a Token<'a>,
     },
+    // the first token of a pattern that isn't a literal or a range of numbers
+    InvalidPattern {
+        token: &'a Token<'a>,
+    },
 }
 
 impl<'a> Error for ParseError<'a> {}
@@ -26,6 +30,14 @@ impl<'a> Display for ParseError<'a> {
             ParseError::InvalidAssignment { equals } => {
                 write!(f, "Invalid assignment target on line {}", equals.line)
             }
+            ParseError::InvalidPattern { token } => {
+                write!(
+                    f,
+                    "Expected a literal or a range of numbers as a pattern at {} on line {}",
+                    str::from_utf8(token.lexeme).unwrap(),
+                    token.line
+                )
+            }
             ParseError::InvalidToken { token } => {
                 write!(
                     f,
@@ -1,13 +1,14 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use crate::{expr::*, parser::*, scanner};
+    use crate::{expr::*, parser::*, scanner, stmt::Pattern};
 
     #[test]
     fn test_parse_literal() {
         let source = "123\n\n".as_bytes();
         let tokens = scanner::scan(source).unwrap();
         let (actual, _) = parse_primary(&tokens, 0).unwrap();
-        let expected = Expr::NumericLiteral(123.);
+        let expected = Expr::NumericLiteral(b"123");
         assert_eq!(actual, expected);
     }
 
@@ -120,4 +121,319 @@ mod tests {
         let (actual, _) = parse_primary(&tokens, 0).unwrap();
         assert!(matches!(actual, Expr::Grouping(..)))
     }
+
+    #[test]
+    fn test_property_access() {
+        let source = "a.b(1).c = 2".as_bytes();
+        let tokens = scanner::scan(source).unwrap();
+        let (actual, _) = parse_expression(&tokens, 0).unwrap();
+        match actual {
+            Expr::Set { object, name, .. } => {
+                assert_eq!(name.lexeme, b"c");
+                assert!(matches!(*object, Expr::Call { .. }));
+            }
+            _ => panic!("expected a set expression"),
+        }
+    }
+
+    #[test]
+    fn test_list_index() {
+        let source = "[1, [2]][0] = []".as_bytes();
+        let tokens = scanner::scan(source).unwrap();
+        let (actual, _) = parse_expression(&tokens, 0).unwrap();
+        match actual {
+            Expr::SetIndex { object, value, .. } => {
+                match *object {
+                    Expr::List { elements, .. } => assert_eq!(elements.len(), 2),
+                    _ => panic!("expected a list literal"),
+                }
+                assert!(matches!(*value, Expr::List { elements, .. } if elements.is_empty()));
+            }
+            _ => panic!("expected an index assignment"),
+        }
+    }
+
+    #[test]
+    fn test_map_literal() {
+        let source = "var m = {\"a\": {}, 1: [2]}; { m; }".as_bytes();
+        let tokens = scanner::scan(source).unwrap();
+        let (statements, errors) = parse(&tokens);
+        assert!(errors.is_empty());
+        match &statements[0] {
+            Stmt::Var(_, Some(Expr::Map { entries, .. })) => {
+                assert_eq!(entries.len(), 2);
+                assert!(matches!(&entries[0].1, Expr::Map { entries, .. } if entries.is_empty()));
+            }
+            _ => panic!("expected a map literal"),
+        }
+        // braces starting a statement are still a block
+        assert!(matches!(statements[1], Stmt::Block(_)));
+    }
+
+    #[test]
+    fn test_for_in() {
+        let source = "for (var i in 0..n + 1) print i; for (var x in) print x;";
+        let tokens = scanner::scan(source.as_bytes()).unwrap();
+        let (statements, errors) = parse(&tokens);
+        match &statements[0] {
+            Stmt::ForIn { name, iterable, .. } => {
+                assert_eq!(name.lexeme, b"i");
+                match iterable {
+                    Expr::Binary { op, right, .. } => {
+                        assert_eq!(op.token_type, TokenType::DotDot);
+                        assert!(matches!(**right, Expr::Binary { .. }));
+                    }
+                    _ => panic!("expected a range"),
+                }
+            }
+            _ => panic!("expected a for-in loop"),
+        }
+        // the loop needs something to iterate over
+        assert!(matches!(
+            errors[0],
+            ParseError::InvalidToken { token } if token.token_type == TokenType::RightParen
+        ));
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let source = r#""a\n${b}c${1 + 2}""#;
+        let tokens = scanner::scan(source.as_bytes()).unwrap();
+        let (expr, pos) = parse_expression(&tokens, 0).unwrap();
+        assert_eq!(pos, tokens.len() - 1);
+        match expr {
+            Expr::Interpolation { parts, .. } => {
+                assert_eq!(parts.len(), 5);
+                assert_eq!(parts[0], Expr::StringLiteral(br"a\n"));
+                assert!(matches!(parts[1], Expr::Variable(_)));
+                assert_eq!(parts[2], Expr::StringLiteral(b"c"));
+                assert!(matches!(parts[3], Expr::Binary { .. }));
+                assert_eq!(parts[4], Expr::StringLiteral(b""));
+            }
+            _ => panic!("expected an interpolated string"),
+        }
+
+        let tokens = scanner::scan(br#"print "${}";"#).unwrap();
+        let (_, errors) = parse(&tokens);
+        assert!(matches!(
+            errors[0],
+            ParseError::InvalidToken { token } if token.lexeme == br#"}""#
+        ));
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let source = "for (var i = 0; i < 3; i = i + 1) { break; continue; }";
+        let tokens = scanner::scan(source.as_bytes()).unwrap();
+        let (statements, errors) = parse(&tokens);
+        assert!(errors.is_empty());
+        let Stmt::Block(statements) = &statements[0] else {
+            panic!("expected the loop in a block with its initializer");
+        };
+        match &statements[1] {
+            Stmt::While {
+                body, increment, ..
+            } => {
+                assert!(matches!(increment, Some(Expr::Assign { .. })));
+                assert!(matches!(
+                    &**body,
+                    Stmt::Block(body) if matches!(body[..], [Stmt::Break(_), Stmt::Continue(_)])
+                ));
+            }
+            _ => panic!("expected a while loop"),
+        }
+    }
+
+    #[test]
+    fn test_match() {
+        let source = "match (x) { case 1, -2..3 => print 1; case \"a\" => {} default => print 2; }";
+        let tokens = scanner::scan(source.as_bytes()).unwrap();
+        let (statements, errors) = parse(&tokens);
+        assert!(errors.is_empty());
+        match &statements[0] {
+            Stmt::Match { cases, default, .. } => {
+                assert_eq!(cases.len(), 2);
+                assert!(matches!(
+                    cases[0].0[..],
+                    [
+                        Pattern::Literal(Expr::NumericLiteral(b"1")),
+                        Pattern::Range { .. }
+                    ]
+                ));
+                assert!(matches!(cases[1].1, Stmt::Block(_)));
+                assert!(matches!(default.as_deref(), Some(Stmt::Print(_))));
+            }
+            _ => panic!("expected a match statement"),
+        }
+
+        for source in [
+            "match (x) { case y => print 1; }",
+            "match (x) { case 1..\"a\" => {} }",
+        ] {
+            let tokens = scanner::scan(source.as_bytes()).unwrap();
+            let (_, errors) = parse(&tokens);
+            assert!(matches!(errors[0], ParseError::InvalidPattern { .. }));
+        }
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let source = "fun (a) { return a; }(1); var f = () => 1; g((a, b) => { print a; }, (c));";
+        let tokens = scanner::scan(source.as_bytes()).unwrap();
+        let (statements, errors) = parse(&tokens);
+        assert!(errors.is_empty());
+        match &statements[0] {
+            Stmt::Expression(Expr::Call { callee, .. }) => assert!(matches!(
+                &**callee,
+                Expr::Lambda { params, body, .. } if params.len() == 1 && matches!(**body, Stmt::Block(_))
+            )),
+            _ => panic!("expected a call of a lambda"),
+        }
+        match &statements[1] {
+            Stmt::Var(_, Some(Expr::Lambda { params, body, .. })) => {
+                assert!(params.is_empty());
+                assert!(matches!(
+                    **body,
+                    Stmt::Return {
+                        value: Some(Expr::NumericLiteral(b"1")),
+                        ..
+                    }
+                ));
+            }
+            _ => panic!("expected an arrow function"),
+        }
+        match &statements[2] {
+            Stmt::Expression(Expr::Call { arguments, .. }) => {
+                assert!(matches!(&arguments[0], Expr::Lambda { params, .. } if params.len() == 2));
+                assert!(matches!(&arguments[1], Expr::Grouping(_)));
+            }
+            _ => panic!("expected a call"),
+        }
+    }
+
+    #[test]
+    fn test_try_and_throw() {
+        let source = "try { throw 1; } catch (e) { print e; } finally {} try {} finally {}";
+        let tokens = scanner::scan(source.as_bytes()).unwrap();
+        let (statements, errors) = parse(&tokens);
+        assert!(errors.is_empty());
+        match &statements[0] {
+            Stmt::Try {
+                body,
+                catch: Some((name, handler)),
+                finally: Some(_),
+                ..
+            } => {
+                assert!(
+                    matches!(&**body, Stmt::Block(body) if matches!(body[..], [Stmt::Throw { .. }]))
+                );
+                assert_eq!(name.lexeme, b"e");
+                assert!(matches!(**handler, Stmt::Block(_)));
+            }
+            _ => panic!("expected a try statement with both clauses"),
+        }
+        assert!(matches!(
+            statements[1],
+            Stmt::Try {
+                catch: None,
+                finally: Some(_),
+                ..
+            }
+        ));
+
+        for source in [
+            "try {} print 1;",
+            "try print 1; catch (e) {}",
+            "try {} catch {}",
+        ] {
+            let tokens = scanner::scan(source.as_bytes()).unwrap();
+            let (_, errors) = parse(&tokens);
+            assert!(matches!(errors[0], ParseError::ExpectedSomething { .. }));
+        }
+    }
+
+    // the expression with parentheses around every operation
+    fn show(expr: &Expr) -> String {
+        let text = |token: &Token| String::from_utf8_lossy(token.lexeme).into_owned();
+        match expr {
+            Expr::NumericLiteral(n) => String::from_utf8_lossy(n).into_owned(),
+            Expr::Variable(name) => text(name),
+            Expr::Grouping(expr) => show(expr),
+            Expr::Unary { op, expr } => format!("({}{})", text(op), show(expr)),
+            Expr::Binary { left, op, right } | Expr::Logical { left, op, right } => {
+                format!("({} {} {})", show(left), text(op), show(right))
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => format!(
+                "({} ? {} : {})",
+                show(condition),
+                show(then_branch),
+                show(else_branch)
+            ),
+            Expr::Assign { name, value } => format!("({} = {})", text(name), show(value)),
+            Expr::Compound { target, op, value } => {
+                format!("({} {} {})", show(target), text(op), show(value))
+            }
+            Expr::Increment {
+                target,
+                op,
+                postfix: true,
+            } => format!("({}{})", show(target), text(op)),
+            Expr::Increment { target, op, .. } => format!("({}{})", text(op), show(target)),
+            Expr::Index { object, index, .. } => format!("{}[{}]", show(object), show(index)),
+            _ => panic!("unexpected expression {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        for (source, expected) in [
+            ("1 + 2 % 3 * 4", "(1 + ((2 % 3) * 4))"),
+            ("-2 ** 2", "(-(2 ** 2))"),
+            ("2 ** 3 ** 2", "(2 ** (3 ** 2))"),
+            ("2 ** -1", "(2 ** (-1))"),
+            ("~a & b", "((~a) & b)"),
+            (
+                "1 | 2 ^ 3 & 4 << 5 + 6 >> 7",
+                "(1 | (2 ^ (3 & ((4 << (5 + 6)) >> 7))))",
+            ),
+            ("0 .. 1 | 2", "(0 .. (1 | 2))"),
+            ("a < b | c == d", "((a < (b | c)) == d)"),
+            ("a or b ? c : d ? e : f", "((a or b) ? c : (d ? e : f))"),
+            ("a ? b = 1 : c", "(a ? (b = 1) : c)"),
+            ("a += b -= c ? 1 : 2", "(a += (b -= (c ? 1 : 2)))"),
+            ("-a++ * --l[i]", "((-(a++)) * (--l[i]))"),
+            ("++a ** 2", "((++a) ** 2)"),
+        ] {
+            let tokens = scanner::scan(source.as_bytes()).unwrap();
+            let (expr, pos) = parse_expression(&tokens, 0).unwrap();
+            assert_eq!(tokens[pos].token_type, TokenType::Eof, "{}", source);
+            assert_eq!(show(&expr), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_invalid_updates() {
+        for source in ["1 += 2;", "a + b -= 1;", "++1;", "(a)++;", "f()--;"] {
+            let tokens = scanner::scan(source.as_bytes()).unwrap();
+            let (_, errors) = parse(&tokens);
+            assert!(
+                matches!(errors[0], ParseError::InvalidAssignment { .. }),
+                "{}",
+                source
+            );
+        }
+        let tokens = scanner::scan(b"a ? b;").unwrap();
+        let (_, errors) = parse(&tokens);
+        assert!(matches!(
+            errors[0],
+            ParseError::ExpectedSomething {
+                expected: TokenType::Colon,
+                ..
+            }
+        ));
+    }
 }
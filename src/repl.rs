@@ -0,0 +1,135 @@
+// Interactive prompt for `rlox-bytecode` without a script. Lines starting
+// with `:` are commands for looking into the interpreter, see `HELP`.
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use rlox_bytecode::{is_incomplete, CompileMode, Interpreter};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+const HELP: &str = "\
+:dis <code>        show the bytecode <code> compiles to
+:ast <code>        show the statements <code> parses into
+:globals           list the global variables
+:heap              show the objects allocated while running
+:trace on|off      print every instruction as it runs
+:load <file>       run a script
+:reset             forget every global and free every object
+:help              show this message";
+
+// where the REPL keeps the lines entered across sessions
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(".rlox_history"))
+}
+
+pub fn run(mut interpreter: Interpreter) -> Result<(), ReadlineError> {
+    // echo the value of expressions entered without `print`
+    interpreter.set_compile_mode(CompileMode::Repl);
+    let mut editor = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(history) = &history {
+        // there is no history yet the first time
+        let _ = editor.load_history(history);
+    }
+    let mut source = String::new();
+    loop {
+        let prompt = if source.is_empty() { "> " } else { "... " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                source.push_str(&line);
+                source.push('\n');
+            }
+            // Ctrl-C drops what has been typed so far
+            Err(ReadlineError::Interrupted) => {
+                source.clear();
+                continue;
+            }
+            // Ctrl-D
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(error),
+        }
+        if source.trim().is_empty() {
+            source.clear();
+            continue;
+        }
+        if let Some(command) = source.trim().strip_prefix(':') {
+            editor.add_history_entry(source.trim())?;
+            run_command(&mut interpreter, command);
+            source.clear();
+            continue;
+        }
+        if is_incomplete(&source) {
+            continue;
+        }
+        editor.add_history_entry(source.trim_end())?;
+        if let Err(e) = interpreter.eval(as_statement(source.trim_end())) {
+            eprintln!("{}", e);
+        }
+        source.clear();
+    }
+    if let Some(history) = &history {
+        if let Err(error) = editor.save_history(history) {
+            eprintln!("Could not save history to {}: {}", history.display(), error);
+        }
+    }
+    Ok(())
+}
+
+// entries, `:dis` and `:ast` take expressions as well as statements, so a
+// bare `a + 2` is echoed like `a + 2;`
+fn as_statement(code: &str) -> String {
+    if code.ends_with(';') || code.ends_with('}') {
+        code.to_string()
+    } else {
+        format!("{};", code)
+    }
+}
+
+fn run_command(interpreter: &mut Interpreter, command: &str) {
+    let (name, arg) = match command.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (command, ""),
+    };
+    match (name, arg) {
+        ("dis", code) if !code.is_empty() => match interpreter.disassemble(as_statement(code)) {
+            Ok(disassembly) => print!("{}", disassembly),
+            Err(error) => eprintln!("{}", error),
+        },
+        ("ast", code) if !code.is_empty() => match Interpreter::ast(as_statement(code)) {
+            Ok(ast) => println!("{}", ast),
+            Err(error) => eprintln!("{}", error),
+        },
+        ("globals", "") => {
+            for (name, value) in interpreter.globals() {
+                println!("{} = {}", name, value);
+            }
+        }
+        ("heap", "") => {
+            let stats = interpreter.heap_stats();
+            println!(
+                "{} objects, about {} bytes, next collection at {} objects",
+                stats.objects, stats.bytes, stats.next_gc
+            );
+            for (kind, count) in stats.kinds {
+                println!("  {}: {}", kind, count);
+            }
+        }
+        ("trace", "on") => interpreter.set_trace(true),
+        ("trace", "off") => interpreter.set_trace(false),
+        ("load", path) if !path.is_empty() => match fs::read(path) {
+            Ok(source) => {
+                // scripts behave the same as when they are run on their own
+                interpreter.set_compile_mode(CompileMode::Script);
+                if let Err(error) = interpreter.eval(&source) {
+                    eprintln!("{}", error);
+                }
+                interpreter.set_compile_mode(CompileMode::Repl);
+            }
+            Err(error) => eprintln!("Could not read {}: {}", path, error),
+        },
+        ("reset", "") => interpreter.reset(),
+        ("help", "") => println!("{}", HELP),
+        _ => eprintln!("Unknown command :{}, see :help", command),
+    }
+}
@@ -1,24 +1,56 @@
-mod scan_error;
+pub mod scan_error;
 mod tests;
 
+use std::{iter::Peekable, slice::Iter, str};
+
 use crate::{
-    byte_string::ByteSlice,
+    byte_string::{ByteSlice, ByteVector},
     token::Token,
     token_type::{string_to_keyword, TokenType},
 };
 
 use self::scan_error::ScanError;
 
+type Chars<'a> = Peekable<std::iter::Enumerate<Iter<'a, u8>>>;
+
 pub fn scan(src: &'_ ByteSlice) -> Result<Vec<Token<'_>>, ScanError> {
+    let text = str::from_utf8(src).map_err(|error| {
+        let valid = &src[..error.valid_up_to()];
+        ScanError::InvalidUtf8(valid.iter().filter(|&&c| c == b'\n').count() + 1)
+    })?;
     let mut line = 1;
     let mut tokens = Vec::new();
     let mut chars = src.iter().enumerate().peekable();
+    // the braces opened in each interpolated expression we are in, the string
+    // continues after the `}` that closes the expression
+    let mut interpolations: Vec<usize> = Vec::new();
     while let Some((idx, c)) = chars.next() {
         match c {
+            b'"' => {
+                let (token_type, end_idx) = scan_string(src, &mut chars, &mut line)?;
+                if token_type == TokenType::Interpolation {
+                    interpolations.push(0);
+                }
+                tokens.push(Token::new(token_type, &src[idx..=end_idx], line));
+            }
+            b'}' if interpolations.last() == Some(&0) => {
+                interpolations.pop();
+                let (token_type, end_idx) = scan_string(src, &mut chars, &mut line)?;
+                if token_type == TokenType::Interpolation {
+                    interpolations.push(0);
+                }
+                tokens.push(Token::new(token_type, &src[idx..=end_idx], line));
+            }
             b'{' => {
+                if let Some(braces) = interpolations.last_mut() {
+                    *braces += 1;
+                }
                 tokens.push(Token::new(TokenType::LeftBrace, &src[idx..=idx], line));
             }
             b'}' => {
+                if let Some(braces) = interpolations.last_mut() {
+                    *braces -= 1;
+                }
                 tokens.push(Token::new(TokenType::RightBrace, &src[idx..=idx], line));
             }
             b'(' => {
@@ -27,14 +59,52 @@ pub fn scan(src: &'_ ByteSlice) -> Result<Vec<Token<'_>>, ScanError> {
             b')' => {
                 tokens.push(Token::new(TokenType::RightParen, &src[idx..=idx], line));
             }
+            b'[' => {
+                tokens.push(Token::new(TokenType::LeftBracket, &src[idx..=idx], line));
+            }
+            b']' => {
+                tokens.push(Token::new(TokenType::RightBracket, &src[idx..=idx], line));
+            }
             b'.' => {
-                tokens.push(Token::new(TokenType::Dot, &src[idx..=idx], line));
+                if let Some((end_idx, b'.')) = chars.peek() {
+                    tokens.push(Token::new(TokenType::DotDot, &src[idx..=*end_idx], line));
+                    chars.next();
+                } else {
+                    tokens.push(Token::new(TokenType::Dot, &src[idx..=idx], line));
+                }
             }
             b'-' => {
-                tokens.push(Token::new(TokenType::Minus, &src[idx..=idx], line));
+                if let Some((end_idx, b'-')) = chars.peek() {
+                    tokens.push(Token::new(
+                        TokenType::MinusMinus,
+                        &src[idx..=*end_idx],
+                        line,
+                    ));
+                    chars.next();
+                } else if let Some((end_idx, b'=')) = chars.peek() {
+                    tokens.push(Token::new(
+                        TokenType::MinusEqual,
+                        &src[idx..=*end_idx],
+                        line,
+                    ));
+                    chars.next();
+                } else {
+                    tokens.push(Token::new(TokenType::Minus, &src[idx..=idx], line));
+                }
             }
             b'+' => {
-                tokens.push(Token::new(TokenType::Plus, &src[idx..=idx], line));
+                if let Some((end_idx, b'+')) = chars.peek() {
+                    tokens.push(Token::new(TokenType::PlusPlus, &src[idx..=*end_idx], line));
+                    chars.next();
+                } else if let Some((end_idx, b'=')) = chars.peek() {
+                    tokens.push(Token::new(TokenType::PlusEqual, &src[idx..=*end_idx], line));
+                    chars.next();
+                } else {
+                    tokens.push(Token::new(TokenType::Plus, &src[idx..=idx], line));
+                }
+            }
+            b':' => {
+                tokens.push(Token::new(TokenType::Colon, &src[idx..=idx], line));
             }
             b',' => {
                 tokens.push(Token::new(TokenType::Comma, &src[idx..=idx], line));
@@ -43,7 +113,33 @@ pub fn scan(src: &'_ ByteSlice) -> Result<Vec<Token<'_>>, ScanError> {
                 tokens.push(Token::new(TokenType::Semicolon, &src[idx..=idx], line));
             }
             b'*' => {
-                tokens.push(Token::new(TokenType::Star, &src[idx..=idx], line));
+                if let Some((end_idx, b'*')) = chars.peek() {
+                    tokens.push(Token::new(TokenType::StarStar, &src[idx..=*end_idx], line));
+                    chars.next();
+                } else if let Some((end_idx, b'=')) = chars.peek() {
+                    tokens.push(Token::new(TokenType::StarEqual, &src[idx..=*end_idx], line));
+                    chars.next();
+                } else {
+                    tokens.push(Token::new(TokenType::Star, &src[idx..=idx], line));
+                }
+            }
+            b'%' => {
+                tokens.push(Token::new(TokenType::Percent, &src[idx..=idx], line));
+            }
+            b'&' => {
+                tokens.push(Token::new(TokenType::Ampersand, &src[idx..=idx], line));
+            }
+            b'|' => {
+                tokens.push(Token::new(TokenType::Pipe, &src[idx..=idx], line));
+            }
+            b'^' => {
+                tokens.push(Token::new(TokenType::Caret, &src[idx..=idx], line));
+            }
+            b'~' => {
+                tokens.push(Token::new(TokenType::Tilde, &src[idx..=idx], line));
+            }
+            b'?' => {
+                tokens.push(Token::new(TokenType::Question, &src[idx..=idx], line));
             }
             b'=' => {
                 if let Some((end_idx, b'=')) = chars.peek() {
@@ -53,6 +149,9 @@ pub fn scan(src: &'_ ByteSlice) -> Result<Vec<Token<'_>>, ScanError> {
                         line,
                     ));
                     chars.next();
+                } else if let Some((end_idx, b'>')) = chars.peek() {
+                    tokens.push(Token::new(TokenType::Arrow, &src[idx..=*end_idx], line));
+                    chars.next();
                 } else {
                     tokens.push(Token::new(TokenType::Equal, &src[idx..=idx], line));
                 }
@@ -73,6 +172,13 @@ pub fn scan(src: &'_ ByteSlice) -> Result<Vec<Token<'_>>, ScanError> {
                         line,
                     ));
                     chars.next();
+                } else if let Some((end_idx, b'>')) = chars.peek() {
+                    tokens.push(Token::new(
+                        TokenType::GreaterGreater,
+                        &src[idx..=*end_idx],
+                        line,
+                    ));
+                    chars.next();
                 } else {
                     tokens.push(Token::new(TokenType::Greater, &src[idx..=idx], line));
                 }
@@ -81,6 +187,9 @@ pub fn scan(src: &'_ ByteSlice) -> Result<Vec<Token<'_>>, ScanError> {
                 if let Some((end_idx, b'=')) = chars.peek() {
                     tokens.push(Token::new(TokenType::LessEqual, &src[idx..=*end_idx], line));
                     chars.next();
+                } else if let Some((end_idx, b'<')) = chars.peek() {
+                    tokens.push(Token::new(TokenType::LessLess, &src[idx..=*end_idx], line));
+                    chars.next();
                 } else {
                     tokens.push(Token::new(TokenType::Less, &src[idx..=idx], line));
                 }
@@ -94,26 +203,17 @@ pub fn scan(src: &'_ ByteSlice) -> Result<Vec<Token<'_>>, ScanError> {
                             chars.next();
                         }
                     }
+                } else if let Some((end_idx, b'=')) = chars.peek() {
+                    tokens.push(Token::new(
+                        TokenType::SlashEqual,
+                        &src[idx..=*end_idx],
+                        line,
+                    ));
+                    chars.next();
                 } else {
                     tokens.push(Token::new(TokenType::Slash, &src[idx..=idx], line));
                 }
             }
-            b'"' => {
-                for (end_idx, c) in chars.by_ref() {
-                    match c {
-                        b'"' => {
-                            let lexeme = &src[idx..=end_idx];
-                            tokens.push(Token::new(TokenType::String, lexeme, line));
-                            break;
-                        }
-                        b'\n' => line += 1,
-                        _ => (),
-                    }
-                }
-                // if chars.peek() == None {
-                //     return Err(ScanError::UnterminatedString(line));
-                // }
-            }
             digit if digit.is_ascii_digit() => {
                 let mut end_idx = idx;
                 let mut peek = chars.clone();
@@ -145,16 +245,14 @@ pub fn scan(src: &'_ ByteSlice) -> Result<Vec<Token<'_>>, ScanError> {
                 let lexeme = &src[idx..=end_idx];
                 tokens.push(Token::new(TokenType::Number, lexeme, line));
             }
-            alpha if alpha.is_ascii_alphabetic() => {
-                let mut end_idx = idx;
-                while let Some((_, maybe_alnum)) = chars.peek() {
-                    if !maybe_alnum.is_ascii_alphanumeric() {
-                        break;
-                    }
-                    end_idx += 1;
-                    chars.next();
-                }
-                let lexeme = &src[idx..=end_idx];
+            _ if text[idx..].starts_with(|c: char| c.is_alphabetic() || c == '_') => {
+                // identifiers can have letters and digits from any script
+                let rest = &text[idx..];
+                let len = (rest.char_indices())
+                    .find(|(_, c)| !c.is_alphanumeric() && *c != '_')
+                    .map_or(rest.len(), |(len, _)| len);
+                let lexeme = &src[idx..idx + len];
+                while chars.next_if(|(end_idx, _)| *end_idx < idx + len).is_some() {}
                 match string_to_keyword(lexeme) {
                     Some(keyword) => tokens.push(Token::new(keyword, lexeme, line)),
                     None => tokens.push(Token::new(TokenType::Identifier, lexeme, line)),
@@ -165,9 +263,94 @@ pub fn scan(src: &'_ ByteSlice) -> Result<Vec<Token<'_>>, ScanError> {
             b'\r' => (),
             b'\t' => (),
 
-            c => return Err(ScanError::UnexpectedChar(*c, line)),
+            _ => {
+                let c = text[idx..].chars().next().unwrap();
+                return Err(ScanError::UnexpectedChar(c, line));
+            }
         }
     }
+    if !interpolations.is_empty() {
+        return Err(ScanError::UnterminatedString(line));
+    }
     tokens.push(Token::new(TokenType::Eof, b"", line));
     Ok(tokens)
 }
+
+// Scans the rest of a string up to its closing quote, or up to the `${` of an
+// interpolated expression. Returns which of the two it found and where.
+fn scan_string(
+    src: &ByteSlice,
+    chars: &mut Chars<'_>,
+    line: &mut usize,
+) -> Result<(TokenType, usize), ScanError> {
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            b'"' => return Ok((TokenType::String, idx)),
+            b'$' if matches!(chars.peek(), Some((_, b'{'))) => {
+                let (end_idx, _) = chars.next().unwrap();
+                return Ok((TokenType::Interpolation, end_idx));
+            }
+            b'\\' => match escape(&src[idx + 1..]) {
+                Some((_, len)) => {
+                    chars.nth(len - 1);
+                }
+                None => {
+                    let end = (idx + 2).min(src.len());
+                    let sequence = String::from_utf8_lossy(&src[idx..end]).into();
+                    return Err(ScanError::InvalidEscape(sequence, *line));
+                }
+            },
+            b'\n' => *line += 1,
+            _ => (),
+        }
+    }
+    Err(ScanError::UnterminatedString(*line))
+}
+
+// Decodes the escape sequence at the start of `bytes`, which comes right
+// after a backslash. Returns the character along with the length of the
+// sequence, or `None` if it isn't a valid one.
+fn escape(bytes: &ByteSlice) -> Option<(char, usize)> {
+    let c = match bytes.first()? {
+        b'n' => '\n',
+        b't' => '\t',
+        b'r' => '\r',
+        b'0' => '\0',
+        b'"' => '"',
+        b'\\' => '\\',
+        b'$' => '$',
+        // `\u{...}` with the code point in one to six hex digits
+        b'u' => {
+            if bytes.get(1) != Some(&b'{') {
+                return None;
+            }
+            let len = bytes[2..].iter().take(7).position(|&b| b == b'}')?;
+            let digits = &bytes[2..2 + len];
+            if digits.is_empty() || !digits.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            let code = u32::from_str_radix(str::from_utf8(digits).ok()?, 16).ok()?;
+            return char::from_u32(code).map(|c| (c, len + 3));
+        }
+        _ => return None,
+    };
+    Some((c, 1))
+}
+
+// Replaces the escape sequences in the contents of a string with the
+// characters they stand for. The scanner has already rejected invalid ones.
+pub fn unescape(contents: &ByteSlice) -> ByteVector {
+    let mut bytes = ByteVector::with_capacity(contents.len());
+    let mut idx = 0;
+    while idx < contents.len() {
+        if contents[idx] == b'\\' {
+            let (c, len) = escape(&contents[idx + 1..]).expect("invalid escape sequence");
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            idx += len + 1;
+        } else {
+            bytes.push(contents[idx]);
+            idx += 1;
+        }
+    }
+    bytes
+}
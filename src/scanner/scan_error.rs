@@ -1,12 +1,12 @@
 use core::fmt;
 use std::{error::Error, fmt::Display};
 
-use crate::byte_string::Byte;
-
 #[derive(Debug)]
 pub enum ScanError {
-    UnexpectedChar(Byte, usize),
+    UnexpectedChar(char, usize),
+    InvalidUtf8(usize),
     UnterminatedString(usize),
+    InvalidEscape(String, usize),
 }
 
 impl Error for ScanError {}
@@ -21,9 +21,19 @@ impl Display for ScanError {
                     c, line
                 )
             }
+            ScanError::InvalidUtf8(line) => {
+                write!(f, "Found invalid UTF-8 on line {}", line)
+            }
             ScanError::UnterminatedString(line) => {
                 write!(f, "Found unterminated string on line {}", line)
             }
+            ScanError::InvalidEscape(sequence, line) => {
+                write!(
+                    f,
+                    "Found invalid escape sequence {} in string on line {}",
+                    sequence, line
+                )
+            }
         }
     }
 }
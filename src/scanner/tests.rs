@@ -1,6 +1,11 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use crate::{scanner::scan, token::Token, token_type::TokenType};
+    use crate::{
+        scanner::{scan, scan_error::ScanError, unescape},
+        token::Token,
+        token_type::TokenType,
+    };
 
     #[test]
     fn leftparen_rightparen_bang() {
@@ -110,4 +115,186 @@ mod tests {
         ];
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_brackets() {
+        let source = "a[0] = [1]:";
+        let actual = scan(source.as_bytes()).unwrap();
+        let expected = vec![
+            Token::new(TokenType::Identifier, b"a", 1),
+            Token::new(TokenType::LeftBracket, b"[", 1),
+            Token::new(TokenType::Number, b"0", 1),
+            Token::new(TokenType::RightBracket, b"]", 1),
+            Token::new(TokenType::Equal, b"=", 1),
+            Token::new(TokenType::LeftBracket, b"[", 1),
+            Token::new(TokenType::Number, b"1", 1),
+            Token::new(TokenType::RightBracket, b"]", 1),
+            Token::new(TokenType::Colon, b":", 1),
+            Token::new(TokenType::Eof, b"", 1),
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_range() {
+        let source = "for (var i in 0..10.5)";
+        let actual = scan(source.as_bytes()).unwrap();
+        let expected = vec![
+            Token::new(TokenType::For, b"for", 1),
+            Token::new(TokenType::LeftParen, b"(", 1),
+            Token::new(TokenType::Var, b"var", 1),
+            Token::new(TokenType::Identifier, b"i", 1),
+            Token::new(TokenType::In, b"in", 1),
+            Token::new(TokenType::Number, b"0", 1),
+            Token::new(TokenType::DotDot, b"..", 1),
+            Token::new(TokenType::Number, b"10.5", 1),
+            Token::new(TokenType::RightParen, b")", 1),
+            Token::new(TokenType::Eof, b"", 1),
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_escapes() {
+        let source = r#""a\"b\\" "\u{1F600}\$""#;
+        let actual = scan(source.as_bytes()).unwrap();
+        let expected = vec![
+            Token::new(TokenType::String, br#""a\"b\\""#, 1),
+            Token::new(TokenType::String, br#""\u{1F600}\$""#, 1),
+            Token::new(TokenType::Eof, b"", 1),
+        ];
+        assert_eq!(actual, expected);
+        assert_eq!(unescape(br#"\n\t\r\0\"\\\$"#), b"\n\t\r\0\"\\$");
+        assert_eq!(unescape(br"caf\u{e9} \u{1F600}"), "café 😀".as_bytes());
+
+        for (source, sequence) in [
+            (r#""\q""#, r"\q"),
+            (r#""\u{110000}""#, r"\u"),
+            (r#""\u{}""#, r"\u"),
+            (r#""\u{d800}""#, r"\u"),
+        ] {
+            match scan(source.as_bytes()) {
+                Err(ScanError::InvalidEscape(actual, 1)) => assert_eq!(actual, sequence),
+                result => panic!("expected an invalid escape, got {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let source = r#""a ${b + "${c}"} ${ {}[1] }""#;
+        let actual = scan(source.as_bytes()).unwrap();
+        let expected = vec![
+            Token::new(TokenType::Interpolation, br#""a ${"#, 1),
+            Token::new(TokenType::Identifier, b"b", 1),
+            Token::new(TokenType::Plus, b"+", 1),
+            Token::new(TokenType::Interpolation, br#""${"#, 1),
+            Token::new(TokenType::Identifier, b"c", 1),
+            Token::new(TokenType::String, br#"}""#, 1),
+            Token::new(TokenType::Interpolation, b"} ${", 1),
+            Token::new(TokenType::LeftBrace, b"{", 1),
+            Token::new(TokenType::RightBrace, b"}", 1),
+            Token::new(TokenType::LeftBracket, b"[", 1),
+            Token::new(TokenType::Number, b"1", 1),
+            Token::new(TokenType::RightBracket, b"]", 1),
+            Token::new(TokenType::String, br#"}""#, 1),
+            Token::new(TokenType::Eof, b"", 1),
+        ];
+        assert_eq!(actual, expected);
+        assert!(matches!(
+            scan(br#""a ${b"#),
+            Err(ScanError::UnterminatedString(1))
+        ));
+    }
+
+    #[test]
+    fn test_unicode() {
+        let source = "var café = \"😀\"; 数2 _a_1";
+        let actual = scan(source.as_bytes()).unwrap();
+        let expected = vec![
+            Token::new(TokenType::Var, b"var", 1),
+            Token::new(TokenType::Identifier, "café".as_bytes(), 1),
+            Token::new(TokenType::Equal, b"=", 1),
+            Token::new(TokenType::String, "\"😀\"".as_bytes(), 1),
+            Token::new(TokenType::Semicolon, b";", 1),
+            Token::new(TokenType::Identifier, "数2".as_bytes(), 1),
+            Token::new(TokenType::Identifier, b"_a_1", 1),
+            Token::new(TokenType::Eof, b"", 1),
+        ];
+        assert_eq!(actual, expected);
+        assert!(matches!(
+            scan("a € b".as_bytes()),
+            Err(ScanError::UnexpectedChar('€', 1))
+        ));
+        assert!(matches!(
+            scan(b"print 1;\n\"\xff\";"),
+            Err(ScanError::InvalidUtf8(2))
+        ));
+    }
+
+    #[test]
+    fn test_arrow() {
+        let source = "case 1 => x == y";
+        let actual = scan(source.as_bytes()).unwrap();
+        let expected = vec![
+            Token::new(TokenType::Case, b"case", 1),
+            Token::new(TokenType::Number, b"1", 1),
+            Token::new(TokenType::Arrow, b"=>", 1),
+            Token::new(TokenType::Identifier, b"x", 1),
+            Token::new(TokenType::EqualEqual, b"==", 1),
+            Token::new(TokenType::Identifier, b"y", 1),
+            Token::new(TokenType::Eof, b"", 1),
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_operators() {
+        let source = "a%b**c&d|e^~f<<g>>h?i:j++--k+=l-=m*=n/=o- -p";
+        let actual = scan(source.as_bytes()).unwrap();
+        let types = actual
+            .iter()
+            .map(|token| token.token_type)
+            .collect::<Vec<_>>();
+        use TokenType::*;
+        let expected = vec![
+            Identifier,
+            Percent,
+            Identifier,
+            StarStar,
+            Identifier,
+            Ampersand,
+            Identifier,
+            Pipe,
+            Identifier,
+            Caret,
+            Tilde,
+            Identifier,
+            LessLess,
+            Identifier,
+            GreaterGreater,
+            Identifier,
+            Question,
+            Identifier,
+            Colon,
+            Identifier,
+            PlusPlus,
+            MinusMinus,
+            Identifier,
+            PlusEqual,
+            Identifier,
+            MinusEqual,
+            Identifier,
+            StarEqual,
+            Identifier,
+            SlashEqual,
+            Identifier,
+            Minus,
+            Minus,
+            Identifier,
+            Eof,
+        ];
+        assert_eq!(types, expected);
+        assert_eq!(actual[12].lexeme, b"<<");
+    }
 }
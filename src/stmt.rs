@@ -1,6 +1,6 @@
 use crate::{expr::Expr, token::Token};
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Stmt<'a> {
     Print(Expr<'a>),
     Return {
@@ -18,10 +18,48 @@ pub enum Stmt<'a> {
     While {
         condition: Expr<'a>,
         body: Box<Stmt<'a>>,
+        // the increment of a desugared `for` loop, which `continue` runs too
+        increment: Option<Expr<'a>>,
     },
+    // `for (var name in iterable) body`
+    ForIn {
+        name: &'a Token<'a>,
+        iterable: Expr<'a>,
+        body: Box<Stmt<'a>>,
+    },
+    // `match (subject) { case patterns => body ... default => body }`, which
+    // runs the body of the first case with a matching pattern
+    Match {
+        keyword: &'a Token<'a>,
+        subject: Expr<'a>,
+        cases: Vec<(Vec<Pattern<'a>>, Stmt<'a>)>,
+        default: Option<Box<Stmt<'a>>>,
+    },
+    Throw {
+        keyword: &'a Token<'a>,
+        value: Expr<'a>,
+    },
+    // `try body catch (name) handler finally finally`, with at least one of
+    // the two clauses
+    Try {
+        keyword: &'a Token<'a>,
+        body: Box<Stmt<'a>>,
+        catch: Option<(&'a Token<'a>, Box<Stmt<'a>>)>,
+        finally: Option<Box<Stmt<'a>>>,
+    },
+    Break(&'a Token<'a>),
+    Continue(&'a Token<'a>),
     Function {
         name: &'a Token<'a>,
         params: Vec<&'a Token<'a>>,
         body: Box<Stmt<'a>>,
     },
 }
+
+#[derive(Debug, PartialEq)]
+pub enum Pattern<'a> {
+    // matches values equal to a literal, as with `==`
+    Literal(Expr<'a>),
+    // matches numbers from `start` up to but not including `end`
+    Range { start: Expr<'a>, end: Expr<'a> },
+}
@@ -7,6 +7,9 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -14,37 +17,67 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    Question,
 
     // One or two character tokens.
+    Arrow,
+    DotDot,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
     Greater,
     GreaterEqual,
+    GreaterGreater,
     Less,
     LessEqual,
+    LessLess,
+    MinusMinus,
+    MinusEqual,
+    PlusPlus,
+    PlusEqual,
+    StarStar,
+    StarEqual,
+    SlashEqual,
 
     // Literals.
     Identifier,
     String,
+    // the part of a string up to and including the `${` of an interpolated
+    // expression, the rest of the string follows the expression
+    Interpolation,
     Number,
 
     // Keywords.
     And,
+    Break,
+    Case,
+    Catch,
     Class,
+    Continue,
+    Default,
     Else,
     False,
+    Finally,
     Fun,
     For,
     If,
+    In,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
 
@@ -54,19 +87,29 @@ pub enum TokenType {
 pub fn string_to_keyword(string: &ByteSlice) -> Option<TokenType> {
     match string {
         b"and" => Some(TokenType::And),
+        b"break" => Some(TokenType::Break),
+        b"case" => Some(TokenType::Case),
+        b"catch" => Some(TokenType::Catch),
         b"class" => Some(TokenType::Class),
+        b"continue" => Some(TokenType::Continue),
+        b"default" => Some(TokenType::Default),
         b"else" => Some(TokenType::Else),
         b"false" => Some(TokenType::False),
+        b"finally" => Some(TokenType::Finally),
         b"for" => Some(TokenType::For),
         b"fun" => Some(TokenType::Fun),
         b"if" => Some(TokenType::If),
+        b"in" => Some(TokenType::In),
+        b"match" => Some(TokenType::Match),
         b"nil" => Some(TokenType::Nil),
         b"or" => Some(TokenType::Or),
         b"print" => Some(TokenType::Print),
         b"return" => Some(TokenType::Return),
         b"super" => Some(TokenType::Super),
         b"this" => Some(TokenType::This),
+        b"throw" => Some(TokenType::Throw),
         b"true" => Some(TokenType::True),
+        b"try" => Some(TokenType::Try),
         b"var" => Some(TokenType::Var),
         b"while" => Some(TokenType::While),
         _ => None,
@@ -0,0 +1,106 @@
+#[cfg(feature = "nan_boxing")]
+mod nan_boxing;
+#[cfg(not(feature = "nan_boxing"))]
+mod tagged_union;
+mod test;
+
+use std::fmt::Display;
+
+use crate::error::RuntimeError;
+use crate::object::Obj;
+use crate::opcode::OpCode;
+
+#[cfg(feature = "nan_boxing")]
+pub use self::nan_boxing::Value;
+#[cfg(not(feature = "nan_boxing"))]
+pub use self::tagged_union::Value;
+
+impl Value {
+    // contents of a string, for host code looking at the values it gets
+    pub fn as_str(&self) -> Option<&str> {
+        match self.as_obj_ptr()?.as_obj() {
+            Obj::String(string) => std::str::from_utf8(string).ok(),
+            _ => None,
+        }
+    }
+}
+
+// `n` as an operand of a bitwise operator, which has to be a whole number
+// that fits in 64 bits
+fn to_integer(n: f64) -> Result<i64, RuntimeError> {
+    // `i64::MAX` rounds up to 2^63 as a float, which is already too large
+    if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 {
+        Ok(n as i64)
+    } else {
+        Err(RuntimeError::OperandsMustBeIntegers)
+    }
+}
+
+// Applies the bitwise operator `instruction` to two numbers, shared by the
+// VM and constant folding.
+pub(crate) fn bitwise(instruction: OpCode, a: f64, b: f64) -> Result<f64, RuntimeError> {
+    let (a, b) = (to_integer(a)?, to_integer(b)?);
+    let shift = || match u32::try_from(b) {
+        Ok(shift) if shift < 64 => Ok(shift),
+        _ => Err(RuntimeError::ShiftOutOfRange),
+    };
+    let result = match instruction {
+        OpCode::BitAnd => a & b,
+        OpCode::BitOr => a | b,
+        OpCode::BitXor => a ^ b,
+        OpCode::ShiftLeft => a << shift()?,
+        OpCode::ShiftRight => a >> shift()?,
+        _ => unreachable!("not a bitwise operator"),
+    };
+    Ok(result as f64)
+}
+
+pub(crate) fn bit_not(n: f64) -> Result<f64, RuntimeError> {
+    match to_integer(n) {
+        Ok(n) => Ok(!n as f64),
+        Err(_) => Err(RuntimeError::OperandMustBeInteger),
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl From<Value> for bool {
+    fn from(value: Value) -> Self {
+        !(value.is_nil() || value.as_boolean() == Some(false))
+    }
+}
+
+impl Value {
+    // `==` in Lox: strings are equal when their contents are, other objects
+    // only when they are the same object
+    pub fn equals(self, other: Value) -> bool {
+        match (self.as_obj_ptr(), other.as_obj_ptr()) {
+            (Some(a), Some(b)) if a.is_string() && b.is_string() => a.as_string() == b.as_string(),
+            _ => self == other,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(b) = self.as_boolean() {
+            write!(f, "{}", b)
+        } else if let Some(n) = self.as_number() {
+            write!(f, "{}", n)
+        } else if let Some(o) = self.as_obj_ptr() {
+            write!(f, "{}", o)
+        } else {
+            write!(f, "nil")
+        }
+    }
+}
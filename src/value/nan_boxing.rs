@@ -0,0 +1,95 @@
+use std::fmt::Debug;
+
+use crate::object::ObjPtr;
+
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+const QNAN: u64 = 0x7ffc_0000_0000_0000;
+
+const TAG_NIL: u64 = 1;
+const TAG_FALSE: u64 = 2;
+const TAG_TRUE: u64 = 3;
+
+// Values packed into the bits of an `f64`. Numbers are stored as they are while
+// everything else lives in the unused payload of a quiet NaN: `nil` and the
+// booleans are tagged in the lowest bits and object pointers set the sign bit.
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct Value(u64);
+
+// named like the variants of the tagged union so that values are built the same
+// way with either representation
+#[allow(non_upper_case_globals, non_snake_case)]
+impl Value {
+    pub const Nil: Value = Value(QNAN | TAG_NIL);
+
+    pub fn Boolean(b: bool) -> Self {
+        Value(QNAN | if b { TAG_TRUE } else { TAG_FALSE })
+    }
+
+    pub fn Number(n: f64) -> Self {
+        // any NaN coming out of arithmetic could collide with the tagged values
+        if n.is_nan() {
+            Value(f64::NAN.to_bits())
+        } else {
+            Value(n.to_bits())
+        }
+    }
+
+    pub fn ObjPtr(ptr: ObjPtr) -> Self {
+        Value(SIGN_BIT | QNAN | ptr.to_bits())
+    }
+}
+
+impl Value {
+    pub fn is_nil(self) -> bool {
+        self.0 == Self::Nil.0
+    }
+
+    pub fn as_boolean(self) -> Option<bool> {
+        match self.0 {
+            bits if bits == QNAN | TAG_TRUE => Some(true),
+            bits if bits == QNAN | TAG_FALSE => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(self) -> Option<f64> {
+        if self.0 & QNAN != QNAN {
+            Some(f64::from_bits(self.0))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_obj_ptr(self) -> Option<ObjPtr> {
+        if self.0 & (SIGN_BIT | QNAN) == SIGN_BIT | QNAN {
+            // only `Value::ObjPtr` sets both the sign bit and the quiet NaN bits
+            Some(unsafe { ObjPtr::from_bits(self.0 & !(SIGN_BIT | QNAN)) })
+        } else {
+            None
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.as_number(), other.as_number()) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.0 == other.0,
+        }
+    }
+}
+
+impl Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(b) = self.as_boolean() {
+            f.debug_tuple("Boolean").field(&b).finish()
+        } else if let Some(n) = self.as_number() {
+            f.debug_tuple("Number").field(&n).finish()
+        } else if let Some(ptr) = self.as_obj_ptr() {
+            f.debug_tuple("ObjPtr").field(&ptr).finish()
+        } else {
+            write!(f, "Nil")
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::object::ObjPtr;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    ObjPtr(ObjPtr), // heap allocated object
+}
+
+impl Value {
+    pub fn is_nil(self) -> bool {
+        matches!(self, Value::Nil)
+    }
+
+    pub fn as_boolean(self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_obj_ptr(self) -> Option<ObjPtr> {
+        match self {
+            Value::ObjPtr(ptr) => Some(ptr),
+            _ => None,
+        }
+    }
+}
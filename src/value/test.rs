@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::RuntimeError,
+        object::ObjPtr,
+        opcode::OpCode,
+        value::{bit_not, bitwise, Value},
+    };
+
+    #[test]
+    fn round_trip() {
+        assert!(Value::Nil.is_nil());
+        assert_eq!(Value::Boolean(true).as_boolean(), Some(true));
+        assert_eq!(Value::Boolean(false).as_boolean(), Some(false));
+        assert_eq!(Value::Number(-1.5).as_number(), Some(-1.5));
+        assert_eq!(
+            Value::Number(f64::INFINITY).as_number(),
+            Some(f64::INFINITY)
+        );
+        let ptr: ObjPtr = b"lox"[..].into();
+        assert_eq!(Value::ObjPtr(ptr).as_obj_ptr(), Some(ptr));
+    }
+
+    #[test]
+    fn kinds_are_distinct() {
+        assert_eq!(Value::Nil.as_boolean(), None);
+        assert_eq!(Value::Nil.as_number(), None);
+        assert_eq!(Value::Boolean(false).as_number(), None);
+        assert!(!Value::Boolean(false).is_nil());
+        assert!(!Value::Number(0f64).is_nil());
+        assert_eq!(Value::Number(0f64).as_obj_ptr(), None);
+        assert_eq!(Value::ObjPtr(b""[..].into()).as_number(), None);
+    }
+
+    #[test]
+    fn nan_is_a_number() {
+        for nan in [Value::Number(f64::NAN), Value::Number(-f64::NAN)] {
+            assert!(nan.as_number().unwrap().is_nan());
+            assert_eq!(nan.as_obj_ptr(), None);
+            assert!(!nan.is_nil());
+            assert_ne!(nan, nan);
+        }
+    }
+
+    #[cfg(feature = "nan_boxing")]
+    #[test]
+    fn fits_in_a_word() {
+        assert_eq!(std::mem::size_of::<Value>(), 8);
+    }
+
+    #[test]
+    fn truthiness() {
+        assert!(!bool::from(Value::Nil));
+        assert!(!bool::from(Value::Boolean(false)));
+        assert!(bool::from(Value::Boolean(true)));
+        assert!(bool::from(Value::Number(0f64)));
+        assert!(bool::from(Value::ObjPtr(b""[..].into())));
+    }
+
+    #[test]
+    fn strings_equal_by_contents() {
+        let a = Value::ObjPtr(b"lox"[..].into());
+        let b = Value::ObjPtr(b"lox"[..].into());
+        assert!(a.equals(b));
+        assert!(!a.equals(Value::ObjPtr(b"clox"[..].into())));
+        assert!(!a.equals(Value::Nil));
+        assert!(Value::Number(1f64).equals(Value::Number(1f64)));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Value::Nil.to_string(), "nil");
+        assert_eq!(Value::from(true).to_string(), "true");
+        assert_eq!(Value::from(2.5).to_string(), "2.5");
+        assert_eq!(Value::ObjPtr(b"lox"[..].into()).to_string(), "lox");
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        assert_eq!(bitwise(OpCode::BitAnd, 6.0, 3.0).unwrap(), 2.0);
+        assert_eq!(bitwise(OpCode::BitOr, 6.0, 3.0).unwrap(), 7.0);
+        assert_eq!(bitwise(OpCode::BitXor, -1.0, 5.0).unwrap(), -6.0);
+        assert_eq!(
+            bitwise(OpCode::ShiftLeft, 1.0, 62.0).unwrap(),
+            2f64.powi(62)
+        );
+        assert_eq!(bitwise(OpCode::ShiftRight, -8.0, 1.0).unwrap(), -4.0);
+        assert_eq!(bit_not(0.0).unwrap(), -1.0);
+        assert!(matches!(
+            bitwise(OpCode::BitAnd, 1.5, 1.0),
+            Err(RuntimeError::OperandsMustBeIntegers)
+        ));
+        assert!(matches!(
+            bitwise(OpCode::BitOr, 2f64.powi(63), 1.0),
+            Err(RuntimeError::OperandsMustBeIntegers)
+        ));
+        assert!(matches!(
+            bitwise(OpCode::ShiftLeft, 1.0, 64.0),
+            Err(RuntimeError::ShiftOutOfRange)
+        ));
+        assert!(matches!(
+            bitwise(OpCode::ShiftRight, 1.0, -1.0),
+            Err(RuntimeError::ShiftOutOfRange)
+        ));
+        assert!(matches!(
+            bit_not(f64::NAN),
+            Err(RuntimeError::OperandMustBeInteger)
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+// Methods of built-in types that take a function to call, like `map` on
+// lists. Whatever they build has to stay reachable from the stack, since the
+// calls can trigger collections.
+use crate::builtins::list;
+use crate::error::RuntimeError;
+use crate::object::{Obj, ObjPtr};
+use crate::value::Value;
+
+use super::{STACK_MAX, VM};
+
+impl VM {
+    // a new list on top of the stack, which keeps it alive until it's popped
+    fn push_list(&mut self, elements: Vec<Value>) -> Result<ObjPtr, RuntimeError> {
+        if self.stack.len() == STACK_MAX {
+            return Err(RuntimeError::StackOverflow);
+        }
+        self.maybe_collect();
+        let ptr = self.allocate(Obj::List(elements));
+        self.push(Value::ObjPtr(ptr));
+        Ok(ptr)
+    }
+
+    // `function` is called with every element in turn and the results end up
+    // in a new list
+    pub(crate) fn list_map(
+        &mut self,
+        receiver: ObjPtr,
+        function: Value,
+    ) -> Result<Value, RuntimeError> {
+        let mapped = self.push_list(Vec::new())?;
+        // the function is free to change the list while it runs
+        let mut idx = 0;
+        while let Some(element) = list(receiver).get(idx).copied() {
+            let result = self.call_back(function, &[element])?;
+            list(mapped).push(result);
+            idx += 1;
+        }
+        self.pop();
+        Ok(Value::ObjPtr(mapped))
+    }
+
+    // a new list with the elements `function` returns something truthy for
+    pub(crate) fn list_filter(
+        &mut self,
+        receiver: ObjPtr,
+        function: Value,
+    ) -> Result<Value, RuntimeError> {
+        let filtered = self.push_list(Vec::new())?;
+        let mut idx = 0;
+        while let Some(element) = list(receiver).get(idx).copied() {
+            if bool::from(self.call_back(function, &[element])?) {
+                list(filtered).push(element);
+            }
+            idx += 1;
+        }
+        self.pop();
+        Ok(Value::ObjPtr(filtered))
+    }
+
+    // A new list with the elements in the order given by `compare`, which
+    // returns a negative number when its first argument goes before the
+    // second, a positive one when it goes after and zero when either is fine.
+    // Elements that compare equal keep their order.
+    pub(crate) fn list_sort(
+        &mut self,
+        receiver: ObjPtr,
+        compare: Value,
+    ) -> Result<Value, RuntimeError> {
+        let mut elements = list(receiver).clone();
+        // the copy on the stack keeps the elements alive while they're sorted
+        let sorted = self.push_list(elements.clone())?;
+        self.merge_sort(&mut elements, compare)?;
+        *list(sorted) = elements;
+        self.pop();
+        Ok(Value::ObjPtr(sorted))
+    }
+
+    // unlike the sorts of the standard library this is fine with comparisons
+    // that contradict each other, which functions from Lox can't rule out
+    fn merge_sort(&mut self, elements: &mut [Value], compare: Value) -> Result<(), RuntimeError> {
+        if elements.len() < 2 {
+            return Ok(());
+        }
+        let mid = elements.len() / 2;
+        self.merge_sort(&mut elements[..mid], compare)?;
+        self.merge_sort(&mut elements[mid..], compare)?;
+        let mut merged = Vec::with_capacity(elements.len());
+        let (mut left, mut right) = (0, mid);
+        while left < mid && right < elements.len() {
+            let order = self.call_back(compare, &[elements[left], elements[right]])?;
+            let order = order
+                .as_number()
+                .ok_or(RuntimeError::ComparisonMustBeNumber)?;
+            if order > 0f64 {
+                merged.push(elements[right]);
+                right += 1;
+            } else {
+                merged.push(elements[left]);
+                left += 1;
+            }
+        }
+        merged.extend_from_slice(&elements[left..mid]);
+        merged.extend_from_slice(&elements[right..]);
+        elements.copy_from_slice(&merged);
+        Ok(())
+    }
+}
@@ -1,199 +1,1398 @@
+mod callbacks;
 mod test;
 
-use std::cell::Cell;
-use std::collections::LinkedList;
-use std::str;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet, LinkedList};
+use std::fmt::Debug;
+use std::io::{self, Write};
 
-use crate::object::ObjPtr;
+use crate::builtins::{self, list_index, map_key, undefined_key, Builtin, Method};
+use crate::byte_string::{ByteSlice, ByteVector};
+use crate::compiler::{CompileMode, OptLevel};
+use crate::host::{HostClass, NativeContext, NativeFunction, NativeMethod};
+use crate::object::{
+    BoundBuiltin, BoundNative, Capture, Closure, Exception, Foreign, Function, Map, Native, Obj,
+    ObjPtr, Range, Upvalue,
+};
 use crate::{
-    chunk::Chunk, compiler::compile, error::RuntimeError, opcode::OpCode, parser::parse_expression,
-    scanner::scan, value::Value,
+    chunk::Chunk,
+    compiler::compile_script,
+    error::{InterpretError, RuntimeError},
+    opcode::OpCode,
+    parser::parse,
+    scanner::scan,
+    value::{self, Value},
 };
 
 type Stack = Vec<Value>;
-type IP = usize;
 
-static STACK_UNDERFLOW: &'_ str = "Tried poping from empty stack";
+const FRAMES_MAX: usize = 64;
+const STACK_MAX: usize = FRAMES_MAX * 256;
+// number of live objects after which the first collection happens
+const GC_INITIAL_THRESHOLD: usize = 1024;
+
+// What the objects allocated while running add up to, constants aren't
+// counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeapStats {
+    pub objects: usize,
+    // approximate, see `Obj::size`
+    pub bytes: usize,
+    // number of objects of every kind
+    pub kinds: BTreeMap<&'static str, usize>,
+    // number of objects at which the next collection happens
+    pub next_gc: usize,
+}
+
+// Counts of what the VM did since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunStats {
+    // only counted while counting is turned on or there is a step limit
+    pub instructions: u64,
+    pub allocations: u64,
+    pub collections: u64,
+}
 
+// Everything needed to resume a caller once the function it called returns.
+// The pointers stay valid because chunks of functions are never freed and the
+// top level chunk outlives the call to `execute`.
 #[derive(Debug)]
+struct CallFrame {
+    chunk: *const Chunk,
+    ip: *const u8,
+    // index of the stack slot holding the called function
+    slots: usize,
+}
+
+// The frame that is running, which `dispatch` keeps apart from its callers in
+// `frames`. It belongs to `execute`, which needs to know where an error
+// happened to handle it.
+struct Registers<'c> {
+    chunk: &'c Chunk,
+    ip: *const u8,
+    slots: usize,
+}
+
+// Where a `try` statement that is running continues once it catches an
+// exception.
+#[derive(Debug)]
+struct Handler {
+    // number of frames below the frame that installed the handler, the ones
+    // above it are unwound
+    depth: usize,
+    frame: CallFrame,
+    // the stack is cut back to this height before the exception is pushed
+    height: usize,
+}
+
 pub struct VM {
-    // pub chunk: RefCell<Chunk>,
-    ip: Cell<IP>,
     stack: Stack,
+    frames: Vec<CallFrame>,
+    // handlers of the `try` statements that are running, innermost last
+    handlers: Vec<Handler>,
+    // upvalues of locals that are still on the stack, ordered by their slots
+    open_upvalues: Vec<ObjPtr>,
+    // objects allocated while running, constants aren't in here
     objects: LinkedList<ObjPtr>,
+    // number of objects at which the next collection happens
+    next_gc: usize,
+    // collect before every allocation, to flush out objects that aren't rooted
+    gc_stress: bool,
+    // runtime error once this many instructions have run in total
+    max_steps: u64,
+    // whether `stats.instructions` is kept up to date
+    count_instructions: bool,
+    stats: RunStats,
+    globals: HashMap<ByteVector, Value>,
+    // where `print` writes to
+    out: Box<dyn Write>,
+    // where traces and disassembly are written to
+    err: Box<dyn Write>,
+}
+
+impl Debug for VM {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VM")
+            .field("stack", &self.stack)
+            .field("frames", &self.frames)
+            .field("objects", &self.objects)
+            .field("globals", &self.globals)
+            .finish_non_exhaustive()
+    }
+}
+
+// SAFETY: `ip` has to point into verified code, which guarantees that operands
+// are never read past the end of the chunk
+unsafe fn read_byte(ip: &mut *const u8) -> u8 {
+    let byte = **ip;
+    *ip = ip.add(1);
+    byte
+}
+
+unsafe fn read_short(ip: &mut *const u8) -> u16 {
+    let h = read_byte(ip);
+    let l = read_byte(ip);
+    u16::from_be_bytes([h, l])
+}
+
+unsafe fn read_constant(ip: &mut *const u8, chunk: &Chunk) -> Value {
+    *chunk.constants.get_unchecked(read_byte(ip) as usize)
+}
+
+unsafe fn read_constant_long(ip: &mut *const u8, chunk: &Chunk) -> Value {
+    let h = read_byte(ip);
+    let m = read_byte(ip);
+    let l = read_byte(ip);
+    let idx = u32::from_be_bytes([0, h, m, l]);
+    *chunk.constants.get_unchecked(idx as usize)
+}
+
+unsafe fn read_name(ip: &mut *const u8, chunk: &Chunk) -> ObjPtr {
+    // the verifier checked that names of globals and properties are string constants
+    read_constant(ip, chunk).as_obj_ptr().unwrap_unchecked()
+}
+
+// index of the stack slot holding the local an open upvalue refers to
+fn open_slot(upvalue: ObjPtr) -> usize {
+    match upvalue.as_obj() {
+        Obj::Upvalue(Upvalue::Open(slot)) => *slot,
+        _ => unreachable!("closed upvalues aren't kept track of"),
+    }
 }
 
 impl VM {
     pub fn new() -> Self {
         Self {
-            // chunk: RefCell::new(Chunk::default()),
-            ip: Cell::new(0),
-            stack: Vec::with_capacity(256),
+            stack: Vec::with_capacity(STACK_MAX),
+            frames: Vec::with_capacity(FRAMES_MAX),
+            handlers: Vec::new(),
+            open_upvalues: Vec::new(),
             objects: LinkedList::new(),
+            next_gc: GC_INITIAL_THRESHOLD,
+            gc_stress: false,
+            max_steps: u64::MAX,
+            count_instructions: false,
+            stats: RunStats::default(),
+            globals: HashMap::new(),
+            out: Box::new(io::stdout()),
+            err: Box::new(io::stderr()),
         }
     }
 
+    pub fn set_output(&mut self, out: Box<dyn Write>) {
+        self.out = out;
+    }
+
+    pub fn set_error_output(&mut self, err: Box<dyn Write>) {
+        self.err = err;
+    }
+
+    pub fn set_gc_stress(&mut self, gc_stress: bool) {
+        self.gc_stress = gc_stress;
+    }
+
+    pub fn set_max_steps(&mut self, max_steps: Option<u64>) {
+        self.max_steps = max_steps.unwrap_or(u64::MAX);
+    }
+
+    pub fn set_count_instructions(&mut self, count_instructions: bool) {
+        self.count_instructions = count_instructions;
+    }
+
+    pub fn stats(&self) -> RunStats {
+        self.stats
+    }
+
     pub fn reset_stack(&mut self) {
+        // closures outliving the stack keep the values their variables had
+        self.close_upvalues(0);
         self.stack.clear();
+        self.frames.clear();
+        self.handlers.clear();
     }
 
-    fn read_byte(&self, chunk: &Chunk) -> u8 {
-        let ip = self.ip.get();
-        let instruction = chunk.code[ip];
-        self.ip.set(ip + 1);
-        instruction
+    // Runs `chunk` as top level code. The code is verified up front, so the dispatch loop can go without
+    // bounds checks on the code, the constants and the stack.
+    pub fn run_bytecode(&mut self, chunk: &Chunk, debug: bool) -> Result<Value, RuntimeError> {
+        let verified = chunk
+            .verify(0, self.stack.len(), 0)
+            .map_err(RuntimeError::InvalidBytecode)?;
+        if verified.max_stack > STACK_MAX {
+            return Err(RuntimeError::StackOverflow);
+        }
+        let result = if verified.falls_through {
+            // make sure the loop stops at the end of the chunk
+            let mut halting = chunk.clone();
+            halting.write(OpCode::Halt as u8, 0);
+            self.execute(&halting, 0, 0, debug)
+        } else {
+            self.execute(chunk, 0, 0, debug)
+        };
+        if result.is_err() {
+            self.close_upvalues(0);
+            self.frames.clear();
+            self.handlers.clear();
+        }
+        result.map_err(Self::uncaught)
     }
 
-    fn read_constant(&self, chunk: &Chunk) -> Value {
-        chunk.constants[self.read_byte(chunk) as usize]
+    // Calls `callee` with `args` from the host, outside of any running code.
+    pub fn call(
+        &mut self,
+        callee: Value,
+        args: &[Value],
+        debug: bool,
+    ) -> Result<Value, RuntimeError> {
+        self.reset_stack();
+        let function = self.check_call(callee, args.len(), 0)?;
+        self.push(callee);
+        for arg in args {
+            self.push(*arg);
+        }
+        // SAFETY: functions are never freed
+        let chunk = unsafe { &(*function).chunk };
+        let result = self.execute(chunk, 0, 0, debug);
+        if result.is_err() {
+            self.reset_stack();
+        }
+        result.map_err(Self::uncaught)
     }
 
-    fn read_constant_long(&self, chunk: &Chunk) -> Value {
-        let h = self.read_byte(chunk);
-        let m = self.read_byte(chunk);
-        let l = self.read_byte(chunk);
-        let idx = u32::from_be_bytes([0, h, m, l]);
-        chunk.constants[idx as usize]
+    // what the host gets for an error that made it out of all the running code
+    fn uncaught(error: RuntimeError) -> RuntimeError {
+        match error {
+            RuntimeError::Thrown(value) => RuntimeError::Uncaught(value.to_string()),
+            error => error,
+        }
     }
 
-    pub fn run_bytecode(&mut self, chunk: &Chunk, debug: bool) -> Result<Value, RuntimeError> {
-        while self.ip.get() < chunk.code.len() {
+    // Calls `callee` with `args` while a method of a built-in type is
+    // running, returning once the call is done. Lox functions run in a
+    // dispatch loop of their own on top of the stack.
+    fn call_back(&mut self, callee: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+        if self.stack.len() + args.len() + 1 > STACK_MAX {
+            return Err(RuntimeError::StackOverflow);
+        }
+        let callee_slot = self.stack.len();
+        self.push(callee);
+        for arg in args {
+            self.push(*arg);
+        }
+        match self.call_value(callee, args.len(), callee_slot)? {
+            Some(function) => {
+                // stands in for the frame of the method, so that callbacks
+                // calling methods with callbacks count towards `FRAMES_MAX`
+                self.frames.push(CallFrame {
+                    chunk: std::ptr::null(),
+                    ip: std::ptr::null(),
+                    slots: callee_slot,
+                });
+                // SAFETY: functions are never freed
+                let chunk = unsafe { &(*function).chunk };
+                let result = self.execute(chunk, 0, callee_slot, false);
+                self.frames.pop();
+                result
+            }
+            None => Ok(self.pop()),
+        }
+    }
+
+    // Calls `callee`, which sits in `callee_slot` right below its arguments.
+    // Lox functions return the function for the caller to push a frame for,
+    // classes and methods implemented by the host run right away and leave
+    // their result in place of the callee.
+    fn call_value(
+        &mut self,
+        callee: Value,
+        arg_count: usize,
+        callee_slot: usize,
+    ) -> Result<Option<*const Function>, RuntimeError> {
+        let ptr = callee.as_obj_ptr().ok_or(RuntimeError::NotCallable)?;
+        match ptr.as_obj() {
+            Obj::Function(_) | Obj::Closure(_) => {
+                self.check_call(callee, arg_count, callee_slot).map(Some)
+            }
+            Obj::HostClass(class) => {
+                if arg_count != class.arity {
+                    return Err(RuntimeError::WrongArity {
+                        expected: class.arity,
+                        got: arg_count,
+                    });
+                }
+                // the arguments are still on the stack, and nothing the
+                // constructor allocates can be collected before the instance
+                // holding on to it exists
+                self.maybe_collect();
+                let args = &self.stack[callee_slot + 1..];
+                let mut context = NativeContext::new(&mut self.objects);
+                let payload =
+                    (class.constructor)(&mut context, args).map_err(RuntimeError::Native)?;
+                let instance = self.allocate(Obj::Foreign(Foreign {
+                    class: ptr,
+                    fields: HashMap::new(),
+                    payload,
+                }));
+                self.stack.truncate(callee_slot);
+                self.push(Value::ObjPtr(instance));
+                Ok(None)
+            }
+            Obj::BoundNative(bound) => {
+                self.call_native(bound.receiver, bound.method, callee_slot)?;
+                Ok(None)
+            }
+            Obj::Native(native) => {
+                if arg_count != native.arity {
+                    return Err(RuntimeError::WrongArity {
+                        expected: native.arity,
+                        got: arg_count,
+                    });
+                }
+                let args = &self.stack[callee_slot + 1..];
+                let mut context = NativeContext::new(&mut self.objects);
+                let result = (native.function)(&mut context, args).map_err(RuntimeError::Native)?;
+                self.stack.truncate(callee_slot);
+                self.push(result);
+                Ok(None)
+            }
+            Obj::BoundBuiltin(bound) => {
+                self.call_builtin(bound.receiver, bound.builtin, arg_count, callee_slot)?;
+                Ok(None)
+            }
+            Obj::String(_)
+            | Obj::Upvalue(_)
+            | Obj::Foreign(_)
+            | Obj::List(_)
+            | Obj::Map(_)
+            | Obj::Range(_)
+            | Obj::Exception(_) => Err(RuntimeError::NotCallable),
+        }
+    }
+
+    // Runs a method of a built-in type on `receiver` with the arguments above
+    // `callee_slot`, replacing them with the result.
+    fn call_builtin(
+        &mut self,
+        receiver: ObjPtr,
+        builtin: Builtin,
+        arg_count: usize,
+        callee_slot: usize,
+    ) -> Result<(), RuntimeError> {
+        if arg_count != builtin.arity {
+            return Err(RuntimeError::WrongArity {
+                expected: builtin.arity,
+                got: arg_count,
+            });
+        }
+        let result = match builtin.method {
+            Method::Native(method) => {
+                // the receiver and the arguments are still reachable from the stack
+                self.maybe_collect();
+                let args = &self.stack[callee_slot + 1..];
+                let mut context = NativeContext::new(&mut self.objects);
+                method(&mut context, receiver, args)?
+            }
+            // the function stays on the stack while it gets called
+            Method::Callback(method) => method(self, receiver, self.stack[callee_slot + 1])?,
+        };
+        self.stack.truncate(callee_slot);
+        self.push(result);
+        Ok(())
+    }
+
+    // Runs a method of a host class on `receiver` with the arguments above
+    // `callee_slot`, replacing them with the result.
+    fn call_native(
+        &mut self,
+        receiver: ObjPtr,
+        method: NativeMethod,
+        callee_slot: usize,
+    ) -> Result<(), RuntimeError> {
+        let payload = match receiver.as_obj_mut() {
+            Obj::Foreign(instance) => &mut *instance.payload,
+            _ => unreachable!("methods are only bound to instances"),
+        };
+        let args = &self.stack[callee_slot + 1..];
+        let mut context = NativeContext::new(&mut self.objects);
+        let result = method(&mut context, payload, args).map_err(RuntimeError::Native)?;
+        self.stack.truncate(callee_slot);
+        self.push(result);
+        Ok(())
+    }
+
+    fn find_method(instance: &Foreign, name: &ByteSlice) -> Result<NativeMethod, RuntimeError> {
+        let class = match instance.class.as_obj() {
+            Obj::HostClass(class) => class,
+            _ => unreachable!("instances always belong to a host class"),
+        };
+        match class.methods.get(name) {
+            Some(method) => Ok(*method),
+            None => Err(RuntimeError::UndefinedProperty(
+                String::from_utf8_lossy(name).into(),
+            )),
+        }
+    }
+
+    // Calls the method `name` of a host instance with a single argument, for
+    // the VM's own use.
+    fn call_host_method(
+        &mut self,
+        receiver: ObjPtr,
+        name: &ByteSlice,
+        arg: Value,
+    ) -> Result<Value, RuntimeError> {
+        let instance = match receiver.as_obj_mut() {
+            Obj::Foreign(instance) => instance,
+            _ => unreachable!("only instances have host methods"),
+        };
+        let method = Self::find_method(instance, name)?;
+        // the receiver and the argument have to be reachable from the stack
+        self.maybe_collect();
+        let mut context = NativeContext::new(&mut self.objects);
+        method(&mut context, &mut *instance.payload, &[arg]).map_err(RuntimeError::Native)
+    }
+
+    // Advances the iterator of a `for-in` loop in `iterator_slot`, returning
+    // the next element of `sequence` if there is one. Host instances
+    // implement this with an `iterate` method, which returns the next
+    // iterator or false when done, and an `iteratorValue` method returning the
+    // element the iterator points to.
+    fn iterate(
+        &mut self,
+        sequence: Value,
+        iterator_slot: usize,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let sequence = sequence.as_obj_ptr().ok_or(RuntimeError::NotIterable)?;
+        let iterator = self.stack[iterator_slot];
+        if let Obj::Foreign(_) = sequence.as_obj() {
+            let iterator = self.call_host_method(sequence, b"iterate", iterator)?;
+            if !bool::from(iterator) {
+                return Ok(None);
+            }
+            self.stack[iterator_slot] = iterator;
+            return (self.call_host_method(sequence, b"iteratorValue", iterator)).map(Some);
+        }
+        // iterating over strings allocates the characters
+        self.maybe_collect();
+        let mut context = NativeContext::new(&mut self.objects);
+        match builtins::iterate(&mut context, sequence, iterator)? {
+            Some((iterator, element)) => {
+                self.stack[iterator_slot] = iterator;
+                Ok(Some(element))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Checks that `callee` can be called with `arg_count` arguments by a new
+    // frame starting at `callee_slot`.
+    fn check_call(
+        &self,
+        callee: Value,
+        arg_count: usize,
+        callee_slot: usize,
+    ) -> Result<*const Function, RuntimeError> {
+        let function = match callee.as_obj_ptr().map(ObjPtr::as_obj) {
+            Some(Obj::Function(function)) => function as *const Function,
+            Some(Obj::Closure(closure)) => match closure.function.as_obj() {
+                Obj::Function(function) => function as *const Function,
+                _ => unreachable!("closures are always made from functions"),
+            },
+            _ => return Err(RuntimeError::NotCallable),
+        };
+        // SAFETY: functions are never freed
+        let function = unsafe { &*function };
+        if arg_count != function.arity {
+            return Err(RuntimeError::WrongArity {
+                expected: function.arity,
+                got: arg_count,
+            });
+        }
+        // the body was verified when it was compiled, so it stays within
+        // `max_stack` and this is the only check it needs
+        if self.frames.len() == FRAMES_MAX || callee_slot + function.max_stack > STACK_MAX {
+            return Err(RuntimeError::StackOverflow);
+        }
+        Ok(function)
+    }
+
+    // Runs `chunk` from `start` in a frame starting at `slots` until it halts
+    // or that frame returns, handing back what it returns.
+    fn execute(
+        &mut self,
+        chunk: &Chunk,
+        start: usize,
+        slots: usize,
+        debug: bool,
+    ) -> Result<Value, RuntimeError> {
+        // `frames` only has the callers of the running frame, starting with
+        // those of the frame this was called with
+        let base = self.frames.len();
+        let mut registers = Registers {
+            chunk,
+            ip: unsafe { chunk.code.as_ptr().add(start) },
+            slots,
+        };
+        loop {
+            let result = if !self.count_instructions && self.max_steps == u64::MAX {
+                self.dispatch::<false>(&mut registers, base, debug, u64::MAX, &mut 0)
+            } else {
+                let mut steps = 0;
+                let budget = self.max_steps.saturating_sub(self.stats.instructions);
+                let result = self.dispatch::<true>(&mut registers, base, debug, budget, &mut steps);
+                self.stats.instructions += steps;
+                result
+            };
+            match result {
+                Err(error) => self.catch(error, &mut registers, base)?,
+                result => return result,
+            }
+        }
+    }
+
+    // Resumes `registers` at the innermost handler installed by the frames
+    // from `base` upwards, with `error` on top of the stack. Without one the
+    // frames are unwound and the error is returned, for a dispatch loop
+    // further down to handle. Runtime errors that any handler could catch
+    // become exceptions right away, while the line they happened on is known.
+    fn catch(
+        &mut self,
+        error: RuntimeError,
+        registers: &mut Registers,
+        base: usize,
+    ) -> Result<(), RuntimeError> {
+        let exception = match error {
+            RuntimeError::Thrown(value) => value,
+            error if error.is_catchable() && !self.handlers.is_empty() => {
+                // `ip` is past the instruction that failed
+                let offset = registers.ip as usize - registers.chunk.code.as_ptr() as usize;
+                let line = registers.chunk.get_line(offset - 1);
+                self.maybe_collect();
+                let exception = self.allocate(Obj::Exception(Exception {
+                    message: error.to_string(),
+                    line,
+                }));
+                Value::ObjPtr(exception)
+            }
+            error => {
+                self.frames.truncate(base);
+                return Err(error);
+            }
+        };
+        match self.handlers.last() {
+            Some(handler) if handler.depth >= base => {
+                let Handler {
+                    depth,
+                    frame,
+                    height,
+                } = self.handlers.pop().unwrap();
+                self.frames.truncate(depth);
+                self.close_upvalues(height);
+                self.stack.truncate(height);
+                self.push(exception);
+                *registers = Registers {
+                    chunk: unsafe { &*frame.chunk },
+                    ip: frame.ip,
+                    slots: frame.slots,
+                };
+                Ok(())
+            }
+            _ => {
+                self.frames.truncate(base);
+                Err(RuntimeError::Thrown(exception))
+            }
+        }
+    }
+
+    // The loop behind `execute`. With `COUNT` it counts the instructions it
+    // runs in `steps` and fails once there were more than `budget`, which
+    // slows down dispatch enough to only do it when asked to.
+    fn dispatch<const COUNT: bool>(
+        &mut self,
+        registers: &mut Registers,
+        base: usize,
+        debug: bool,
+        budget: u64,
+        steps: &mut u64,
+    ) -> Result<Value, RuntimeError> {
+        let Registers { chunk, ip, slots } = registers;
+        loop {
+            if COUNT {
+                *steps += 1;
+                if *steps > budget {
+                    return Err(RuntimeError::StepLimit(self.max_steps));
+                }
+            }
             if debug {
                 // TODO: make this compile time
-                print!("[TRACE] ");
-                print!("          ");
-                print!("stack: {:?}", self.stack);
-                print!("  ");
-                print!("objects: {:?}", self.objects);
-                println!();
-                println!();
-                print!("[TRACE] ");
-                chunk.disassemble_instruction(self.ip.get());
-            }
-            let byte = self.read_byte(chunk);
+                write!(self.err, "[TRACE] ")?;
+                write!(self.err, "          ")?;
+                write!(self.err, "stack: {:?}", self.stack)?;
+                write!(self.err, "  ")?;
+                write!(self.err, "objects: {:?}", self.objects)?;
+                writeln!(self.err)?;
+                writeln!(self.err)?;
+                write!(self.err, "[TRACE] ")?;
+                let offset = *ip as usize - chunk.code.as_ptr() as usize;
+                chunk.disassemble_instruction(offset, &mut self.err)?;
+            }
+            let byte = unsafe { read_byte(ip) };
             match byte.into() {
                 OpCode::Return => {
-                    let ret = Self::pop_unsafe(&mut self.stack);
-                    println!("{}", ret);
-                    return Ok(ret);
+                    let ret = self.pop();
+                    // the code removes its handlers before returning
+                    debug_assert!(self
+                        .handlers
+                        .last()
+                        .is_none_or(|h| h.depth < self.frames.len()));
+                    self.close_upvalues(*slots);
+                    self.stack.truncate(*slots);
+                    if self.frames.len() == base {
+                        return Ok(ret);
+                    }
+                    let frame = self.frames.pop().unwrap();
+                    self.push(ret);
+                    *chunk = unsafe { &*frame.chunk };
+                    *ip = frame.ip;
+                    *slots = frame.slots;
                 }
+                OpCode::Halt => return Ok(Value::Nil),
                 OpCode::Constant => {
-                    let constant = self.read_constant(chunk);
-                    if let Value::ObjPtr(ptr) = constant {
-                        self.objects.push_back(ptr); // TODO: abstract this
-                    }
-                    self.stack.push(constant);
+                    let constant = unsafe { read_constant(ip, chunk) };
+                    self.push(constant);
                 }
                 OpCode::ConstantLong => {
-                    let constant = self.read_constant_long(chunk);
-                    if let Value::ObjPtr(ptr) = constant {
-                        self.objects.push_back(ptr); // TODO: abstract this
-                    }
-                    self.stack.push(constant);
+                    let constant = unsafe { read_constant_long(ip, chunk) };
+                    self.push(constant);
                 }
                 OpCode::Negate => {
-                    let last_ref = self.stack.last_mut().expect(STACK_UNDERFLOW);
-                    match last_ref {
-                        Value::Number(n) => *n = -*n,
-                        _ => return Err(RuntimeError::OperandMustBeNumber),
+                    let last_ref = self.peek_mut();
+                    match last_ref.as_number() {
+                        Some(n) => *last_ref = Value::Number(-n),
+                        None => return Err(RuntimeError::OperandMustBeNumber),
                     }
                 }
                 OpCode::Add => {
-                    let (a, b) = Self::pop_twice_unsafe(&mut self.stack);
-                    match (a, b) {
-                        (Value::Number(a), Value::Number(b)) => {
-                            self.stack.push(Value::Number(a + b))
-                        }
-                        (Value::ObjPtr(a), Value::ObjPtr(b)) if a.is_string() && b.is_string() => {
-                            let concat = [a.into_string(), b.into_string()].concat();
-                            let ptr = concat.into();
-                            self.objects.push_back(ptr); // TODO: abstract this
-                            self.stack.push(Value::ObjPtr(ptr))
-                        }
-                        _ => return Err(RuntimeError::OperandsMustBeNumber),
-                    }
+                    let (a, b) = self.pop_twice();
+                    self.add(a, b)?;
                 }
                 OpCode::Subtract => {
-                    let (a, b) = Self::pop_twice_unsafe(&mut self.stack);
-                    match (a, b) {
-                        (Value::Number(a), Value::Number(b)) => {
-                            self.stack.push(Value::Number(a - b))
-                        }
+                    let (a, b) = self.pop_twice();
+                    match (a.as_number(), b.as_number()) {
+                        (Some(a), Some(b)) => self.push(Value::Number(a - b)),
                         _ => return Err(RuntimeError::OperandsMustBeNumber),
                     }
                 }
                 OpCode::Multiply => {
-                    let (a, b) = Self::pop_twice_unsafe(&mut self.stack);
-                    match (a, b) {
-                        (Value::Number(a), Value::Number(b)) => {
-                            self.stack.push(Value::Number(a * b))
-                        }
+                    let (a, b) = self.pop_twice();
+                    match (a.as_number(), b.as_number()) {
+                        (Some(a), Some(b)) => self.push(Value::Number(a * b)),
                         _ => return Err(RuntimeError::OperandsMustBeNumber),
                     }
                 }
                 OpCode::Divide => {
-                    let (a, b) = Self::pop_twice_unsafe(&mut self.stack);
-                    match (a, b) {
-                        (Value::Number(a), Value::Number(b)) => {
-                            self.stack.push(Value::Number(a / b))
-                        }
+                    let (a, b) = self.pop_twice();
+                    match (a.as_number(), b.as_number()) {
+                        (Some(a), Some(b)) => self.push(Value::Number(a / b)),
+                        _ => return Err(RuntimeError::OperandsMustBeNumber),
+                    }
+                }
+                OpCode::Modulo => {
+                    let (a, b) = self.pop_twice();
+                    match (a.as_number(), b.as_number()) {
+                        (Some(a), Some(b)) => self.push(Value::Number(a % b)),
                         _ => return Err(RuntimeError::OperandsMustBeNumber),
                     }
                 }
-                OpCode::Nil => self.stack.push(Value::Nil),
-                OpCode::True => self.stack.push(Value::Boolean(true)),
-                OpCode::False => self.stack.push(Value::Boolean(false)),
+                OpCode::Power => {
+                    let (a, b) = self.pop_twice();
+                    match (a.as_number(), b.as_number()) {
+                        (Some(a), Some(b)) => self.push(Value::Number(a.powf(b))),
+                        _ => return Err(RuntimeError::OperandsMustBeNumber),
+                    }
+                }
+                instruction @ (OpCode::BitAnd
+                | OpCode::BitOr
+                | OpCode::BitXor
+                | OpCode::ShiftLeft
+                | OpCode::ShiftRight) => {
+                    let (a, b) = self.pop_twice();
+                    match (a.as_number(), b.as_number()) {
+                        (Some(a), Some(b)) => {
+                            self.push(Value::Number(value::bitwise(instruction, a, b)?))
+                        }
+                        _ => return Err(RuntimeError::OperandsMustBeIntegers),
+                    }
+                }
+                OpCode::BitNot => {
+                    let last_ref = self.peek_mut();
+                    match last_ref.as_number() {
+                        Some(n) => *last_ref = Value::Number(value::bit_not(n)?),
+                        None => return Err(RuntimeError::OperandMustBeInteger),
+                    }
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Boolean(true)),
+                OpCode::False => self.push(Value::Boolean(false)),
                 OpCode::Not => {
-                    let last_ref = self.stack.last_mut().expect(STACK_UNDERFLOW);
+                    let last_ref = self.peek_mut();
                     let as_bool: bool = (*last_ref).into();
                     *last_ref = Value::Boolean(!as_bool);
                 }
                 OpCode::Equal => {
-                    let (a, b) = Self::pop_twice_unsafe(&mut self.stack);
-                    self.stack.push((a == b).into())
+                    let (a, b) = self.pop_twice();
+                    self.push(a.equals(b).into())
                 }
                 OpCode::Greater => {
-                    let (a, b) = Self::pop_twice_unsafe(&mut self.stack);
-                    match (a, b) {
-                        (Value::Number(n1), Value::Number(n2)) => {
-                            self.stack.push(Value::Boolean(n1 > n2))
-                        }
+                    let (a, b) = self.pop_twice();
+                    match (a.as_number(), b.as_number()) {
+                        (Some(n1), Some(n2)) => self.push(Value::Boolean(n1 > n2)),
                         _ => return Err(RuntimeError::OperandsMustBeNumber),
                     }
                 }
                 OpCode::Less => {
-                    let (a, b) = Self::pop_twice_unsafe(&mut self.stack);
-                    match (a, b) {
-                        (Value::Number(n1), Value::Number(n2)) => {
-                            self.stack.push(Value::Boolean(n1 < n2))
+                    let (a, b) = self.pop_twice();
+                    match (a.as_number(), b.as_number()) {
+                        (Some(n1), Some(n2)) => self.push(Value::Boolean(n1 < n2)),
+                        _ => return Err(RuntimeError::OperandsMustBeNumber),
+                    }
+                }
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    writeln!(self.out, "{}", value)?;
+                }
+                OpCode::DefineGlobal => {
+                    let name = unsafe { read_name(ip, chunk) };
+                    let value = self.pop();
+                    self.globals.insert(name.as_string().into(), value);
+                }
+                OpCode::GetGlobal => {
+                    let name = unsafe { read_name(ip, chunk) };
+                    let name = name.as_string();
+                    match self.globals.get(name) {
+                        Some(value) => {
+                            let value = *value;
+                            self.push(value)
+                        }
+                        None => {
+                            return Err(RuntimeError::UndefinedVariable(
+                                String::from_utf8_lossy(name).into(),
+                            ))
+                        }
+                    }
+                }
+                OpCode::SetGlobal => {
+                    let name = unsafe { read_name(ip, chunk) };
+                    let name = name.as_string();
+                    let value = *self.peek_mut();
+                    match self.globals.get_mut(name) {
+                        Some(global) => *global = value,
+                        None => {
+                            return Err(RuntimeError::UndefinedVariable(
+                                String::from_utf8_lossy(name).into(),
+                            ))
+                        }
+                    }
+                }
+                OpCode::GetLocal => {
+                    let slot = unsafe { read_byte(ip) };
+                    let value = self.local(*slots, slot);
+                    self.push(value);
+                }
+                OpCode::SetLocal => {
+                    let slot = unsafe { read_byte(ip) } as usize;
+                    let value = *self.peek_mut();
+                    // SAFETY: the verifier checked that the slot is below the top of the stack
+                    unsafe { *self.stack.get_unchecked_mut(*slots + slot) = value };
+                }
+                OpCode::Jump => {
+                    let offset = unsafe { read_short(ip) };
+                    *ip = unsafe { ip.add(offset as usize) };
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = unsafe { read_short(ip) };
+                    let condition: bool = (*self.peek_mut()).into();
+                    if !condition {
+                        *ip = unsafe { ip.add(offset as usize) };
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = unsafe { read_short(ip) };
+                    *ip = unsafe { ip.sub(offset as usize) };
+                }
+                OpCode::GreaterEqual => {
+                    let (a, b) = self.pop_twice();
+                    match (a.as_number(), b.as_number()) {
+                        // negation of `<` rather than `>=` so that NaN behaves like `Less; Not`
+                        (Some(n1), Some(n2)) => {
+                            self.push(Value::Boolean(n1.partial_cmp(&n2) != Some(Ordering::Less)))
+                        }
+                        _ => return Err(RuntimeError::OperandsMustBeNumber),
+                    }
+                }
+                OpCode::LessEqual => {
+                    let (a, b) = self.pop_twice();
+                    match (a.as_number(), b.as_number()) {
+                        (Some(n1), Some(n2)) => self.push(Value::Boolean(
+                            n1.partial_cmp(&n2) != Some(Ordering::Greater),
+                        )),
+                        _ => return Err(RuntimeError::OperandsMustBeNumber),
+                    }
+                }
+                OpCode::NotEqual => {
+                    let (a, b) = self.pop_twice();
+                    self.push((!a.equals(b)).into())
+                }
+                OpCode::AddConstant => {
+                    let b = unsafe { read_constant(ip, chunk) };
+                    let a = self.pop();
+                    self.add(a, b)?;
+                }
+                OpCode::GetLocalGetLocal => {
+                    let slot1 = unsafe { read_byte(ip) };
+                    let slot2 = unsafe { read_byte(ip) };
+                    let value1 = self.local(*slots, slot1);
+                    self.push(value1);
+                    // the same as two `GetLocal`s, so this can read `value1`
+                    let value2 = self.local(*slots, slot2);
+                    self.push(value2);
+                }
+                OpCode::Call => {
+                    let arg_count = unsafe { read_byte(ip) } as usize;
+                    let callee_slot = self.stack.len() - arg_count - 1;
+                    let callee = self.stack[callee_slot];
+                    if let Some(function) = self.call_value(callee, arg_count, callee_slot)? {
+                        self.frames.push(CallFrame {
+                            chunk: *chunk,
+                            ip: *ip,
+                            slots: *slots,
+                        });
+                        // SAFETY: functions are never freed
+                        *chunk = unsafe { &(*function).chunk };
+                        *ip = chunk.code.as_ptr();
+                        *slots = callee_slot;
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = unsafe { read_name(ip, chunk) };
+                    let name = name.as_string();
+                    let object = *self.peek_mut();
+                    let receiver =
+                        (object.as_obj_ptr()).ok_or(RuntimeError::OnlyInstancesHaveProperties)?;
+                    let value = match receiver.as_obj() {
+                        Obj::Foreign(instance) => match instance.fields.get(name) {
+                            Some(value) => *value,
+                            None => {
+                                let method = Self::find_method(instance, name)?;
+                                self.maybe_collect();
+                                let bound = self.allocate(Obj::BoundNative(BoundNative {
+                                    receiver,
+                                    name: name.into(),
+                                    method,
+                                }));
+                                Value::ObjPtr(bound)
+                            }
+                        },
+                        Obj::Exception(exception) => match name {
+                            b"message" => {
+                                let message = exception.message.clone().into_bytes();
+                                self.maybe_collect();
+                                Value::ObjPtr(self.allocate(Obj::String(message)))
+                            }
+                            b"line" => Value::Number(exception.line as f64),
+                            _ => {
+                                return Err(RuntimeError::UndefinedProperty(
+                                    String::from_utf8_lossy(name).into(),
+                                ))
+                            }
+                        },
+                        _ => {
+                            let builtin = builtins::find_method(receiver, name)?;
+                            self.maybe_collect();
+                            let bound = self.allocate(Obj::BoundBuiltin(BoundBuiltin {
+                                receiver,
+                                name: name.into(),
+                                builtin,
+                            }));
+                            Value::ObjPtr(bound)
+                        }
+                    };
+                    *self.peek_mut() = value;
+                }
+                OpCode::SetProperty => {
+                    let name = unsafe { read_name(ip, chunk) };
+                    let value = self.pop();
+                    let object = self.pop();
+                    match object.as_obj_ptr().map(ObjPtr::as_obj_mut) {
+                        Some(Obj::Foreign(instance)) => {
+                            instance.fields.insert(name.as_string().into(), value);
+                        }
+                        _ => return Err(RuntimeError::OnlyInstancesHaveFields),
+                    }
+                    self.push(value);
+                }
+                OpCode::Invoke => {
+                    let name = unsafe { read_name(ip, chunk) };
+                    let name = name.as_string();
+                    let arg_count = unsafe { read_byte(ip) } as usize;
+                    let receiver_slot = self.stack.len() - arg_count - 1;
+                    let receiver = (self.stack[receiver_slot].as_obj_ptr())
+                        .ok_or(RuntimeError::OnlyInstancesHaveProperties)?;
+                    let function = match receiver.as_obj() {
+                        Obj::Foreign(instance) => match instance.fields.get(name) {
+                            // a field holding something callable
+                            Some(field) => {
+                                let field = *field;
+                                self.stack[receiver_slot] = field;
+                                self.call_value(field, arg_count, receiver_slot)?
+                            }
+                            None => {
+                                let method = Self::find_method(instance, name)?;
+                                self.call_native(receiver, method, receiver_slot)?;
+                                None
+                            }
+                        },
+                        _ => {
+                            let builtin = builtins::find_method(receiver, name)?;
+                            self.call_builtin(receiver, builtin, arg_count, receiver_slot)?;
+                            None
+                        }
+                    };
+                    if let Some(function) = function {
+                        self.frames.push(CallFrame {
+                            chunk: *chunk,
+                            ip: *ip,
+                            slots: *slots,
+                        });
+                        // SAFETY: functions are never freed
+                        *chunk = unsafe { &(*function).chunk };
+                        *ip = chunk.code.as_ptr();
+                        *slots = receiver_slot;
+                    }
+                }
+                OpCode::BuildList => {
+                    let count = unsafe { read_byte(ip) } as usize;
+                    // the elements stay on the stack until the list holding them exists
+                    self.maybe_collect();
+                    let elements = self.stack.split_off(self.stack.len() - count);
+                    let list = self.allocate(Obj::List(elements));
+                    self.push(Value::ObjPtr(list));
+                }
+                OpCode::BuildMap => {
+                    let count = unsafe { read_byte(ip) } as usize;
+                    let entries = self.stack.len() - count * 2;
+                    let mut map = Map::default();
+                    for entry in self.stack[entries..].chunks_exact(2) {
+                        map.insert(map_key(entry[0])?, entry[1]);
+                    }
+                    // the entries stay on the stack until the map holding them exists
+                    self.maybe_collect();
+                    self.stack.truncate(entries);
+                    let map = self.allocate(Obj::Map(map));
+                    self.push(Value::ObjPtr(map));
+                }
+                OpCode::Range => {
+                    let (a, b) = self.pop_twice();
+                    match (a.as_number(), b.as_number()) {
+                        (Some(start), Some(end)) => {
+                            self.maybe_collect();
+                            let range = self.allocate(Obj::Range(Range { start, end }));
+                            self.push(Value::ObjPtr(range));
                         }
                         _ => return Err(RuntimeError::OperandsMustBeNumber),
                     }
                 }
+                OpCode::Stringify => {
+                    let value = *self.peek_mut();
+                    if !value.as_obj_ptr().is_some_and(|ptr| ptr.is_string()) {
+                        let string = value.to_string().into_bytes();
+                        self.maybe_collect();
+                        let ptr = self.allocate(Obj::String(string));
+                        *self.peek_mut() = Value::ObjPtr(ptr);
+                    }
+                }
+                OpCode::MatchValue => {
+                    let (value, pattern) = self.pop_twice();
+                    self.push(Value::Boolean(value.equals(pattern)));
+                }
+                OpCode::MatchRange => {
+                    let end = self.pop();
+                    let (value, start) = self.pop_twice();
+                    let matches = match (value.as_number(), start.as_number(), end.as_number()) {
+                        (Some(n), Some(start), Some(end)) => start <= n && n < end,
+                        _ => false,
+                    };
+                    self.push(Value::Boolean(matches));
+                }
+                OpCode::ForIter => {
+                    let slot = unsafe { read_byte(ip) };
+                    let offset = unsafe { read_short(ip) };
+                    let sequence = self.local(*slots, slot);
+                    match self.iterate(sequence, *slots + slot as usize + 1)? {
+                        Some(element) => self.push(element),
+                        None => {
+                            self.push(Value::Nil);
+                            *ip = unsafe { ip.add(offset as usize) };
+                        }
+                    }
+                }
+                OpCode::Closure => {
+                    let function = unsafe { read_constant(ip, chunk) };
+                    // SAFETY: the verifier checked that the constant is a function
+                    let function = unsafe { function.as_obj_ptr().unwrap_unchecked() };
+                    let captures = match function.as_obj() {
+                        Obj::Function(function) => &function.captures,
+                        _ => unreachable!("closures are always made from functions"),
+                    };
+                    let mut upvalues = Vec::with_capacity(captures.len());
+                    for capture in captures {
+                        upvalues.push(match capture {
+                            Capture::Local(slot) => self.capture_upvalue(*slots + *slot as usize),
+                            Capture::Upvalue(idx) => self.upvalue(*slots, *idx),
+                        });
+                    }
+                    // the new upvalues are open, which keeps them alive
+                    self.maybe_collect();
+                    let closure = self.allocate(Obj::Closure(Closure { function, upvalues }));
+                    self.push(Value::ObjPtr(closure));
+                }
+                OpCode::GetUpvalue => {
+                    let idx = unsafe { read_byte(ip) };
+                    let value = match self.upvalue(*slots, idx).as_obj() {
+                        Obj::Upvalue(Upvalue::Open(slot)) => self.stack[*slot],
+                        Obj::Upvalue(Upvalue::Closed(value)) => *value,
+                        _ => unreachable!("closures only capture upvalues"),
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let idx = unsafe { read_byte(ip) };
+                    let value = *self.peek_mut();
+                    match self.upvalue(*slots, idx).as_obj_mut() {
+                        Obj::Upvalue(Upvalue::Open(slot)) => self.stack[*slot] = value,
+                        Obj::Upvalue(Upvalue::Closed(closed)) => *closed = value,
+                        _ => unreachable!("closures only capture upvalues"),
+                    }
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Dup => {
+                    let count = unsafe { read_byte(ip) } as usize;
+                    let start = self.stack.len() - count;
+                    self.stack.extend_from_within(start..);
+                }
+                OpCode::Bury => {
+                    let depth = unsafe { read_byte(ip) } as usize;
+                    let value = self.pop();
+                    self.stack.insert(self.stack.len() - depth, value);
+                }
+                OpCode::SetupTry => {
+                    let offset = unsafe { read_short(ip) };
+                    self.handlers.push(Handler {
+                        depth: self.frames.len(),
+                        frame: CallFrame {
+                            chunk: *chunk,
+                            ip: unsafe { ip.add(offset as usize) },
+                            slots: *slots,
+                        },
+                        height: self.stack.len(),
+                    });
+                }
+                OpCode::PopTry => {
+                    self.handlers.pop();
+                }
+                OpCode::Throw => return Err(RuntimeError::Thrown(self.pop())),
+                OpCode::GetIndex => {
+                    let (object, index) = self.pop_twice();
+                    let value = match object.as_obj_ptr().map(ObjPtr::as_obj) {
+                        Some(Obj::List(elements)) => elements[list_index(index, elements.len())?],
+                        Some(Obj::Map(map)) => {
+                            (map.get(map_key(index)?)).ok_or_else(|| undefined_key(index))?
+                        }
+                        _ => return Err(RuntimeError::NotIndexable),
+                    };
+                    self.push(value);
+                }
+                OpCode::SetIndex => {
+                    let value = self.pop();
+                    let (object, index) = self.pop_twice();
+                    match object.as_obj_ptr().map(ObjPtr::as_obj_mut) {
+                        Some(Obj::List(elements)) => {
+                            let idx = list_index(index, elements.len())?;
+                            elements[idx] = value;
+                        }
+                        Some(Obj::Map(map)) => map.insert(map_key(index)?, value),
+                        _ => return Err(RuntimeError::NotIndexable),
+                    }
+                    self.push(value);
+                }
             }
         }
-        Ok(0f64.into())
     }
 
-    fn pop_unsafe(stack: &mut Stack) -> Value {
-        stack.pop().expect(STACK_UNDERFLOW)
+    // The upvalue for the local in `slot`, which is shared by every closure
+    // capturing the local while it's on the stack.
+    fn capture_upvalue(&mut self, slot: usize) -> ObjPtr {
+        let idx = (self.open_upvalues).partition_point(|upvalue| open_slot(*upvalue) < slot);
+        if let Some(upvalue) = self.open_upvalues.get(idx) {
+            if open_slot(*upvalue) == slot {
+                return *upvalue;
+            }
+        }
+        self.maybe_collect();
+        let upvalue = self.allocate(Obj::Upvalue(Upvalue::Open(slot)));
+        self.open_upvalues.insert(idx, upvalue);
+        upvalue
+    }
+
+    // upvalue `idx` of the closure running in the frame starting at `slots`
+    fn upvalue(&self, slots: usize, idx: u8) -> ObjPtr {
+        match self.stack[slots].as_obj_ptr().map(ObjPtr::as_obj) {
+            Some(Obj::Closure(closure)) => closure.upvalues[idx as usize],
+            _ => unreachable!("only closures have upvalues"),
+        }
+    }
+
+    // Moves the locals from `slot` upwards that were captured into their
+    // upvalues, as they are about to be popped.
+    fn close_upvalues(&mut self, slot: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let open = open_slot(*upvalue);
+            if open < slot {
+                break;
+            }
+            *upvalue.as_obj_mut() = Obj::Upvalue(Upvalue::Closed(self.stack[open]));
+            self.open_upvalues.pop();
+        }
+    }
+
+    fn add(&mut self, a: Value, b: Value) -> Result<(), RuntimeError> {
+        if let (Some(a), Some(b)) = (a.as_number(), b.as_number()) {
+            self.push(Value::Number(a + b));
+            return Ok(());
+        }
+        match (a.as_obj_ptr(), b.as_obj_ptr()) {
+            (Some(a), Some(b)) if a.is_string() && b.is_string() => {
+                let concat = [a.as_string(), b.as_string()].concat();
+                // the operands have been copied, so it doesn't matter that
+                // they are no longer on the stack
+                self.maybe_collect();
+                let ptr = self.allocate(Obj::String(concat));
+                self.push(Value::ObjPtr(ptr));
+                Ok(())
+            }
+            _ => Err(RuntimeError::OperandsMustBeNumber),
+        }
     }
 
-    fn pop_twice_unsafe(stack: &mut Stack) -> (Value, Value) {
-        let b = stack.pop().expect(STACK_UNDERFLOW);
-        let a = stack.pop().expect(STACK_UNDERFLOW);
+    fn allocate(&mut self, obj: Obj) -> ObjPtr {
+        let ptr = obj.into_obj_ptr();
+        self.objects.push_back(ptr);
+        self.stats.allocations += 1;
+        ptr
+    }
+
+    // Collections only happen right before an instruction allocates, while
+    // everything it still needs is reachable from the stack or the globals.
+    fn maybe_collect(&mut self) {
+        if self.gc_stress || self.objects.len() >= self.next_gc {
+            self.collect_garbage();
+        }
+    }
+
+    // Frees every object allocated while running that can't be reached from
+    // the stack or the globals anymore.
+    pub fn collect_garbage(&mut self) {
+        self.stats.collections += 1;
+        let mut reachable = HashSet::new();
+        let mut gray = (self.stack.iter().chain(self.globals.values()))
+            .filter_map(|value| value.as_obj_ptr())
+            .chain(self.open_upvalues.iter().copied())
+            .collect::<Vec<_>>();
+        while let Some(ptr) = gray.pop() {
+            if !reachable.insert(ptr) {
+                continue;
+            }
+            match ptr.as_obj() {
+                Obj::Foreign(instance) => {
+                    gray.push(instance.class);
+                    gray.extend(
+                        instance
+                            .fields
+                            .values()
+                            .filter_map(|value| value.as_obj_ptr()),
+                    );
+                    if let Obj::HostClass(HostClass {
+                        trace: Some(trace), ..
+                    }) = instance.class.as_obj()
+                    {
+                        trace(&*instance.payload, &mut |value| {
+                            gray.extend(value.as_obj_ptr());
+                        });
+                    }
+                }
+                Obj::BoundNative(bound) => gray.push(bound.receiver),
+                Obj::List(elements) => {
+                    gray.extend(elements.iter().filter_map(|value| value.as_obj_ptr()))
+                }
+                Obj::Map(map) => gray.extend(
+                    (map.entries().iter())
+                        .flat_map(|(key, value)| [key, value])
+                        .filter_map(|value| value.as_obj_ptr()),
+                ),
+                Obj::BoundBuiltin(bound) => gray.push(bound.receiver),
+                Obj::Range(_) | Obj::Exception(_) => (),
+                // the function of a closure is a constant
+                Obj::Closure(closure) => gray.extend(closure.upvalues.iter().copied()),
+                Obj::Upvalue(Upvalue::Closed(value)) => gray.extend(value.as_obj_ptr()),
+                // the local is on the stack
+                Obj::Upvalue(Upvalue::Open(_)) => (),
+                // functions are constants and only refer to other constants
+                Obj::String(_) | Obj::Function(_) | Obj::HostClass(_) | Obj::Native(_) => (),
+            }
+        }
+        for ptr in std::mem::take(&mut self.objects) {
+            if reachable.contains(&ptr) {
+                self.objects.push_back(ptr);
+            } else {
+                unsafe { ptr.free() };
+            }
+        }
+        self.next_gc = (self.objects.len() * 2).max(GC_INITIAL_THRESHOLD);
+    }
+
+    pub fn define_native(&mut self, name: &str, arity: usize, function: NativeFunction) {
+        let ptr = self.allocate(Obj::Native(Native {
+            name: name.into(),
+            arity,
+            function,
+        }));
+        self.globals.insert(name.into(), Value::ObjPtr(ptr));
+    }
+
+    // allocates a string owned by the VM, which is freed once unreachable
+    pub fn new_string(&mut self, string: &str) -> Value {
+        Value::ObjPtr(self.allocate(Obj::String(string.into())))
+    }
+
+    // makes `class` available to Lox code as a global with the same name
+    pub fn define_host_class(&mut self, class: HostClass) {
+        let name = class.name.clone();
+        let ptr = self.allocate(Obj::HostClass(class));
+        self.globals.insert(name, Value::ObjPtr(ptr));
+    }
+
+    fn push(&mut self, value: Value) {
+        // SAFETY: the verifier and the checks on calls keep the stack within
+        // `STACK_MAX`, which is what it was allocated with
+        debug_assert!(self.stack.len() < self.stack.capacity());
+        unsafe {
+            let len = self.stack.len();
+            self.stack.as_mut_ptr().add(len).write(value);
+            self.stack.set_len(len + 1);
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        // SAFETY: the verifier checked that no instruction pops an empty stack
+        debug_assert!(!self.stack.is_empty());
+        unsafe {
+            let len = self.stack.len() - 1;
+            self.stack.set_len(len);
+            *self.stack.as_ptr().add(len)
+        }
+    }
+
+    fn pop_twice(&mut self) -> (Value, Value) {
+        let b = self.pop();
+        let a = self.pop();
         (a, b)
     }
 
+    fn peek_mut(&mut self) -> &mut Value {
+        let len = self.stack.len();
+        unsafe { self.stack.get_unchecked_mut(len - 1) }
+    }
+
+    fn local(&self, slots: usize, slot: u8) -> Value {
+        unsafe { *self.stack.get_unchecked(slots + slot as usize) }
+    }
+
+    // Compiles `source` into a fresh top level function, without running it.
+    pub fn compile(
+        source: &ByteSlice,
+        opt_level: OptLevel,
+        mode: CompileMode,
+    ) -> Result<Function, InterpretError> {
+        let tokens =
+            scan(source).map_err(|error| InterpretError::Compile(vec![error.to_string()]))?;
+        let (statements, errors) = parse(&tokens);
+        if !errors.is_empty() {
+            let errors = errors.iter().map(|error| error.to_string()).collect();
+            return Err(InterpretError::Compile(errors));
+        }
+        compile_script(&statements, opt_level, mode)
+            .map_err(|error| InterpretError::Compile(vec![error.to_string()]))
+    }
+
+    // Compiles `source` and runs it. Globals and objects are kept around for
+    // the next run.
     pub fn run(
         &mut self,
-        source: &str,
-        chunk: &mut Chunk,
+        source: &ByteSlice,
+        opt_level: OptLevel,
+        mode: CompileMode,
         debug: bool,
-    ) -> Result<Value, RuntimeError> {
-        let tokens = scan(source.as_bytes()).unwrap();
-        let (expr, _) = parse_expression(&tokens, 0).unwrap();
-        compile(&expr, chunk).unwrap();
-        self.run_bytecode(chunk, debug)
+    ) -> Result<Value, InterpretError> {
+        let script = Self::compile(source, opt_level, mode)?;
+        if debug {
+            (script.chunk)
+                .disassemble("script", &mut self.err)
+                .map_err(RuntimeError::from)?;
+        }
+        // locals never outlive the source they were declared in
+        self.reset_stack();
+        Ok(self.run_bytecode(&script.chunk, debug)?)
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = (&ByteSlice, Value)> {
+        self.globals.iter().map(|(name, value)| (&name[..], *value))
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        let mut stats = HeapStats {
+            objects: self.objects.len(),
+            bytes: 0,
+            kinds: BTreeMap::new(),
+            next_gc: self.next_gc,
+        };
+        for ptr in &self.objects {
+            let obj = ptr.as_obj();
+            stats.bytes += obj.size();
+            *stats.kinds.entry(obj.kind()).or_default() += 1;
+        }
+        stats
+    }
+
+    // Forgets every global and frees every object allocated while running.
+    pub fn reset(&mut self) {
+        self.reset_stack();
+        self.globals.clear();
+        for ptr in std::mem::take(&mut self.objects) {
+            unsafe { ptr.free() };
+        }
+        self.next_gc = GC_INITIAL_THRESHOLD;
+    }
+
+    pub fn get_global(&self, name: &ByteSlice) -> Option<Value> {
+        self.globals.get(name).copied()
+    }
+
+    pub fn set_global(&mut self, name: &ByteSlice, value: Value) {
+        self.globals.insert(name.into(), value);
     }
 }
@@ -1,6 +1,14 @@
 #[cfg(test)]
 mod tests {
-    use crate::{chunk::Chunk, opcode::OpCode, value::Value, vm::VM};
+    use crate::{
+        chunk::Chunk,
+        compiler::{CompileMode, OptLevel},
+        error::{InterpretError, RuntimeError},
+        opcode::OpCode,
+        output::OutputBuffer,
+        value::Value,
+        vm::VM,
+    };
 
     #[test]
     fn test_binary_ops() {
@@ -51,4 +59,755 @@ mod tests {
         vm.run_bytecode(&chunk, false).unwrap();
         assert_eq!(vm.stack.last().unwrap(), &Value::Number(90f64));
     }
+
+    #[test]
+    fn test_globals_locals_and_loops() {
+        let source = "
+            var total = 0;
+            {
+                var i = 0;
+                while (i < 5) {
+                    if (i != 2 and true) total = total + i;
+                    i = i + 1;
+                }
+            }
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let mut vm = VM::new();
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(vm.globals[&b"total"[..]], Value::Number(8f64));
+            assert!(vm.stack.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_fused_instructions() {
+        let source = "
+            var results = 0;
+            {
+                var a = 3;
+                var b = 4;
+                if (a >= b) results = results + 1;
+                if (a <= b) results = results + 10;
+                if (a != b) results = results + 100;
+                if (a + b >= 7) results = results + 1000;
+            }
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let mut vm = VM::new();
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(vm.globals[&b"results"[..]], Value::Number(1110f64));
+        }
+    }
+
+    #[test]
+    fn test_strings_compare_by_contents() {
+        // `a` and `b` are separate constants, and `c` is built at runtime
+        let source = "
+            var a = \"lox\";
+            var b = \"lox\";
+            var c = \"lo\" + a;
+            var equal = a == b and a + \"x\" == \"l\" + \"oxx\" and !(a != b) and c != a;
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let mut vm = VM::new();
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(vm.globals[&b"equal"[..]], Value::Boolean(true));
+        }
+    }
+
+    #[test]
+    fn test_functions() {
+        let source = "
+            fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            fun sum(a, b, c) { var total = a + b; return total + c; }
+            fun nothing() {}
+            var result = fib(15) + sum(1, 2, 3);
+            var none = nothing();
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let mut vm = VM::new();
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(vm.globals[&b"result"[..]], Value::Number(616f64));
+            assert_eq!(vm.globals[&b"none"[..]], Value::Nil);
+            assert!(vm.stack.is_empty());
+            assert!(vm.frames.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_call_errors() {
+        let cases = [
+            ("fun f(a) {} f();", "Expected 1 arguments but got 0"),
+            ("var f = 1; f();", "Can only call functions"),
+            ("fun f() { f(); } f();", "Stack overflow"),
+        ];
+        for (source, message) in cases {
+            let mut vm = VM::new();
+            let error = vm
+                .run(source.as_bytes(), OptLevel::O1, CompileMode::Script, false)
+                .unwrap_err();
+            assert_eq!(error.to_string(), message);
+            assert!(vm.frames.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_lists() {
+        let source = "
+            var a = [1, 2, [3]];
+            a[0] = a[0] + a[2][0];
+            a.push(5);
+            a.insert(0, 0);
+            a.insert(a.len(), 6);
+            var removed = a.remove(2);
+            var popped = a.pop();
+            var push = a.push;
+            push(a.len());
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let out = OutputBuffer::new();
+            let mut vm = VM::new();
+            vm.set_output(Box::new(out.clone()));
+            vm.set_gc_stress(true);
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            vm.run(b"print a;", opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(out.take(), "[0, 4, [3], 5, 4]\n");
+            assert_eq!(vm.globals[&b"removed"[..]], Value::Number(2f64));
+            assert_eq!(vm.globals[&b"popped"[..]], Value::Number(6f64));
+        }
+    }
+
+    #[test]
+    fn test_list_errors() {
+        let cases = [
+            (
+                "[1, 2][2];",
+                "Index 2 is out of bounds for a list of length 2",
+            ),
+            (
+                "[1][-1] = 0;",
+                "Index -1 is out of bounds for a list of length 1",
+            ),
+            ("[1][0.5];", "Index must be a whole number"),
+            ("[1][\"0\"];", "Index must be a whole number"),
+            ("nil[0];", "Can only index lists and maps"),
+            ("[].pop();", "Can't pop from an empty list"),
+            (
+                "[].remove(0);",
+                "Index 0 is out of bounds for a list of length 0",
+            ),
+            ("[].push();", "Expected 1 arguments but got 0"),
+            ("[].size();", "Undefined property size"),
+            ("[]();", "Can only call functions"),
+        ];
+        for (source, message) in cases {
+            let mut vm = VM::new();
+            let error = vm
+                .run(source.as_bytes(), OptLevel::O1, CompileMode::Script, false)
+                .unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_maps() {
+        let source = "
+            var m = {\"a\": 1, 2: \"two\", true: nil};
+            m[\"a\"] = m[\"a\"] + 1;
+            // any string with the same contents finds the entry
+            m[\"b\" + \"c\"] = [m[\"a\"]];
+            m[nil] = {};
+            var hasBc = m.has(\"bc\");
+            var removed = m.remove(2);
+            var hasTwo = m.has(2);
+            var keys = m.keys();
+            var values = m.values();
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let out = OutputBuffer::new();
+            let mut vm = VM::new();
+            vm.set_output(Box::new(out.clone()));
+            vm.set_gc_stress(true);
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            vm.run(
+                b"print m; print keys; print values; print m.len();",
+                opt_level,
+                CompileMode::Script,
+                false,
+            )
+            .unwrap();
+            assert_eq!(
+                out.take(),
+                "{a: 2, true: nil, bc: [2], nil: {}}\n\
+                 [a, true, bc, nil]\n\
+                 [2, nil, [2], {}]\n\
+                 4\n"
+            );
+            assert_eq!(vm.globals[&b"hasBc"[..]], Value::Boolean(true));
+            assert_eq!(vm.globals[&b"hasTwo"[..]], Value::Boolean(false));
+            assert!(vm.globals[&b"removed"[..]].as_str() == Some("two"));
+        }
+    }
+
+    #[test]
+    fn test_map_errors() {
+        let cases = [
+            ("({})[\"a\"];", "Undefined key a"),
+            ("({}).remove(1);", "Undefined key 1"),
+            (
+                "var m = {[]: 1};",
+                "Map keys must be strings, numbers, booleans or nil",
+            ),
+            (
+                "({})[{}] = 1;",
+                "Map keys must be strings, numbers, booleans or nil",
+            ),
+            ("({}).has();", "Expected 1 arguments but got 0"),
+        ];
+        for (source, message) in cases {
+            let mut vm = VM::new();
+            let error = vm
+                .run(source.as_bytes(), OptLevel::O1, CompileMode::Script, false)
+                .unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_for_in() {
+        let source = "
+            for (var x in [1, \"two\", nil]) print x;
+            for (var key in {\"a\": 1, 2: 2}) print key;
+            for (var c in \"h\u{e9}!\") print c;
+            var sum = 0;
+            for (var i in 1..101) sum = sum + i;
+            print sum;
+            var empty = 3..1;
+            for (var i in empty) print i;
+            var pairs = [];
+            for (var i in 0..2) {
+                for (var j in [\"a\", \"b\"]) {
+                    pairs.push([i, j]);
+                }
+            }
+            print pairs;
+            print 0..3;
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let out = OutputBuffer::new();
+            let mut vm = VM::new();
+            vm.set_output(Box::new(out.clone()));
+            vm.set_gc_stress(true);
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(
+                out.take(),
+                "1\ntwo\nnil\na\n2\nh\n\u{e9}\n!\n5050\n\
+                 [[0, a], [0, b], [1, a], [1, b]]\n0..3\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_for_in_errors() {
+        let cases = [
+            (
+                "for (var x in 1) print x;",
+                "Can only iterate over lists, maps, strings, ranges and instances",
+            ),
+            ("var r = \"a\"..1;", "Operands should be number"),
+        ];
+        for (source, message) in cases {
+            let mut vm = VM::new();
+            let error = vm
+                .run(source.as_bytes(), OptLevel::O1, CompileMode::Script, false)
+                .unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let source = r#"
+            print "tab\there\n\"quoted\" \\ caf\u{e9} \${not}";
+            var name = "lox";
+            fun f(n) { return n * 2; }
+            print "Hello ${name}! ${f(2) + 1} ${nil} ${[1, "a"]}";
+            print "${"nested ${name}"}" + "${1}${2}";
+            var s = "${name}";
+            var same = s == name;
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let out = OutputBuffer::new();
+            let mut vm = VM::new();
+            vm.set_output(Box::new(out.clone()));
+            vm.set_gc_stress(true);
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(
+                out.take(),
+                "tab\there\n\"quoted\" \\ café ${not}\n\
+                 Hello lox! 5 nil [1, a]\n\
+                 nested lox12\n"
+            );
+            // strings aren't copied when they are interpolated on their own
+            assert_eq!(vm.globals[&b"same"[..]], Value::Boolean(true));
+        }
+    }
+
+    #[test]
+    fn test_string_methods() {
+        let source = r#"
+            var s = "  Grüße, 世界! ";
+            print s.len();
+            print s.trim().upper();
+            print s.trim().lower();
+            print s.trim().chars();
+            print "é!".bytes();
+            print s.substring(2, 7);
+            print s.substring(0, 0).len();
+            print s.indexOf("世界");
+            print s.indexOf("x");
+            print "a,b,,c".split(",");
+            print "añ".split("");
+            var trim = s.trim;
+            print trim();
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let out = OutputBuffer::new();
+            let mut vm = VM::new();
+            vm.set_output(Box::new(out.clone()));
+            vm.set_gc_stress(true);
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(
+                out.take(),
+                "13\nGRÜSSE, 世界!\ngrüße, 世界!\n[G, r, ü, ß, e, ,,  , 世, 界, !]\n\
+                 [195, 169, 33]\nGrüße\n0\n9\n-1\n[a, b, , c]\n[a, ñ]\nGrüße, 世界!\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_string_method_errors() {
+        let cases = [
+            (
+                "\"abc\".substring(2, 5);",
+                "Substring 2..5 is out of bounds for a string of length 3",
+            ),
+            (
+                "\"日本\".substring(1, 0);",
+                "Substring 1..0 is out of bounds for a string of length 2",
+            ),
+            ("\"abc\".substring(0.5, 1);", "Index must be a whole number"),
+            ("\"abc\".indexOf(1);", "Argument should be a string"),
+            ("\"abc\".split(nil);", "Argument should be a string"),
+            ("\"abc\".push(1);", "Undefined property push"),
+        ];
+        for (source, message) in cases {
+            let mut vm = VM::new();
+            let error = vm
+                .run(source.as_bytes(), OptLevel::O1, CompileMode::Script, false)
+                .unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let source = r#"
+            for (var i = 0; i < 10; i = i + 1) {
+                var square = i * i;
+                if (i == 2) continue;
+                if (square > 30) break;
+                print i;
+            }
+            var n = 0;
+            while (true) {
+                n = n + 1;
+                { var a = 1; var b = 2; if (n < 3) continue; }
+                if (n >= 5) break;
+            }
+            print n;
+            for (var x in [1, 2, 3, 4]) {
+                var y = x * 10;
+                for (var z in 0..3) { if (z == 1) break; print y + z; }
+                if (x == 2) continue;
+                if (x == 3) break;
+                print x;
+            }
+            fun f() { while (true) { var q = 5; return q; } }
+            print f();
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let out = OutputBuffer::new();
+            let mut vm = VM::new();
+            vm.set_output(Box::new(out.clone()));
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(out.take(), "0\n1\n3\n4\n5\n5\n10\n1\n20\n30\n5\n");
+        }
+    }
+
+    #[test]
+    fn test_match() {
+        let source = r#"
+            fun describe(x) {
+                match (x) {
+                    case 0 => return "zero";
+                    case 1, 2, 3 => return "small";
+                    case -5..0 => return "negative";
+                    case 4..100 => { var size = "medium"; return size; }
+                    case "x", "y" => return "letter";
+                    case true => return "yes";
+                    case nil => return "nothing";
+                    default => return "other";
+                }
+            }
+            for (var v in [0, 2, -3, 50, "x" + "", "y", true, false, nil, 1000, 3.5]) {
+                print describe(v);
+            }
+            for (var i in 0..5) {
+                match (i) {
+                    case 1 => continue;
+                    case 3 => { var last = i; break; }
+                }
+                print i;
+            }
+            match ("no default") { case "other" => print "wrong"; }
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let out = OutputBuffer::new();
+            let mut vm = VM::new();
+            vm.set_output(Box::new(out.clone()));
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(
+                out.take(),
+                "zero\nsmall\nnegative\nmedium\nletter\nletter\nyes\nother\nnothing\nother\nother\n\
+                 0\n2\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_closures() {
+        let source = "
+            fun counter() {
+                var count = 0;
+                fun increment() {
+                    count = count + 1;
+                    return count;
+                }
+                return increment;
+            }
+            var a = counter();
+            var b = counter();
+            a();
+            print a();
+            print b();
+            var get;
+            var set;
+            {
+                var shared = \"before\";
+                get = () => shared;
+                set = (value) => shared = value;
+            }
+            set(\"after\");
+            print get();
+            var loops = [];
+            for (var i in 0..3) {
+                if (i == 2) break;
+                loops.push(() => i);
+            }
+            print loops.map((f) => f());
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let out = OutputBuffer::new();
+            let mut vm = VM::new();
+            vm.set_output(Box::new(out.clone()));
+            vm.set_gc_stress(true);
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(out.take(), "2\n1\nafter\n[0, 1]\n");
+        }
+    }
+
+    #[test]
+    fn test_lambdas_and_callbacks() {
+        let source = "
+            var add = fun (a, b) { return a + b; };
+            print add(1, 2);
+            print (() => \"now\")();
+            var offset = 10;
+            print [1, 2, 3].map((x) => x + offset);
+            print [1, 2, 3, 4].filter((x) => x > 2);
+            var pairs = [[2, \"b\"], [1, \"a\"], [2, \"a\"], [1, \"b\"]];
+            print pairs.sort((p, q) => p[0] - q[0]);
+            print [[1, 2], [3]].map((xs) => xs.map((x) => \"${x}!\"));
+            var sort = [3, 1, 2].sort;
+            print sort((a, b) => b - a);
+            print [].map(add);
+            print fun () {};
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let out = OutputBuffer::new();
+            let mut vm = VM::new();
+            vm.set_output(Box::new(out.clone()));
+            vm.set_gc_stress(true);
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(
+                out.take(),
+                "3\nnow\n[11, 12, 13]\n[3, 4]\n\
+                 [[1, a], [1, b], [2, b], [2, a]]\n[[1!, 2!], [3!]]\n[3, 2, 1]\n[]\n\
+                 <fn lambda>\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_updates() {
+        let source = "
+            var m = {\"k\": \"a\"};
+            m[\"k\"] += \"b\";
+            print m[\"k\"] += \"c\";
+            var l = [1, 2];
+            var i = 0;
+            print l[i++]++;
+            print --l[i] + l[0];
+            fun counter() {
+                var n = 0;
+                return () => { n += 10; return n--; };
+            }
+            var next = counter();
+            next();
+            print next();
+            print l[0] % 2 == 0 ? \"even\" : \"odd\";
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let out = OutputBuffer::new();
+            let mut vm = VM::new();
+            vm.set_output(Box::new(out.clone()));
+            vm.set_gc_stress(true);
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(out.take(), "abc\n1\n3\n19\neven\n");
+            assert!(vm.stack.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_operator_errors() {
+        let cases = [
+            ("var a = 1.5; print a | 1;", "Operands should be integers"),
+            (
+                "var a = \"1\"; print a << 1;",
+                "Operands should be integers",
+            ),
+            (
+                "var a = 1; print a << 64;",
+                "Shift amount should be between 0 and 63",
+            ),
+            ("var a = 0.5; print ~a;", "Operand should be an integer"),
+            ("var a = nil; print a % 2;", "Operands should be number"),
+            ("var a = true; a++;", "Operands should be number"),
+            (
+                "var l = []; l[0] += 1;",
+                "Index 0 is out of bounds for a list of length 0",
+            ),
+        ];
+        for (source, message) in cases {
+            let mut vm = VM::new();
+            let error = vm
+                .run(source.as_bytes(), OptLevel::O1, CompileMode::Script, false)
+                .unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_callback_errors() {
+        let cases = [
+            ("[1].map((a, b) => a);", "Expected 2 arguments but got 1"),
+            (
+                "[1, 2].sort((a, b) => true);",
+                "Comparison function should return a number",
+            ),
+            ("[1].filter(nil);", "Can only call functions"),
+            ("[1].map((x) => -nil);", "Operand should be number"),
+            ("fun f(x) { return [x].map(f); } f(1);", "Stack overflow"),
+        ];
+        for (source, message) in cases {
+            let mut vm = VM::new();
+            let error = vm
+                .run(source.as_bytes(), OptLevel::O1, CompileMode::Script, false)
+                .unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let source = "
+            fun check(x) {
+                if (x > 1) throw \"too big: ${x}\";
+                return x;
+            }
+            try {
+                print check(1);
+                print check(2);
+            } catch (e) {
+                print e;
+            }
+            try {
+                var a = nil;
+                print -a;
+            } catch (e) {
+                print \"${e.message} on line ${e.line}\";
+            }
+            var doubled = nil;
+            try {
+                doubled = [1, 2, 3].map((x) => check(x) * 2);
+            } catch (e) {
+                print \"map: ${e}\";
+            }
+            print [1, 2, 3].map((x) => {
+                try { return check(x); } catch (e) { return 0; }
+            });
+            fun cleanup() {
+                var log = [];
+                for (var i in 0..3) {
+                    try {
+                        if (i == 1) continue;
+                        if (i == 2) break;
+                        log.push(\"body\");
+                    } finally {
+                        log.push(i);
+                    }
+                }
+                try {
+                    try { throw \"inner\"; } finally { log.push(\"finally\"); }
+                } catch (e) {
+                    log.push(e);
+                }
+                try { return log; } finally { log.push(\"return\"); }
+            }
+            print cleanup();
+        ";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let out = OutputBuffer::new();
+            let mut vm = VM::new();
+            vm.set_output(Box::new(out.clone()));
+            vm.set_gc_stress(true);
+            vm.run(source.as_bytes(), opt_level, CompileMode::Script, false)
+                .unwrap();
+            assert_eq!(
+                out.take(),
+                "1\ntoo big: 2\nOperand should be number on line 14\nmap: too big: 2\n\
+                 [1, 0, 0]\n[body, 0, 1, 2, finally, inner, return]\n"
+            );
+            assert!(vm.stack.is_empty());
+            assert!(vm.handlers.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_uncaught_exceptions() {
+        let cases = [
+            ("throw \"oops\";", "Uncaught exception: oops"),
+            (
+                "try { throw 1; } finally { print 2; }",
+                "Uncaught exception: 1",
+            ),
+            (
+                "try { nil(); } catch (e) { e(); }",
+                "Can only call functions",
+            ),
+            (
+                "try { print x; } finally { print 2; }",
+                "Uncaught exception: Undefined variable x",
+            ),
+            ("[1].map((x) => { throw x; });", "Uncaught exception: 1"),
+            (
+                "try { throw 1; } catch (e) { print e.message; }",
+                "Only instances have properties",
+            ),
+        ];
+        for (source, message) in cases {
+            let mut vm = VM::new();
+            vm.set_output(Box::new(OutputBuffer::new()));
+            let error = vm
+                .run(source.as_bytes(), OptLevel::O1, CompileMode::Script, false)
+                .unwrap_err();
+            assert_eq!(error.to_string(), message);
+            assert!(vm.handlers.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_step_limit_is_not_catchable() {
+        let mut vm = VM::new();
+        vm.set_max_steps(Some(1000));
+        let error = vm
+            .run(
+                b"try { while (true) {} } catch (e) { print e; }",
+                OptLevel::O1,
+                CompileMode::Script,
+                false,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            InterpretError::Runtime(RuntimeError::StepLimit(1000))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_invalid_bytecode() {
+        let mut chunk = Chunk::default();
+        let mut vm = VM::new();
+        chunk.write(OpCode::Add as u8, 123);
+        assert!(matches!(
+            vm.run_bytecode(&chunk, false),
+            Err(RuntimeError::InvalidBytecode(_))
+        ));
+    }
+
+    #[test]
+    fn test_output_is_redirected() {
+        let out = OutputBuffer::new();
+        let err = OutputBuffer::new();
+        let mut vm = VM::new();
+        vm.set_output(Box::new(out.clone()));
+        vm.set_error_output(Box::new(err.clone()));
+        vm.run(
+            b"print 1; print \"two\";",
+            OptLevel::O1,
+            CompileMode::Script,
+            false,
+        )
+        .unwrap();
+        assert_eq!(out.take(), "1\ntwo\n");
+        assert_eq!(err.contents(), "");
+
+        vm.run(b"print nil;", OptLevel::O1, CompileMode::Script, true)
+            .unwrap();
+        assert_eq!(out.contents(), "nil\n");
+        let trace = err.contents();
+        assert!(trace.starts_with("== script =="));
+        assert!(trace.contains("[TRACE]"));
+        assert!(trace.contains("OP_PRINT"));
+    }
 }
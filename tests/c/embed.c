@@ -0,0 +1,83 @@
+/* Drives the interpreter through the C API, see tests/c_api.rs. */
+#include <stdio.h>
+#include <string.h>
+
+#include "rlox.h"
+
+static LoxValue *add(void *user_data, int argc, const LoxValue *const *args) {
+    int *calls = user_data;
+    (*calls)++;
+    if (lox_value_type(args[0]) != LOX_TYPE_NUMBER || lox_value_type(args[1]) != LOX_TYPE_NUMBER) {
+        return lox_error("add takes two numbers");
+    }
+    return lox_number(lox_value_as_number(args[0]) + lox_value_as_number(args[1]));
+}
+
+static LoxValue *shout(void *user_data, int argc, const LoxValue *const *args) {
+    char buffer[64];
+    const char *string = lox_value_as_string(args[0]);
+    snprintf(buffer, sizeof buffer, "%s!", string ? string : "?");
+    return lox_string(buffer);
+}
+
+static void print_global(LoxVM *vm, const char *name) {
+    LoxValue *value = lox_get_global(vm, name);
+    if (value == NULL) {
+        printf("%s undefined\n", name);
+        return;
+    }
+    switch (lox_value_type(value)) {
+    case LOX_TYPE_NIL: printf("%s = nil\n", name); break;
+    case LOX_TYPE_BOOL: printf("%s = %s\n", name, lox_value_as_bool(value) ? "true" : "false"); break;
+    case LOX_TYPE_NUMBER: printf("%s = %g\n", name, lox_value_as_number(value)); break;
+    case LOX_TYPE_STRING: printf("%s = \"%s\"\n", name, lox_value_as_string(value)); break;
+    default: printf("%s = <object>\n", name); break;
+    }
+    lox_value_free(value);
+}
+
+static void run(LoxVM *vm, const char *source) {
+    LoxResult result = lox_interpret(vm, source);
+    const char *error = lox_last_error(vm);
+    printf("%d %s\n", result, error ? error : "(null)");
+}
+
+int main(void) {
+    /* interleave with what the interpreter prints */
+    setvbuf(stdout, NULL, _IONBF, 0);
+
+    int calls = 0;
+    LoxVM *vm = lox_vm_new();
+    lox_define_native(vm, "add", 2, add, &calls);
+    lox_define_native(vm, "shout", 1, shout, NULL);
+
+    LoxValue *greeting = lox_string("hello");
+    lox_set_global(vm, "greeting", greeting);
+    lox_value_free(greeting);
+    LoxValue *limit = lox_number(3);
+    lox_set_global(vm, "limit", limit);
+    lox_value_free(limit);
+
+    if (lox_interpret(vm, "var sum = 0;\n"
+                          "for (var i = 0; i < limit; i = i + 1) sum = add(sum, i);\n"
+                          "var loud = shout(greeting);\n"
+                          "var done = true;\n"
+                          "print loud;") != LOX_OK) {
+        printf("error: %s\n", lox_last_error(vm));
+        return 1;
+    }
+    print_global(vm, "sum");
+    print_global(vm, "loud");
+    print_global(vm, "done");
+    print_global(vm, "add");
+    print_global(vm, "missing");
+    printf("calls = %d\n", calls);
+
+    run(vm, "add(1, \"two\");");
+    run(vm, "var = 1;");
+    /* the VM is still usable after errors */
+    run(vm, "print add(sum, 1);");
+
+    lox_vm_free(vm);
+    return 0;
+}
@@ -0,0 +1,48 @@
+// Builds `tests/c/embed.c` against the cdylib with `cc` and checks what it
+// prints.
+#![cfg(unix)]
+
+use std::{env, path::Path, process::Command};
+
+#[test]
+fn c_api() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // integration tests live next to the libraries they were built with
+    let deps = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let executable = Path::new(env!("CARGO_TARGET_TMPDIR")).join("embed");
+
+    let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(root.join("tests/c/embed.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-L")
+        .arg(&deps)
+        .arg(format!("-Wl,-rpath,{}", deps.display()))
+        .arg("-lrlox_bytecode")
+        .arg("-o")
+        .arg(&executable)
+        .status()
+        .expect("failed to run cc");
+    assert!(status.success(), "cc failed");
+
+    let output = Command::new(&executable).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "hello!\n\
+         sum = 3\n\
+         loud = \"hello!\"\n\
+         done = true\n\
+         add = <object>\n\
+         missing undefined\n\
+         calls = 3\n\
+         2 add takes two numbers\n\
+         1 Found token = but expected Identifier on line 1\n\
+         4\n\
+         0 (null)\n"
+    );
+}
@@ -0,0 +1,157 @@
+// Runs the `rlox-bytecode` binary with the options and subcommands from
+// `--help` and checks what it prints and how it exits.
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rlox-bytecode"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_run_code_and_stdin() {
+    let output = run(&["-e", "print 1 + 2;"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "3\n");
+
+    for args in [&["-"][..], &["run", "-"][..]] {
+        let output = run(args, "var a = \"in\"; print a;");
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(stdout(&output), "in\n");
+    }
+}
+
+#[test]
+fn test_exit_codes() {
+    let cases: [(&[&str], i32); 7] = [
+        (&["-e", "print ;"], 65),
+        (&["-e", "print nope;"], 70),
+        (&["does/not/exist.lox"], 66),
+        (&["--no-such-option"], 64),
+        (&["run"], 64),
+        (&["--max-steps", "many"], 64),
+        (&["-e", "print 1;", "extra", "args"], 64),
+    ];
+    for (args, code) in cases {
+        let output = run(args, "");
+        assert_eq!(output.status.code(), Some(code), "{:?}", args);
+    }
+}
+
+#[test]
+fn test_help() {
+    let output = run(&["--help"], "");
+    assert_eq!(output.status.code(), Some(0));
+    let help = stdout(&output);
+    for command in [
+        "run",
+        "repl",
+        "compile",
+        "disasm",
+        "check",
+        "fmt",
+        "--max-steps",
+    ] {
+        assert!(help.contains(command), "{} missing from --help", command);
+    }
+}
+
+#[test]
+fn test_static_commands() {
+    // redeclaring a local is caught by the compiler, not the parser
+    let source = "{ var a = 1; var a = 2; }";
+    assert_eq!(run(&["check", "-e", source], "").status.code(), Some(0));
+    assert_eq!(run(&["compile", "-e", source], "").status.code(), Some(65));
+
+    let output = run(&["disasm", "-"], "print 1;");
+    assert!(stdout(&output).contains("OP_PRINT"));
+
+    let output = run(&["fmt", "-e", "if(a){print -1;}"], "");
+    assert_eq!(stdout(&output), "if (a) {\n    print -1;\n}\n");
+    // none of them run the code
+    let output = run(&["compile", "-e", "print 1;"], "");
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn test_runtime_options() {
+    let output = run(&["--max-steps", "1000", "-e", "while (true) {}"], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stderr(&output), "Exceeded the limit of 1000 steps\n");
+
+    let source = "var s = \"\"; for (var i = 0; i < 10; i = i + 1) s = s + \"a\"; print s;";
+    let output = run(&["--gc-stress", "--stats", "-e", source], "");
+    assert_eq!(stdout(&output), "aaaaaaaaaa\n");
+    let stats = stderr(&output);
+    assert!(stats.contains("allocations   10\n"), "{}", stats);
+    assert!(stats.contains("collections   10\n"), "{}", stats);
+    // the last collection ran before the last string was built, when the
+    // one before it was still in `s`
+    assert!(stats.contains("live objects  2 "), "{}", stats);
+}
+
+#[test]
+fn test_invalid_utf8() {
+    let path = std::env::temp_dir().join(format!("rlox-invalid-utf8-{}.lox", std::process::id()));
+    std::fs::write(&path, b"print 1;\nprint \"\xff\";\n").unwrap();
+    let script = path.to_str().unwrap();
+    for command in ["run", "check", "fmt"] {
+        let output = run(&[command, script], "");
+        assert_eq!(output.status.code(), Some(65), "{}", command);
+        assert_eq!(stderr(&output), "Found invalid UTF-8 on line 2\n");
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox-bytecode"))
+        .arg("repl")
+        .env_remove("HOME")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            (child.stdin.take().unwrap()).write_all(format!(":load {}\n", script).as_bytes())?;
+            child.wait_with_output()
+        })
+        .unwrap();
+    assert_eq!(stderr(&output), "Found invalid UTF-8 on line 2\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_repl_echoes_bare_expressions() {
+    // without HOME the REPL keeps no history
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rlox-bytecode"))
+        .arg("repl")
+        .env_remove("HOME")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdin = child.stdin.as_mut().unwrap();
+    stdin.write_all(b"var a = 1;\na + 2\na;\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(stderr(&output), "");
+    assert_eq!(stdout(&output), "3\n1\n");
+}
@@ -0,0 +1,229 @@
+// Runs the Lox programs under `tests/lox`, which follow the format of the
+// craftinginterpreters test suite, and checks what the interpreter prints and
+// how it exits against the annotations in them:
+//
+//     print 1; // expect: 1
+//     nil(); // expect runtime error: Can only call functions and classes.
+//     (a) = 1; // Error at '=': Invalid assignment target.
+//     // [line 2] Error: Unterminated string.
+//
+// Tests listed in `tests/lox/known_failures.txt` are expected to fail, any
+// other failure is a regression. Set `LOX_TEST_DIR` to the `test` directory
+// of the craftinginterpreters repository to run the official suite instead, in
+// which case only the pass rates are reported.
+//
+// Run with `cargo test --test conformance -- --nocapture` to see pass rates
+// per chapter.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+const EXIT_COMPILE_ERROR: i32 = 65;
+const EXIT_RUNTIME_ERROR: i32 = 70;
+
+#[derive(Debug, Default)]
+struct Expectations {
+    output: Vec<String>,
+    compile_errors: Vec<String>,
+    runtime_error: Option<String>,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+        for (idx, line) in source.lines().enumerate() {
+            let line_number = idx + 1;
+            let comment = match line.find("//") {
+                Some(start) => line[start + 2..].trim_start(),
+                None => continue,
+            };
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expectations.output.push(output.to_string());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expectations.runtime_error = Some(message.to_string());
+            } else if comment.starts_with("Error") {
+                expectations
+                    .compile_errors
+                    .push(format!("[line {}] {}", line_number, comment));
+            } else if let Some(rest) = comment.strip_prefix('[') {
+                // errors can be specific to one of the two interpreters from
+                // the book, this is a bytecode VM like the C one
+                let rest = rest.strip_prefix("c ").unwrap_or(rest);
+                if rest.starts_with("line ") && rest.contains("] Error") {
+                    expectations.compile_errors.push(format!("[{}", rest));
+                }
+            }
+        }
+        expectations
+    }
+
+    fn exit_code(&self) -> i32 {
+        if !self.compile_errors.is_empty() {
+            EXIT_COMPILE_ERROR
+        } else if self.runtime_error.is_some() {
+            EXIT_RUNTIME_ERROR
+        } else {
+            0
+        }
+    }
+}
+
+// returns why the test failed, if it did
+fn run_test(path: &Path) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let expectations = Expectations::parse(&source);
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox-bytecode"))
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| error.to_string())?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let exit_code = output.status.code();
+    if exit_code != Some(expectations.exit_code()) {
+        return Err(format!(
+            "expected exit code {} but got {:?}, stderr: {:?}",
+            expectations.exit_code(),
+            exit_code,
+            stderr
+                .lines()
+                .find(|line| !line.is_empty() && !line.starts_with("thread "))
+                .unwrap_or("")
+        ));
+    }
+    let printed = stdout.lines().collect::<Vec<_>>();
+    if printed != expectations.output {
+        return Err(format!(
+            "expected output {:?} but got {:?}",
+            expectations.output, printed
+        ));
+    }
+    if let Some(message) = &expectations.runtime_error {
+        let first_line = stderr.lines().next().unwrap_or("");
+        if first_line != message {
+            return Err(format!(
+                "expected runtime error {:?} but got {:?}",
+                message, first_line
+            ));
+        }
+    }
+    if !expectations.compile_errors.is_empty() {
+        let errors = stderr.lines().collect::<Vec<_>>();
+        if errors != expectations.compile_errors {
+            return Err(format!(
+                "expected compile errors {:?} but got {:?}",
+                expectations.compile_errors, errors
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn collect_tests(dir: &Path, tests: &mut Vec<PathBuf>) {
+    let mut entries = fs::read_dir(dir)
+        .unwrap_or_else(|error| panic!("can't read {}: {}", dir.display(), error))
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_tests(&path, tests);
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            tests.push(path);
+        }
+    }
+}
+
+// tests are grouped by the directory they are in, which is named after the
+// feature of the language they cover
+fn chapter(relative: &Path) -> String {
+    match relative.parent() {
+        Some(parent) if parent != Path::new("") => parent.display().to_string(),
+        _ => "(top level)".to_string(),
+    }
+}
+
+fn known_failures(dir: &Path) -> BTreeSet<String> {
+    fs::read_to_string(dir.join("known_failures.txt"))
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn conformance() {
+    let (dir, official) = match env::var_os("LOX_TEST_DIR") {
+        Some(dir) => (PathBuf::from(dir), true),
+        None => (
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox"),
+            false,
+        ),
+    };
+    let mut tests = Vec::new();
+    collect_tests(&dir, &mut tests);
+    let known_failures = known_failures(&dir);
+
+    let mut chapters = BTreeMap::<String, (usize, usize)>::new();
+    let mut regressions = Vec::new();
+    let mut fixed = Vec::new();
+    for path in &tests {
+        let relative = path.strip_prefix(&dir).unwrap();
+        let name = relative.display().to_string();
+        let result = run_test(path);
+        let (passed, total) = chapters.entry(chapter(relative)).or_default();
+        *total += 1;
+        match result {
+            Ok(()) => {
+                *passed += 1;
+                if known_failures.contains(&name) {
+                    fixed.push(name);
+                }
+            }
+            Err(reason) => {
+                if !known_failures.contains(&name) {
+                    regressions.push(format!("{}: {}", name, reason));
+                }
+            }
+        }
+    }
+
+    println!("{:32} {:>7} {:>7}", "chapter", "passed", "rate");
+    for (chapter, (passed, total)) in &chapters {
+        println!(
+            "{:32} {:>3}/{:<3} {:>6.1}%",
+            chapter,
+            passed,
+            total,
+            100.0 * *passed as f64 / *total as f64
+        );
+    }
+    let passed = chapters.values().map(|(passed, _)| passed).sum::<usize>();
+    println!(
+        "{:32} {:>3}/{:<3} {:>6.1}%",
+        "total",
+        passed,
+        tests.len(),
+        100.0 * passed as f64 / tests.len() as f64
+    );
+
+    if official {
+        return;
+    }
+    assert!(
+        regressions.is_empty(),
+        "tests not in known_failures.txt failed:\n{}",
+        regressions.join("\n")
+    );
+    assert!(
+        fixed.is_empty(),
+        "tests in known_failures.txt passed, remove them from the list:\n{}",
+        fixed.join("\n")
+    );
+}